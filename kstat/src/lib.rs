@@ -0,0 +1,1955 @@
+/*
+ * A safe wrapper around libkstat(3LIB), for reading the kernel statistics of
+ * an illumos system.  This began life inside fillmem, and remains shaped by
+ * its needs: memory, paging, and the ZFS ARC.
+ */
+
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+pub mod consts {
+    use std::ffi::CStr;
+
+    pub const MODULE_CPU_INFO: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"cpu_info\0") };
+
+    pub const STAT_CLOCK_MHZ: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"clock_MHz\0") };
+
+    pub const MODULE_CPU: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"cpu\0") };
+    pub const NAME_VM: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"vm\0") };
+    pub const STAT_ANONPGIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"anonpgin\0") };
+    pub const STAT_FSPGIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"fspgin\0") };
+    pub const STAT_PGFREC: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pgfrec\0") };
+    pub const STAT_PGIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pgin\0") };
+    pub const STAT_ANONPGOUT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"anonpgout\0") };
+    pub const STAT_PGSWAPIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pgswapin\0") };
+    pub const STAT_SCAN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"scan\0") };
+    pub const NAME_SYS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"sys\0") };
+    pub const STAT_PGOUT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pgout\0") };
+    pub const STAT_PGPGIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pgpgin\0") };
+    pub const STAT_PGPGOUT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pgpgout\0") };
+    pub const STAT_FSPGOUT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"fspgout\0") };
+    pub const STAT_EXECPGIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"execpgin\0") };
+    pub const STAT_EXECPGOUT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"execpgout\0") };
+    pub const STAT_PGSWAPOUT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pgswapout\0") };
+    pub const STAT_MAJ_FAULT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"maj_fault\0") };
+    pub const STAT_XCALLS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"xcalls\0") };
+    pub const STAT_SYSCALL: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"syscall\0") };
+    pub const STAT_INTR: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"intr\0") };
+    pub const STAT_PSWITCH: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pswitch\0") };
+
+    pub const MODULE_UNIX: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"unix\0") };
+
+    pub const NAME_SYSTEM_MISC: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"system_misc\0") };
+    pub const STAT_BOOT_TIME: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"boot_time\0") };
+    pub const STAT_NPROC: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"nproc\0") };
+    pub const STAT_AVENRUN_1MIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"avenrun_1min\0") };
+    pub const STAT_AVENRUN_5MIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"avenrun_5min\0") };
+    pub const STAT_AVENRUN_15MIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"avenrun_15min\0") };
+
+    pub const NAME_VMINFO: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"vminfo\0") };
+
+    pub const CLASS_KMEM_CACHE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"kmem_cache\0") };
+    pub const CLASS_DISK: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"disk\0") };
+    pub const STAT_BUF_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"buf_size\0") };
+    pub const STAT_BUF_INUSE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"buf_inuse\0") };
+    pub const STAT_SLAB_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"slab_size\0") };
+    pub const STAT_SLAB_CREATE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"slab_create\0") };
+    pub const STAT_SLAB_DESTROY: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"slab_destroy\0") };
+
+    pub const MODULE_TCP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"tcp\0") };
+    pub const NAME_TCP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"tcp\0") };
+    pub const STAT_TCP_CURR_ESTAB: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"tcpCurrEstab\0") };
+    pub const MODULE_UDP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"udp\0") };
+    pub const NAME_UDP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"udp\0") };
+    pub const STAT_UDP_IN_OVERFLOWS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"udpInOverflows\0") };
+
+    pub const NAME_SYSTEM_PAGES: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"system_pages\0") };
+    pub const STAT_FREEMEM: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"freemem\0") };
+    pub const STAT_PHYSMEM: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"physmem\0") };
+    pub const STAT_AVAILRMEM: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"availrmem\0") };
+    pub const STAT_MINFREE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"minfree\0") };
+    pub const STAT_DESFREE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"desfree\0") };
+    pub const STAT_LOTSFREE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"lotsfree\0") };
+    pub const STAT_PP_KERNEL: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pp_kernel\0") };
+
+    pub const MODULE_MEMORY_CAP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"memory_cap\0") };
+    pub const STAT_RSS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"rss\0") };
+    pub const STAT_PHYSCAP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"physcap\0") };
+    pub const STAT_SWAP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"swap\0") };
+    pub const STAT_SWAPCAP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"swapcap\0") };
+    pub const STAT_NOVER: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"nover\0") };
+    pub const STAT_PAGEDOUT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"pagedout\0") };
+
+    pub const MODULE_ZFS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"zfs\0") };
+    pub const NAME_ZFETCHSTATS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"zfetchstats\0") };
+    pub const NAME_ARCSTATS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"arcstats\0") };
+    pub const STAT_C: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"c\0") };
+    pub const STAT_C_MIN: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"c_min\0") };
+    pub const STAT_C_MAX: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"c_max\0") };
+    pub const STAT_ARC_NO_GROW: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"arc_no_grow\0") };
+    pub const STAT_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"size\0") };
+    pub const STAT_P: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"p\0") };
+    pub const STAT_ARC_META_LIMIT: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"arc_meta_limit\0") };
+    pub const STAT_MRU_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"mru_size\0") };
+    pub const STAT_MFU_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"mfu_size\0") };
+    pub const STAT_ARC_META_USED: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"arc_meta_used\0") };
+    pub const STAT_HITS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"hits\0") };
+    pub const STAT_MISSES: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"misses\0") };
+    pub const STAT_DELETED: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"deleted\0") };
+    pub const STAT_EVICT_SKIP: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"evict_skip\0") };
+    pub const STAT_MUTEX_MISS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"mutex_miss\0") };
+    pub const STAT_MEMORY_THROTTLE_COUNT: &CStr = unsafe {
+        &CStr::from_bytes_with_nul_unchecked(b"memory_throttle_count\0")
+    };
+    pub const STAT_ARC_META_MAX: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"arc_meta_max\0") };
+    pub const STAT_MRU_HITS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"mru_hits\0") };
+    pub const STAT_MFU_HITS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"mfu_hits\0") };
+    pub const STAT_MRU_GHOST_HITS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"mru_ghost_hits\0") };
+    pub const STAT_MFU_GHOST_HITS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"mfu_ghost_hits\0") };
+    pub const STAT_DEMAND_DATA_HITS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"demand_data_hits\0") };
+    pub const STAT_DEMAND_DATA_MISSES: &CStr = unsafe {
+        &CStr::from_bytes_with_nul_unchecked(b"demand_data_misses\0")
+    };
+    pub const STAT_DEMAND_METADATA_HITS: &CStr = unsafe {
+        &CStr::from_bytes_with_nul_unchecked(b"demand_metadata_hits\0")
+    };
+    pub const STAT_DEMAND_METADATA_MISSES: &CStr = unsafe {
+        &CStr::from_bytes_with_nul_unchecked(b"demand_metadata_misses\0")
+    };
+    pub const STAT_PREFETCH_DATA_HITS: &CStr = unsafe {
+        &CStr::from_bytes_with_nul_unchecked(b"prefetch_data_hits\0")
+    };
+    pub const STAT_PREFETCH_DATA_MISSES: &CStr = unsafe {
+        &CStr::from_bytes_with_nul_unchecked(b"prefetch_data_misses\0")
+    };
+    pub const STAT_PREFETCH_METADATA_HITS: &CStr = unsafe {
+        &CStr::from_bytes_with_nul_unchecked(b"prefetch_metadata_hits\0")
+    };
+    pub const STAT_PREFETCH_METADATA_MISSES: &CStr = unsafe {
+        &CStr::from_bytes_with_nul_unchecked(b"prefetch_metadata_misses\0")
+    };
+    pub const STAT_EVICT_NOT_ENOUGH: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"evict_not_enough\0") };
+    pub const STAT_HASH_COLLISIONS: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"hash_collisions\0") };
+    pub const STAT_COMPRESSED_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"compressed_size\0") };
+    pub const STAT_UNCOMPRESSED_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"uncompressed_size\0") };
+    pub const STAT_OVERHEAD_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"overhead_size\0") };
+    pub const STAT_DATA_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"data_size\0") };
+    pub const STAT_METADATA_SIZE: &CStr =
+        unsafe { &CStr::from_bytes_with_nul_unchecked(b"metadata_size\0") };
+}
+use consts::*;
+
+pub use wrapper::{KstatWrapper, Named};
+
+static FAULTS: AtomicU32 = AtomicU32::new(0);
+
+/// Failures that can be injected into the library on demand, so that callers
+/// can exercise their error handling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KstatFault {
+    /// kstat_read(3KSTAT) fails.
+    Read,
+    /// kstat_chain_update(3KSTAT) fails.
+    ChainUpdate,
+}
+
+/// Arm or disarm an injected fault.
+pub fn inject_fault(f: KstatFault, armed: bool) {
+    let bit = 1 << (f as u32);
+    if armed {
+        FAULTS.fetch_or(bit, Ordering::Relaxed);
+    } else {
+        FAULTS.fetch_and(!bit, Ordering::Relaxed);
+    }
+}
+
+fn injected(f: KstatFault) -> bool {
+    FAULTS.load(Ordering::Relaxed) & (1 << (f as u32)) != 0
+}
+
+/// The ways in which reading kernel statistics can fail.  A kstat or statistic
+/// that does not exist is distinguished from a genuine failure, as many are
+/// only present on some systems; e.g., there are no arcstats without ZFS.
+#[derive(Debug)]
+pub enum KstatError {
+    /// kstat_open(3KSTAT) failed.
+    OpenFailed(std::io::Error),
+    /// kstat_chain_update(3KSTAT) failed.
+    ChainUpdateFailed(std::io::Error),
+    /// The named kstat or statistic does not exist on this system.
+    NotFound(String),
+    /// The kstat exists but is not of the type we need.
+    WrongType(String),
+    /// A pattern given to query() or dump() could not be parsed.
+    InvalidPattern(String),
+    /// Reading a kstat, or writing out what was read, failed.
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for KstatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KstatError::OpenFailed(e) => {
+                write!(f, "kstat_open(3KSTAT) failed: {e}")
+            }
+            KstatError::ChainUpdateFailed(e) => {
+                write!(f, "kstat_chain_update() failure: {e}")
+            }
+            KstatError::NotFound(what) => write!(f, "{what} not found"),
+            KstatError::WrongType(what) => write!(f, "{what} has wrong type"),
+            KstatError::InvalidPattern(msg) => f.write_str(msg),
+            KstatError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for KstatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KstatError::OpenFailed(e)
+            | KstatError::ChainUpdateFailed(e)
+            | KstatError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for KstatError {
+    fn from(e: std::io::Error) -> KstatError {
+        KstatError::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, KstatError>;
+
+/// A type that can be filled in from the named statistics of a single kstat,
+/// so that the whole kstat can be read at once with KstatRef::read_into()
+/// rather than one statistic at a time.
+pub trait FromKstat: Sized {
+    fn from_kstat(n: &Named) -> Result<Self>;
+}
+
+/// The change in a cumulative counter between two reads of a kstat.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Delta {
+    /// The change in the counter.
+    pub delta: u64,
+    /// The change per second, over the snaptime between the reads.
+    pub rate: f64,
+}
+
+/// Remembers the counters from the previous read of a single named kstat, so
+/// that each read can produce the change in each counter since the last.
+pub struct KstatRates {
+    module: CString,
+    instance: i32,
+    name: CString,
+    stats: Vec<CString>,
+    prev: Option<(u64, u64, Vec<Option<u64>>)>,
+}
+
+impl KstatRates {
+    /// Track the given statistics of a kstat.  An instance of -1 matches any
+    /// instance.
+    pub fn new(
+        module: &CStr,
+        instance: i32,
+        name: &CStr,
+        stats: &[&CStr],
+    ) -> KstatRates {
+        KstatRates {
+            module: module.to_owned(),
+            instance,
+            name: name.to_owned(),
+            stats: stats.iter().map(|s| (*s).to_owned()).collect(),
+            prev: None,
+        }
+    }
+
+    /// The number of statistics being tracked.
+    pub fn count(&self) -> usize {
+        self.stats.len()
+    }
+
+    /// Read the kstat again and return the change in each statistic since
+    /// the previous read, in the order they were given to new().  Returns
+    /// None on the first read, or if the kstat has been recreated in the
+    /// meantime and the counters have started again.  A statistic that is
+    /// missing from either read has no delta.
+    pub fn update(
+        &mut self,
+        ks: &KstatWrapper,
+    ) -> Result<Option<Vec<Option<Delta>>>> {
+        let Some(k) = ks.lookup_instance(
+            Some(&self.module),
+            self.instance,
+            Some(&self.name),
+        ) else {
+            self.prev = None;
+            return Err(KstatError::NotFound(format!(
+                "kstat {}:{}:{}",
+                self.module.to_string_lossy(),
+                self.instance,
+                self.name.to_string_lossy()
+            )));
+        };
+
+        let n = k.snapshot()?;
+        let crtime = k.crtime();
+        let snaptime = n.snaptime();
+        let values =
+            self.stats.iter().map(|s| n.counter(s)).collect::<Vec<_>>();
+
+        let out = match &self.prev {
+            Some((pcrtime, psnaptime, pvalues)) if *pcrtime == crtime => Some(
+                values
+                    .iter()
+                    .zip(pvalues.iter())
+                    .map(|(v, p)| {
+                        let delta = (*v)?.saturating_sub((*p)?);
+                        let nsec = snaptime.saturating_sub(*psnaptime);
+                        Some(Delta {
+                            delta,
+                            rate: rate(delta, nsec).unwrap_or(0.0),
+                        })
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        };
+
+        self.prev = Some((crtime, snaptime, values));
+        Ok(out)
+    }
+}
+
+/// The per-second rate of change of a cumulative counter, given the change in
+/// the counter and the change in snaptime, in nanoseconds, between two reads.
+/// Returns None if no time has passed.
+pub fn rate(delta: u64, nsec: u64) -> Option<f64> {
+    if nsec == 0 {
+        None
+    } else {
+        Some(delta as f64 * 1_000_000_000.0 / nsec as f64)
+    }
+}
+
+/// The type of a kstat, which determines the layout of its data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KstatKind {
+    /// Data in a layout particular to the kstat, e.g., a vminfo_t.
+    Raw,
+    /// An array of kstat_named_t, each a name and a typed value.
+    Named,
+    /// A kstat_intr_t of interrupt counts.
+    Intr,
+    /// A kstat_io_t of disk I/O counters.
+    Io,
+    /// An array of kstat_timer_t.
+    Timer,
+    Unknown(u8),
+}
+
+impl KstatKind {
+    /// The name of the type, as used by kstat(8).
+    pub fn name(&self) -> &'static str {
+        match self {
+            KstatKind::Raw => "raw",
+            KstatKind::Named => "named",
+            KstatKind::Intr => "intr",
+            KstatKind::Io => "io",
+            KstatKind::Timer => "timer",
+            KstatKind::Unknown(_) => "unknown",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct KstatDataIo {
+    pub nread: u64,
+    pub nwritten: u64,
+    pub reads: u32,
+    pub writes: u32,
+    pub wtime: i64,
+    pub wlentime: i64,
+    pub wlastupdate: i64,
+    pub rtime: i64,
+    pub rlentime: i64,
+    pub rlastupdate: i64,
+    pub wcnt: u32,
+    pub rcnt: u32,
+}
+
+/// Interrupt counts, from a KSTAT_TYPE_INTR kstat.
+#[derive(Debug)]
+pub struct KstatDataIntr {
+    pub hard: u32,
+    pub soft: u32,
+    pub watchdog: u32,
+    pub spurious: u32,
+    pub multsvc: u32,
+}
+
+/// One event timer, from a KSTAT_TYPE_TIMER kstat.  Times are in nanoseconds.
+#[derive(Debug)]
+pub struct KstatDataTimer {
+    pub name: std::ffi::CString,
+    pub num_events: u64,
+    pub elapsed_time: i64,
+    pub min_time: i64,
+    pub max_time: i64,
+    pub start_time: i64,
+    pub stop_time: i64,
+}
+
+/// Decode the native-endian integers at the start of the data of a raw kstat,
+/// or None if there is not enough data.
+fn raw_ints<T, const N: usize, const W: usize>(
+    raw: &[u8],
+    decode: fn([u8; W]) -> T,
+) -> Option<[T; N]> {
+    if raw.len() < N * W {
+        return None;
+    }
+    Some(std::array::from_fn(|i| {
+        decode(raw[i * W..(i + 1) * W].try_into().unwrap())
+    }))
+}
+
+/// The contents of the raw "unix:0:vminfo" kstat, a vminfo_t.  Once per
+/// second the kernel adds the current value of each figure, in pages, to the
+/// corresponding counter and increments the update count.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KstatVminfo {
+    pub freemem: u64,
+    pub swap_resv: u64,
+    pub swap_alloc: u64,
+    pub swap_avail: u64,
+    pub swap_free: u64,
+    pub updates: u64,
+}
+
+impl KstatVminfo {
+    pub fn from_raw(raw: &[u8]) -> Option<KstatVminfo> {
+        let [freemem, swap_resv, swap_alloc, swap_avail, swap_free, updates] =
+            raw_ints(raw, u64::from_ne_bytes)?;
+        Some(KstatVminfo {
+            freemem,
+            swap_resv,
+            swap_alloc,
+            swap_avail,
+            swap_free,
+            updates,
+        })
+    }
+
+    /// Present the counters as if they were named statistics.
+    pub fn fields(&self) -> Vec<(&'static str, KstatDataValue)> {
+        vec![
+            ("freemem", KstatDataValue::U64(self.freemem)),
+            ("swap_resv", KstatDataValue::U64(self.swap_resv)),
+            ("swap_alloc", KstatDataValue::U64(self.swap_alloc)),
+            ("swap_avail", KstatDataValue::U64(self.swap_avail)),
+            ("swap_free", KstatDataValue::U64(self.swap_free)),
+            ("updates", KstatDataValue::U64(self.updates)),
+        ]
+    }
+}
+
+/// The contents of the raw "unix:0:sysinfo" kstat, a sysinfo_t.  Once per
+/// second the kernel adds the length of the run and swap queues to these
+/// counters, and the number of threads waiting on I/O.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KstatSysinfo {
+    pub updates: u32,
+    pub runque: u32,
+    pub runocc: u32,
+    pub swpque: u32,
+    pub swpocc: u32,
+    pub waiting: u32,
+}
+
+impl KstatSysinfo {
+    pub fn from_raw(raw: &[u8]) -> Option<KstatSysinfo> {
+        let [updates, runque, runocc, swpque, swpocc, waiting] =
+            raw_ints(raw, u32::from_ne_bytes)?;
+        Some(KstatSysinfo { updates, runque, runocc, swpque, swpocc, waiting })
+    }
+
+    /// Present the counters as if they were named statistics.
+    pub fn fields(&self) -> Vec<(&'static str, KstatDataValue)> {
+        vec![
+            ("updates", KstatDataValue::U32(self.updates)),
+            ("runque", KstatDataValue::U32(self.runque)),
+            ("runocc", KstatDataValue::U32(self.runocc)),
+            ("swpque", KstatDataValue::U32(self.swpque)),
+            ("swpocc", KstatDataValue::U32(self.swpocc)),
+            ("waiting", KstatDataValue::U32(self.waiting)),
+        ]
+    }
+}
+
+#[derive(Debug)]
+pub enum KstatDataValue {
+    Char(i8),
+    S32(i32),
+    U32(u32),
+    S64(i64),
+    U64(u64),
+    /// A KSTAT_DATA_STRING value, copied out of the kstat so that it remains
+    /// valid after the chain is updated or the kstat read again.
+    String(std::ffi::CString),
+    Unknown(u8),
+}
+
+impl KstatDataValue {
+    /// The name of the type of the value, as used by kstat(8).
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            KstatDataValue::Char(_) => "char",
+            KstatDataValue::S32(_) => "int32",
+            KstatDataValue::U32(_) => "uint32",
+            KstatDataValue::S64(_) => "int64",
+            KstatDataValue::U64(_) => "uint64",
+            KstatDataValue::String(_) => "string",
+            KstatDataValue::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl std::fmt::Display for KstatDataValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KstatDataValue::Char(v) => write!(f, "{v}"),
+            KstatDataValue::S32(v) => write!(f, "{v}"),
+            KstatDataValue::U32(v) => write!(f, "{v}"),
+            KstatDataValue::S64(v) => write!(f, "{v}"),
+            KstatDataValue::U64(v) => write!(f, "{v}"),
+            KstatDataValue::String(v) => write!(f, "{}", v.to_string_lossy()),
+            KstatDataValue::Unknown(t) => write!(f, "<unknown type {t}>"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct KstatData {
+    pub name: std::ffi::CString,
+    pub value: KstatDataValue,
+}
+
+mod wrapper {
+    use super::{
+        injected, FromKstat, KstatData, KstatDataIntr, KstatDataIo,
+        KstatDataTimer, KstatDataValue, KstatError, KstatFault, KstatKind,
+        KstatSysinfo, KstatVminfo, Result,
+    };
+    use std::collections::HashMap;
+    use std::ffi::{CStr, CString};
+    use std::marker::PhantomData;
+    use std::os::raw::c_char;
+    use std::os::raw::c_int;
+    use std::os::raw::c_long;
+    use std::os::raw::c_longlong;
+    use std::os::raw::c_uchar;
+    use std::os::raw::c_uint;
+    use std::os::raw::c_ulong;
+    use std::os::raw::c_ulonglong;
+    use std::os::raw::c_void;
+    use std::ptr::{null, null_mut, NonNull};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Mutex;
+
+    const KSTAT_TYPE_RAW: c_uchar = 0;
+    const KSTAT_TYPE_NAMED: c_uchar = 1;
+    const KSTAT_TYPE_INTR: c_uchar = 2;
+    const KSTAT_TYPE_IO: c_uchar = 3;
+    const KSTAT_TYPE_TIMER: c_uchar = 4;
+
+    const KSTAT_STRLEN: usize = 31;
+    const KSTAT_NUM_INTRS: usize = 5;
+
+    const KSTAT_DATA_CHAR: u8 = 0;
+    const KSTAT_DATA_INT32: u8 = 1;
+    const KSTAT_DATA_UINT32: u8 = 2;
+    const KSTAT_DATA_INT64: u8 = 3;
+    const KSTAT_DATA_UINT64: u8 = 4;
+    const KSTAT_DATA_STRING: u8 = 9;
+
+    #[repr(C)]
+    struct Kstat {
+        ks_crtime: c_longlong,
+        ks_next: *mut Kstat,
+        ks_kid: c_uint,
+        ks_module: [c_char; KSTAT_STRLEN],
+        ks_resv: c_uchar,
+        ks_instance: c_int,
+        ks_name: [c_char; KSTAT_STRLEN],
+        ks_type: c_uchar,
+        ks_class: [c_char; KSTAT_STRLEN],
+        ks_flags: c_uchar,
+        ks_data: *mut c_void,
+        ks_ndata: c_uint,
+        ks_data_size: usize,
+        ks_snaptime: c_longlong,
+    }
+
+    impl Kstat {
+        fn name(&self) -> &CStr {
+            unsafe { CStr::from_ptr(self.ks_name.as_ptr()) }
+        }
+
+        fn module(&self) -> &CStr {
+            unsafe { CStr::from_ptr(self.ks_module.as_ptr()) }
+        }
+
+        fn class(&self) -> &CStr {
+            unsafe { CStr::from_ptr(self.ks_class.as_ptr()) }
+        }
+
+        fn instance(&self) -> i32 {
+            self.ks_instance
+        }
+
+        fn type_(&self) -> u8 {
+            self.ks_type
+        }
+    }
+
+    #[repr(C)]
+    struct KstatCtl {
+        kc_chain_id: c_int,
+        kc_chain: *mut Kstat,
+        kc_kd: c_int,
+    }
+
+    /// A string value, which lives in memory allocated separately from the
+    /// kstat_named_t.  The length includes the terminating NUL.
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct KstatStr {
+        ptr: *const c_char,
+        len: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    union KstatValue {
+        c: [c_char; 16],
+        str: KstatStr,
+        l: c_long,
+        ul: c_ulong,
+        ui32: u32,
+        si32: i32,
+        ui64: u64,
+        si64: i64,
+    }
+
+    #[repr(C)]
+    struct KstatNamed {
+        name: [c_char; KSTAT_STRLEN],
+        data_type: c_uchar,
+        value: KstatValue,
+    }
+
+    #[repr(C)]
+    pub struct KstatIo {
+        pub nread: c_ulonglong,
+        pub nwritten: c_ulonglong,
+        pub reads: c_uint,
+        pub writes: c_uint,
+        pub wtime: c_longlong,
+        pub wlentime: c_longlong,
+        pub wlastupdate: c_longlong,
+        pub rtime: c_longlong,
+        pub rlentime: c_longlong,
+        pub rlastupdate: c_longlong,
+        pub wcnt: c_uint,
+        pub rcnt: c_uint,
+    }
+
+    #[repr(C)]
+    struct KstatIntr {
+        intrs: [c_uint; KSTAT_NUM_INTRS],
+    }
+
+    #[repr(C)]
+    struct KstatTimer {
+        name: [c_char; KSTAT_STRLEN],
+        resv: c_uchar,
+        num_events: c_ulonglong,
+        elapsed_time: c_longlong,
+        min_time: c_longlong,
+        max_time: c_longlong,
+        start_time: c_longlong,
+        stop_time: c_longlong,
+    }
+
+    impl KstatStr {
+        /// Copy out the string.  It may be absent altogether, and is not
+        /// necessarily terminated.
+        fn value(&self) -> CString {
+            if self.ptr.is_null() || self.len == 0 {
+                return CString::default();
+            }
+
+            let b = unsafe {
+                std::slice::from_raw_parts(
+                    self.ptr as *const u8,
+                    self.len as usize,
+                )
+            };
+            let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+            CString::new(&b[..end]).unwrap()
+        }
+    }
+
+    impl KstatNamed {
+        fn name(&self) -> &CStr {
+            unsafe { CStr::from_ptr(self.name.as_ptr()) }
+        }
+
+        /// The value, if it is a signed integer.  32-bit values are widened;
+        /// a "long_t" is 64 bits wide.
+        fn signed(&self) -> Option<i64> {
+            match self.data_type {
+                KSTAT_DATA_INT32 => Some(unsafe { self.value.si32 }.into()),
+                KSTAT_DATA_INT64 => Some(unsafe { self.value.si64 }),
+                _ => None,
+            }
+        }
+
+        /// The value, if it is an unsigned integer.  32-bit values are
+        /// widened; a "ulong_t" is 64 bits wide.
+        fn unsigned(&self) -> Option<u64> {
+            match self.data_type {
+                KSTAT_DATA_UINT32 => Some(unsafe { self.value.ui32 }.into()),
+                KSTAT_DATA_UINT64 => Some(unsafe { self.value.ui64 }),
+                _ => None,
+            }
+        }
+
+        /// The value, if it is exactly a "uint32_t".
+        fn u32(&self) -> Option<u32> {
+            match self.data_type {
+                KSTAT_DATA_UINT32 => Some(unsafe { self.value.ui32 }),
+                _ => None,
+            }
+        }
+    }
+
+    #[link(name = "kstat")]
+    extern "C" {
+        fn kstat_open() -> *mut KstatCtl;
+        fn kstat_close(kc: *mut KstatCtl) -> c_int;
+        fn kstat_lookup(
+            kc: *mut KstatCtl,
+            module: *const c_char,
+            instance: c_int,
+            name: *const c_char,
+        ) -> *mut Kstat;
+        fn kstat_read(
+            kc: *mut KstatCtl,
+            ksp: *mut Kstat,
+            buf: *mut c_void,
+        ) -> c_int;
+        fn kstat_data_lookup(
+            ksp: *mut Kstat,
+            name: *const c_char,
+        ) -> *mut c_void;
+        fn kstat_chain_update(ksp: *mut KstatCtl) -> c_int;
+    }
+
+    /// Minimal wrapper around libkstat(3LIB) on illumos and Solaris systems.
+    pub struct KstatWrapper {
+        kc: NonNull<KstatCtl>,
+        cache: Mutex<Cache>,
+        /// Reads that failed even after retrying; see read_with().
+        dropped: AtomicU64,
+    }
+
+    /// How many times read_with() updates the chain and tries again before
+    /// giving up on a read.
+    const READ_RETRIES: usize = 3;
+
+    type CacheKey = (Option<CString>, i32, Option<CString>);
+
+    /// The results of lookup_instance(), including misses.  These remain
+    /// valid only as long as the chain ID stays the same; once it changes,
+    /// kstats may have been freed and the whole cache is discarded.
+    #[derive(Default)]
+    struct Cache {
+        chain_id: c_int,
+        entries: HashMap<CacheKey, Option<NonNull<Kstat>>>,
+    }
+
+    unsafe impl Send for KstatWrapper {}
+    unsafe impl Sync for KstatWrapper {}
+
+    /// Turn an optional CStr into a (const char *) for Some, or NULL for None.
+    fn cp(p: &Option<&CStr>) -> *const c_char {
+        p.map_or_else(|| null(), |p| p.as_ptr())
+    }
+
+    impl KstatWrapper {
+        pub fn open() -> Result<Self> {
+            let kc = NonNull::new(unsafe { kstat_open() });
+            if let Some(kc) = kc {
+                Ok(KstatWrapper {
+                    kc: kc,
+                    cache: Default::default(),
+                    dropped: AtomicU64::new(0),
+                })
+            } else {
+                Err(KstatError::OpenFailed(std::io::Error::last_os_error()))
+            }
+        }
+
+        /// Call kstat_chain_update(3KSTAT).  The chain update invalidates any
+        /// kstat handles, so they may not be held across this call.
+        pub fn chain_update(&mut self) -> Result<()> {
+            if injected(KstatFault::ChainUpdate) {
+                return Err(KstatError::ChainUpdateFailed(
+                    std::io::Error::other("injected fault"),
+                ));
+            }
+
+            if unsafe { kstat_chain_update(self.kc.as_ptr()) } == -1 {
+                return Err(KstatError::ChainUpdateFailed(
+                    std::io::Error::last_os_error(),
+                ));
+            }
+
+            Ok(())
+        }
+
+        /// Call kstat_lookup(3KSTAT) and return the first kstat that matches,
+        /// if there is one.
+        pub fn lookup(
+            &self,
+            module: Option<&CStr>,
+            name: Option<&CStr>,
+        ) -> Option<KstatRef<'_>> {
+            self.lookup_instance(module, -1, name)
+        }
+
+        /// Call kstat_lookup(3KSTAT) for a particular instance, e.g., a
+        /// single CPU or zone.  An instance of -1 matches any instance.
+        pub fn lookup_instance(
+            &self,
+            module: Option<&CStr>,
+            instance: i32,
+            name: Option<&CStr>,
+        ) -> Option<KstatRef<'_>> {
+            let chain_id = unsafe { self.kc.as_ref() }.kc_chain_id;
+            let mut cache = self.cache.lock().unwrap();
+            if cache.chain_id != chain_id {
+                cache.entries.clear();
+                cache.chain_id = chain_id;
+            }
+
+            let key = (
+                module.map(|m| m.to_owned()),
+                instance,
+                name.map(|n| n.to_owned()),
+            );
+            let ks = *cache.entries.entry(key).or_insert_with(|| {
+                NonNull::new(unsafe {
+                    kstat_lookup(
+                        self.kc.as_ptr(),
+                        cp(&module),
+                        instance,
+                        cp(&name),
+                    )
+                })
+            });
+
+            ks.map(|ks| KstatRef { kc: self.kc, ks, _chain: PhantomData })
+        }
+
+        /// Look up a kstat and pass it to f, which will generally read it.
+        /// While devices and zones come and go, the kstat we found may be
+        /// removed before we can read it, or replaced by another with the
+        /// same name; in that case update the chain and try again, a bounded
+        /// number of times.  Reads that still fail are counted, see dropped().
+        pub fn read_with<T>(
+            &mut self,
+            module: Option<&CStr>,
+            instance: i32,
+            name: Option<&CStr>,
+            f: impl Fn(KstatRef<'_>) -> Result<T>,
+        ) -> Result<T> {
+            let mut attempt = 0;
+            loop {
+                let res = match self.lookup_instance(module, instance, name) {
+                    Some(k) => f(k),
+                    None => Err(KstatError::NotFound(format!(
+                        "kstat {}:{instance}:{}",
+                        module.map(|m| m.to_string_lossy()).unwrap_or_default(),
+                        name.map(|n| n.to_string_lossy()).unwrap_or_default(),
+                    ))),
+                };
+
+                match res {
+                    Err(KstatError::Io(_)) if attempt < READ_RETRIES => {
+                        attempt += 1;
+                        self.chain_update()?;
+                    }
+                    Err(e @ KstatError::Io(_)) => {
+                        self.note_dropped();
+                        return Err(e);
+                    }
+                    res => return res,
+                }
+            }
+        }
+
+        /// Record that a reading was lost because a kstat could not be read.
+        pub fn note_dropped(&self) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+
+        /// The number of readings lost so far because a kstat could not be
+        /// read, even after retrying.
+        pub fn dropped(&self) -> u64 {
+            self.dropped.load(Ordering::Relaxed)
+        }
+
+        /// List the instance numbers of the kstats with the given module and
+        /// name, in ascending order, for use with lookup_instance().
+        pub fn instances(
+            &self,
+            module: Option<&CStr>,
+            name: Option<&CStr>,
+        ) -> Vec<i32> {
+            let mut out = self
+                .iter_matching(module, name)
+                .map(|k| k.instance())
+                .collect::<Vec<_>>();
+            out.sort();
+            out.dedup();
+            out
+        }
+
+        /// Walk the whole kstat chain from the beginning.
+        pub fn iter(&self) -> KstatIter<'_> {
+            KstatIter {
+                kc: self.kc,
+                next: NonNull::new(unsafe { self.kc.as_ref().kc_chain }),
+                class: None,
+                _chain: PhantomData,
+            }
+        }
+
+        /// Walk only those kstats in the given class, e.g., "disk" or
+        /// "zone_memory_cap".
+        pub fn walk_class<'a>(&'a self, class: &'a CStr) -> KstatIter<'a> {
+            self.iter().class(class)
+        }
+
+        /// Walk only those kstats with the given module and name, either of
+        /// which may be None to match anything.
+        pub fn iter_matching<'a>(
+            &'a self,
+            module: Option<&'a CStr>,
+            name: Option<&'a CStr>,
+        ) -> impl Iterator<Item = KstatRef<'a>> {
+            self.iter().filter(move |k| {
+                module.is_none_or(|m| k.module() == m)
+                    && name.is_none_or(|n| k.name() == n)
+            })
+        }
+    }
+
+    /// An iterator over the kstat chain, from KstatWrapper::iter().
+    pub struct KstatIter<'a> {
+        kc: NonNull<KstatCtl>,
+        next: Option<NonNull<Kstat>>,
+        class: Option<&'a CStr>,
+        _chain: PhantomData<&'a KstatWrapper>,
+    }
+
+    impl<'a> KstatIter<'a> {
+        /// Skip any kstats that are not in the given class.
+        pub fn class(self, class: &'a CStr) -> KstatIter<'a> {
+            KstatIter { class: Some(class), ..self }
+        }
+    }
+
+    impl<'a> Iterator for KstatIter<'a> {
+        type Item = KstatRef<'a>;
+
+        fn next(&mut self) -> Option<KstatRef<'a>> {
+            loop {
+                let ks = self.next?;
+                let k = unsafe { ks.as_ref() };
+                self.next = NonNull::new(k.ks_next);
+
+                if self.class.is_none_or(|c| k.class() == c) {
+                    return Some(KstatRef {
+                        kc: self.kc,
+                        ks,
+                        _chain: PhantomData,
+                    });
+                }
+            }
+        }
+    }
+
+    /// A single kstat in the chain.  The handle borrows the KstatWrapper, so
+    /// that it cannot outlive a chain update.
+    #[derive(Clone, Copy)]
+    pub struct KstatRef<'a> {
+        kc: NonNull<KstatCtl>,
+        ks: NonNull<Kstat>,
+        _chain: PhantomData<&'a KstatWrapper>,
+    }
+
+    impl<'a> KstatRef<'a> {
+        fn kstat(&self) -> &'a Kstat {
+            unsafe { self.ks.as_ref() }
+        }
+
+        /// Return the module name of the kstat.
+        pub fn module(&self) -> &'a CStr {
+            self.kstat().module()
+        }
+
+        /// Return the name of the kstat.
+        pub fn name(&self) -> &'a CStr {
+            self.kstat().name()
+        }
+
+        /// Return the class of the kstat.
+        pub fn class(&self) -> &'a CStr {
+            self.kstat().class()
+        }
+
+        /// Return the instance number of the kstat.
+        pub fn instance(&self) -> i32 {
+            self.kstat().instance()
+        }
+
+        /// Return the time at which the kstat was created, in nanoseconds of
+        /// gethrtime(3C).
+        pub fn crtime(&self) -> u64 {
+            self.kstat().ks_crtime as u64
+        }
+
+        /// Return the time at which the data was last read with read(), in
+        /// nanoseconds of gethrtime(3C).  Rates should be computed against
+        /// this rather than the time at which we happened to wake up.
+        pub fn snaptime(&self) -> u64 {
+            self.kstat().ks_snaptime as u64
+        }
+
+        /// Return the type of the kstat.
+        pub fn type_(&self) -> u8 {
+            self.kstat().type_()
+        }
+
+        /// Return the type of the kstat, for callers to decide how to
+        /// interpret its data.
+        pub fn kind(&self) -> KstatKind {
+            match self.type_() {
+                KSTAT_TYPE_RAW => KstatKind::Raw,
+                KSTAT_TYPE_NAMED => KstatKind::Named,
+                KSTAT_TYPE_INTR => KstatKind::Intr,
+                KSTAT_TYPE_IO => KstatKind::Io,
+                KSTAT_TYPE_TIMER => KstatKind::Timer,
+                t => KstatKind::Unknown(t),
+            }
+        }
+
+        /// Return the name of the type of the kstat.
+        pub fn type_name(&self) -> &'static str {
+            self.kind().name()
+        }
+
+        pub fn read(&self) -> Result<()> {
+            let ksp = self.ks.as_ptr();
+
+            if injected(KstatFault::Read) {
+                return Err(KstatError::Io(std::io::Error::other(
+                    "kstat_read() failure: injected fault",
+                )));
+            }
+
+            if unsafe { kstat_read(self.kc.as_ptr(), ksp, null_mut()) } == -1 {
+                let e = std::io::Error::last_os_error();
+                return Err(KstatError::Io(std::io::Error::new(
+                    e.kind(),
+                    format!("kstat_read() failure: {e}"),
+                )));
+            }
+
+            Ok(())
+        }
+
+        /// Return the number of data records in the kstat: named values,
+        /// timers, and so on.  A raw kstat counts each structure, or has a
+        /// count of one if it holds only a single structure.
+        pub fn ndata(&self) -> usize {
+            self.kstat().ks_ndata as usize
+        }
+
+        /// Return the size of the data of the kstat in bytes.  For kstats
+        /// with a variable size, e.g., those with KSTAT_DATA_STRING values,
+        /// this is only known once the kstat has been read.
+        pub fn data_size(&self) -> usize {
+            self.kstat().ks_data_size
+        }
+
+        pub fn intr(&self) -> Option<KstatDataIntr> {
+            let ks = self.kstat();
+
+            if ks.ks_type != KSTAT_TYPE_INTR {
+                return None;
+            }
+
+            let ksd: NonNull<KstatIntr> =
+                NonNull::new(ks.ks_data).unwrap().cast();
+
+            let ksd = unsafe { ksd.as_ref() };
+
+            Some(KstatDataIntr {
+                hard: ksd.intrs[0],
+                soft: ksd.intrs[1],
+                watchdog: ksd.intrs[2],
+                spurious: ksd.intrs[3],
+                multsvc: ksd.intrs[4],
+            })
+        }
+
+        pub fn timers(&self) -> Option<Vec<KstatDataTimer>> {
+            let ks = self.kstat();
+
+            if ks.ks_type != KSTAT_TYPE_TIMER {
+                return None;
+            }
+
+            let ksd = NonNull::new(ks.ks_data).unwrap().cast();
+
+            let data: &[KstatTimer] = unsafe {
+                std::slice::from_raw_parts(ksd.as_ptr(), ks.ks_ndata as usize)
+            };
+
+            Some(
+                data.iter()
+                    .map(|t| KstatDataTimer {
+                        name: unsafe { CStr::from_ptr(t.name.as_ptr()) }
+                            .to_owned(),
+                        num_events: t.num_events,
+                        elapsed_time: t.elapsed_time,
+                        min_time: t.min_time,
+                        max_time: t.max_time,
+                        start_time: t.start_time,
+                        stop_time: t.stop_time,
+                    })
+                    .collect(),
+            )
+        }
+
+        pub fn io(&self) -> Option<KstatDataIo> {
+            let ks = self.kstat();
+
+            if ks.ks_type != KSTAT_TYPE_IO {
+                return None;
+            }
+
+            let ksd: NonNull<KstatIo> =
+                NonNull::new(ks.ks_data).unwrap().cast();
+
+            let ksd = unsafe { ksd.as_ref() };
+
+            Some(KstatDataIo {
+                nread: ksd.nread,
+                nwritten: ksd.nwritten,
+                reads: ksd.reads,
+                writes: ksd.writes,
+                wtime: ksd.wtime,
+                wlentime: ksd.wlentime,
+                wlastupdate: ksd.wlastupdate,
+                rtime: ksd.rtime,
+                rlentime: ksd.rlentime,
+                rlastupdate: ksd.rlastupdate,
+                wcnt: ksd.wcnt,
+                rcnt: ksd.rcnt,
+            })
+        }
+
+        /// Return a copy of the data of the kstat, if it is a raw
+        /// kstat.  The kstat must have been read first.  The layout of the
+        /// data is particular to each kstat.
+        pub fn raw(&self) -> Option<Vec<u8>> {
+            let ks = self.kstat();
+
+            if ks.ks_type != KSTAT_TYPE_RAW || ks.ks_data.is_null() {
+                return None;
+            }
+
+            let data: &[u8] = unsafe {
+                std::slice::from_raw_parts(
+                    ks.ks_data as *const u8,
+                    ks.ks_data_size,
+                )
+            };
+
+            Some(data.to_vec())
+        }
+
+        /// Interpret the data of the kstat as a vminfo_t, as found in
+        /// "unix:0:vminfo".  The kstat must have been read first.
+        pub fn vminfo(&self) -> Option<KstatVminfo> {
+            self.raw().and_then(|raw| KstatVminfo::from_raw(&raw))
+        }
+
+        /// Interpret the data of the kstat as a sysinfo_t, as found in
+        /// "unix:0:sysinfo".  The kstat must have been read first.
+        pub fn sysinfo(&self) -> Option<KstatSysinfo> {
+            self.raw().and_then(|raw| KstatSysinfo::from_raw(&raw))
+        }
+
+        /// Read the kstat once and return every named value in it.
+        pub fn data_all(&self) -> Result<Vec<KstatData>> {
+            self.snapshot()?;
+            Ok((0..self.ndata()).filter_map(|i| self.data_get(i)).collect())
+        }
+
+        pub fn data_get(&self, n: usize) -> Option<KstatData> {
+            let ks = self.kstat();
+
+            if ks.ks_type != KSTAT_TYPE_NAMED || n >= ks.ks_ndata as usize {
+                // This is not a named kstat, or it does not have this many
+                // data elements.
+                return None;
+            }
+
+            let ksd = NonNull::new(ks.ks_data).unwrap().cast();
+
+            let data: &[KstatNamed] = unsafe {
+                std::slice::from_raw_parts(ksd.as_ptr(), ks.ks_ndata as usize)
+            };
+
+            let value = match data[n].data_type {
+                KSTAT_DATA_CHAR => {
+                    KstatDataValue::Char(unsafe { data[n].value.c[0] })
+                }
+                KSTAT_DATA_INT32 => {
+                    KstatDataValue::S32(unsafe { data[n].value.si32 })
+                }
+                KSTAT_DATA_INT64 => {
+                    KstatDataValue::S64(unsafe { data[n].value.si64 })
+                }
+                KSTAT_DATA_UINT32 => {
+                    KstatDataValue::U32(unsafe { data[n].value.ui32 })
+                }
+                KSTAT_DATA_UINT64 => {
+                    KstatDataValue::U64(unsafe { data[n].value.ui64 })
+                }
+                KSTAT_DATA_STRING => {
+                    KstatDataValue::String(unsafe { data[n].value.str }.value())
+                }
+                n => KstatDataValue::Unknown(n),
+            };
+
+            Some(KstatData { name: data[n].name().to_owned(), value })
+        }
+
+        /// Read the kstat once and fill in a FromKstat type from the data.
+        pub fn read_into<T: FromKstat>(&self) -> Result<T> {
+            T::from_kstat(&self.snapshot()?)
+        }
+
+        /// Read the kstat once, so that the named statistics can be looked up
+        /// without reading it again.
+        pub fn snapshot(&self) -> Result<Named<'a>> {
+            if self.type_() != KSTAT_TYPE_NAMED {
+                return Err(KstatError::WrongType(format!(
+                    "kstat {}:{}:{}",
+                    self.module().to_string_lossy(),
+                    self.instance(),
+                    self.name().to_string_lossy()
+                )));
+            }
+            self.read()?;
+            Ok(Named { k: *self })
+        }
+
+        /// Read the kstat and look up a named kstat value.  For internal use
+        /// by typed accessors.
+        fn data_value(&self, statistic: &CStr) -> Option<NonNull<KstatNamed>> {
+            if injected(KstatFault::Read)
+                || unsafe {
+                    kstat_read(self.kc.as_ptr(), self.ks.as_ptr(), null_mut())
+                } == -1
+            {
+                return None;
+            }
+
+            self.named(statistic)
+        }
+
+        /// Look up a named kstat value in the data from the last read.
+        fn named(&self, statistic: &CStr) -> Option<NonNull<KstatNamed>> {
+            let (ks, ksp) = (self.kstat(), self.ks.as_ptr());
+
+            if ks.ks_type != KSTAT_TYPE_NAMED || ks.ks_ndata < 1 {
+                // This is not a named kstat, or it has no data payload.
+                return None;
+            }
+
+            NonNull::new(unsafe {
+                kstat_data_lookup(ksp, cp(&Some(statistic)))
+            })
+            .map(|voidp| voidp.cast())
+        }
+
+        /// Look up a named kstat value as a "long_t".  Returns None if the
+        /// value is not a signed integer.
+        pub fn data_long(&self, statistic: &CStr) -> Option<i64> {
+            self.data_value(statistic)
+                .and_then(|kn| unsafe { kn.as_ref() }.signed())
+        }
+
+        /// Look up a named kstat value as a "ulong_t".  Returns None if the
+        /// value is not an unsigned integer.
+        pub fn data_ulong(&self, statistic: &CStr) -> Option<u64> {
+            self.data_u64(statistic)
+        }
+
+        /// Look up a named kstat value as a "uint32_t".  Returns None if the
+        /// value is of any other type, including a wider integer.
+        pub fn data_u32(&self, statistic: &CStr) -> Option<u32> {
+            self.data_value(statistic)
+                .and_then(|kn| unsafe { kn.as_ref() }.u32())
+        }
+
+        /// Look up a named kstat value as a "uint64_t", widening a "uint32_t"
+        /// if need be.  Returns None if the value is not an unsigned integer.
+        pub fn data_u64(&self, statistic: &CStr) -> Option<u64> {
+            self.data_value(statistic)
+                .and_then(|kn| unsafe { kn.as_ref() }.unsigned())
+        }
+    }
+
+    /// The named statistics of a kstat that has already been read, as passed
+    /// to FromKstat::from_kstat().  Lookups do not read the kstat again, so
+    /// all values come from the same snapshot.
+    pub struct Named<'a> {
+        k: KstatRef<'a>,
+    }
+
+    impl<'a> Named<'a> {
+        /// The time at which the statistics were read, in nanoseconds of
+        /// gethrtime(3C).
+        pub fn snaptime(&self) -> u64 {
+            self.k.snaptime()
+        }
+
+        /// Look up a named kstat value as a "ulong_t".  These accessors
+        /// check the type in the same way as those on KstatRef.
+        pub fn ulong(&self, statistic: &CStr) -> Option<u64> {
+            self.u64(statistic)
+        }
+
+        /// Look up a named kstat value as a "uint32_t".
+        pub fn u32(&self, statistic: &CStr) -> Option<u32> {
+            self.k.named(statistic).and_then(|kn| unsafe { kn.as_ref() }.u32())
+        }
+
+        /// Look up a named kstat value as a "uint64_t", widening a "uint32_t"
+        /// if need be.
+        pub fn u64(&self, statistic: &CStr) -> Option<u64> {
+            self.k
+                .named(statistic)
+                .and_then(|kn| unsafe { kn.as_ref() }.unsigned())
+        }
+
+        /// Look up a named counter of any integer width.  Some counters are
+        /// declared signed even though they only ever increase.
+        pub fn counter(&self, statistic: &CStr) -> Option<u64> {
+            let kn = self.k.named(statistic)?;
+            let kn = unsafe { kn.as_ref() };
+            kn.unsigned().or_else(|| kn.signed().map(|v| v as u64))
+        }
+    }
+
+    impl Drop for KstatWrapper {
+        fn drop(&mut self) {
+            unsafe { kstat_close(self.kc.as_ptr()) };
+        }
+    }
+}
+
+/// Paging and system activity counters for a CPU, from the "cpu:<n>:vm" and
+/// "cpu:<n>:sys" kstats.  These are the named successors of the raw
+/// "cpu_stat:<n>" kstats, and carry the same counters at 64 bits wide.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CpuCounters {
+    pub pgin: u64,
+    pub pgout: u64,
+    pub pgpgin: u64,
+    pub pgpgout: u64,
+    pub anonpgin: u64,
+    pub anonpgout: u64,
+    pub fspgin: u64,
+    pub fspgout: u64,
+    pub execpgin: u64,
+    pub execpgout: u64,
+    pub pgswapin: u64,
+    pub pgswapout: u64,
+    pub pgfrec: u64,
+    pub scan: u64,
+    pub maj_fault: u64,
+    pub xcalls: u64,
+    pub syscall: u64,
+    pub intr: u64,
+    pub pswitch: u64,
+}
+
+impl CpuCounters {
+    /// Combine each counter with the corresponding one in another set.
+    fn zip(&self, o: &CpuCounters, f: impl Fn(u64, u64) -> u64) -> CpuCounters {
+        CpuCounters {
+            pgin: f(self.pgin, o.pgin),
+            pgout: f(self.pgout, o.pgout),
+            pgpgin: f(self.pgpgin, o.pgpgin),
+            pgpgout: f(self.pgpgout, o.pgpgout),
+            anonpgin: f(self.anonpgin, o.anonpgin),
+            anonpgout: f(self.anonpgout, o.anonpgout),
+            fspgin: f(self.fspgin, o.fspgin),
+            fspgout: f(self.fspgout, o.fspgout),
+            execpgin: f(self.execpgin, o.execpgin),
+            execpgout: f(self.execpgout, o.execpgout),
+            pgswapin: f(self.pgswapin, o.pgswapin),
+            pgswapout: f(self.pgswapout, o.pgswapout),
+            pgfrec: f(self.pgfrec, o.pgfrec),
+            scan: f(self.scan, o.scan),
+            maj_fault: f(self.maj_fault, o.maj_fault),
+            xcalls: f(self.xcalls, o.xcalls),
+            syscall: f(self.syscall, o.syscall),
+            intr: f(self.intr, o.intr),
+            pswitch: f(self.pswitch, o.pswitch),
+        }
+    }
+}
+
+/// The counters of every CPU at a point in time.  CPUs may be brought online
+/// or taken offline between readings, taking their counters with them, so
+/// the counters are kept per CPU and changes are only taken over the CPUs
+/// present in both readings.
+#[derive(Clone, Debug, Default)]
+pub struct CpuStats {
+    cpus: std::collections::BTreeMap<i32, CpuCounters>,
+    /// The time of the most recent read, in nanoseconds of gethrtime(3C).
+    pub snaptime: u64,
+}
+
+impl CpuStats {
+    /// Read the counters of every CPU.  The caller should have updated the
+    /// chain first, so that newly added CPUs are seen.
+    pub fn read(ks: &KstatWrapper) -> Result<CpuStats> {
+        let mut out = CpuStats::default();
+
+        for k in ks.iter_matching(Some(MODULE_CPU), None) {
+            let (vm, sys) = (k.name() == NAME_VM, k.name() == NAME_SYS);
+            if !vm && !sys {
+                continue;
+            }
+
+            /*
+             * A CPU that has gone away since the chain update cannot be
+             * read, and simply drops out of this reading.
+             */
+            let Ok(n) = k.snapshot() else {
+                ks.note_dropped();
+                continue;
+            };
+            out.snaptime = out.snaptime.max(n.snaptime());
+
+            let c = out.cpus.entry(k.instance()).or_default();
+            let get = |stat| n.counter(stat).unwrap_or(0);
+            if vm {
+                c.pgin = get(STAT_PGIN);
+                c.pgout = get(STAT_PGOUT);
+                c.pgpgin = get(STAT_PGPGIN);
+                c.pgpgout = get(STAT_PGPGOUT);
+                c.anonpgin = get(STAT_ANONPGIN);
+                c.anonpgout = get(STAT_ANONPGOUT);
+                c.fspgin = get(STAT_FSPGIN);
+                c.fspgout = get(STAT_FSPGOUT);
+                c.execpgin = get(STAT_EXECPGIN);
+                c.execpgout = get(STAT_EXECPGOUT);
+                c.pgswapin = get(STAT_PGSWAPIN);
+                c.pgswapout = get(STAT_PGSWAPOUT);
+                c.pgfrec = get(STAT_PGFREC);
+                c.scan = get(STAT_SCAN);
+                c.maj_fault = get(STAT_MAJ_FAULT);
+            } else {
+                c.xcalls = get(STAT_XCALLS);
+                c.syscall = get(STAT_SYSCALL);
+                c.intr = get(STAT_INTR);
+                c.pswitch = get(STAT_PSWITCH);
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// The counters summed across all CPUs.
+    pub fn total(&self) -> CpuCounters {
+        self.cpus
+            .values()
+            .fold(CpuCounters::default(), |t, c| t.zip(c, u64::saturating_add))
+    }
+
+    /// The change in each counter since an earlier reading, summed across the
+    /// CPUs present in both.
+    pub fn since(&self, earlier: &CpuStats) -> CpuCounters {
+        self.cpus
+            .iter()
+            .filter_map(|(id, c)| {
+                earlier.cpus.get(id).map(|e| c.zip(e, u64::saturating_sub))
+            })
+            .fold(CpuCounters::default(), |t, d| t.zip(&d, u64::saturating_add))
+    }
+}
+
+pub fn cpu_mhz() -> Result<u64> {
+    let ks = wrapper::KstatWrapper::open()?;
+
+    for k in ks.iter_matching(Some(MODULE_CPU_INFO), None) {
+        if let Some(mhz) = k.data_long(STAT_CLOCK_MHZ) {
+            return Ok(mhz as u64);
+        }
+    }
+
+    Err(KstatError::NotFound("cpu speed kstat".into()))
+}
+
+pub fn boot_time() -> Result<u64> {
+    let ks = wrapper::KstatWrapper::open()?;
+
+    for k in ks.iter_matching(Some(MODULE_UNIX), Some(NAME_SYSTEM_MISC)) {
+        if let Some(boot_time) = k.data_u32(STAT_BOOT_TIME) {
+            return Ok(boot_time as u64);
+        }
+    }
+
+    Err(KstatError::NotFound("boot time kstat".into()))
+}
+
+pub fn nproc() -> Result<u64> {
+    let ks = wrapper::KstatWrapper::open()?;
+
+    for k in ks.iter_matching(Some(MODULE_UNIX), Some(NAME_SYSTEM_MISC)) {
+        if let Some(nproc) = k.data_u32(STAT_NPROC) {
+            return Ok(nproc as u64);
+        }
+    }
+
+    Err(KstatError::NotFound("process count kstat".into()))
+}
+
+/// Return the 1, 5, and 15 minute load averages, as shown by uptime(1).
+pub fn load_average() -> Result<[f64; 3]> {
+    let ks = wrapper::KstatWrapper::open()?;
+
+    /*
+     * The kernel keeps the averages in fixed point, scaled by FSCALE from
+     * <sys/param.h>.
+     */
+    const FSCALE: f64 = 256.0;
+
+    for k in ks.iter_matching(Some(MODULE_UNIX), Some(NAME_SYSTEM_MISC)) {
+        let n = k.snapshot()?;
+        let avg = |stat| n.u32(stat).map(|v| v as f64 / FSCALE);
+        if let (Some(a), Some(b), Some(c)) = (
+            avg(STAT_AVENRUN_1MIN),
+            avg(STAT_AVENRUN_5MIN),
+            avg(STAT_AVENRUN_15MIN),
+        ) {
+            return Ok([a, b, c]);
+        }
+    }
+
+    Err(KstatError::NotFound("load average kstat".into()))
+}
+
+pub struct Pages {
+    pub freemem: u64,
+    pub physmem: u64,
+}
+
+impl FromKstat for Pages {
+    fn from_kstat(n: &Named) -> Result<Pages> {
+        let (Some(freemem), Some(physmem)) =
+            (n.ulong(STAT_FREEMEM), n.ulong(STAT_PHYSMEM))
+        else {
+            return Err(KstatError::NotFound("system pages statistics".into()));
+        };
+
+        Ok(Pages { freemem, physmem })
+    }
+}
+
+pub fn pages() -> Result<Pages> {
+    let ks = wrapper::KstatWrapper::open()?;
+
+    match ks.lookup(Some(MODULE_UNIX), Some(NAME_SYSTEM_PAGES)) {
+        Some(k) => k.read_into(),
+        None => Err(KstatError::NotFound("system pages kstat".into())),
+    }
+}
+
+/// Match a string against a shell-style pattern, where "*" matches any run of
+/// characters and "?" matches exactly one.
+fn glob_match(pat: &[u8], s: &[u8]) -> bool {
+    match (pat.first(), s.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_match(&pat[1..], s)
+                || (!s.is_empty() && glob_match(pat, &s[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_match(&pat[1..], &s[1..]),
+        (Some(p), Some(c)) if p == c => glob_match(&pat[1..], &s[1..]),
+        _ => false,
+    }
+}
+
+/// A selection of statistics written as "module:instance:name:statistic", as
+/// with kstat(8), e.g., "zfs:0:arcstats:size".  Each field may contain
+/// wildcards, and empty or omitted trailing fields match anything.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KstatSelector {
+    pub module: String,
+    pub instance: String,
+    pub name: String,
+    pub statistic: String,
+}
+
+impl KstatSelector {
+    pub fn parse(pattern: &str) -> Result<KstatSelector> {
+        let mut f = pattern
+            .split(':')
+            .map(|p| if p.is_empty() { "*" } else { p }.to_string())
+            .collect::<Vec<_>>();
+        if f.len() > 4 {
+            return Err(KstatError::InvalidPattern(
+                "pattern should be of the form \
+                module:instance:name:statistic"
+                    .into(),
+            ));
+        }
+        f.resize(4, "*".into());
+
+        let statistic = f.pop().unwrap();
+        let name = f.pop().unwrap();
+        let instance = f.pop().unwrap();
+        let module = f.pop().unwrap();
+        Ok(KstatSelector { module, instance, name, statistic })
+    }
+
+    /// Does the selector include the given kstat?
+    pub fn matches_kstat(
+        &self,
+        module: &str,
+        instance: i32,
+        name: &str,
+    ) -> bool {
+        glob_match(self.module.as_bytes(), module.as_bytes())
+            && glob_match(
+                self.instance.as_bytes(),
+                instance.to_string().as_bytes(),
+            )
+            && glob_match(self.name.as_bytes(), name.as_bytes())
+    }
+
+    /// Does the selector include the given statistic, within a kstat that it
+    /// includes?
+    pub fn matches_statistic(&self, statistic: &str) -> bool {
+        glob_match(self.statistic.as_bytes(), statistic.as_bytes())
+    }
+}
+
+/// A single named statistic, as found by query().
+pub struct Stat {
+    pub module: String,
+    pub instance: i32,
+    pub name: String,
+    pub statistic: String,
+    pub value: KstatDataValue,
+}
+
+/// Find the named statistics selected by a pattern of the form
+/// "module:instance:name:statistic", as with kstat(8).
+pub fn query(pattern: &str) -> Result<Vec<Stat>> {
+    let sel = KstatSelector::parse(pattern)?;
+
+    let ks = wrapper::KstatWrapper::open()?;
+    let mut out = Vec::new();
+
+    for k in ks.iter() {
+        let module = k.module().to_string_lossy();
+        let instance = k.instance();
+        let name = k.name().to_string_lossy();
+
+        if !sel.matches_kstat(&module, instance, &name) {
+            continue;
+        }
+
+        /*
+         * Raw kstats have no names for their contents, but we know the layout
+         * of a few of them.  A kstat may also be removed between walking the
+         * chain and reading it, in which case it has nothing to contribute and
+         * we carry on with the rest.
+         */
+        let data = if module == "unix" && name == "vminfo" {
+            k.read().ok().and_then(|_| k.vminfo()).map(|v| v.fields())
+        } else if module == "unix" && name == "sysinfo" {
+            k.read().ok().and_then(|_| k.sysinfo()).map(|v| v.fields())
+        } else {
+            None
+        }
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(n, v)| (n.to_string(), v))
+        .chain(
+            k.data_all()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|d| (d.name.to_string_lossy().into_owned(), d.value)),
+        )
+        .collect::<Vec<_>>();
+
+        for (statistic, value) in data {
+            if !sel.matches_statistic(&statistic) {
+                continue;
+            }
+
+            out.push(Stat {
+                module: module.to_string(),
+                instance,
+                name: name.to_string(),
+                statistic,
+                value,
+            });
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn selector() {
+        let s = KstatSelector::parse("zfs:0:arcstats:size").unwrap();
+        assert_eq!(s.module, "zfs");
+        assert_eq!(s.instance, "0");
+        assert_eq!(s.name, "arcstats");
+        assert_eq!(s.statistic, "size");
+        assert!(s.matches_kstat("zfs", 0, "arcstats"));
+        assert!(!s.matches_kstat("zfs", 1, "arcstats"));
+        assert!(s.matches_statistic("size"));
+        assert!(!s.matches_statistic("c"));
+    }
+
+    #[test]
+    fn selector_wildcards() {
+        let s = KstatSelector::parse("cpu::vm").unwrap();
+        assert_eq!(s.instance, "*");
+        assert_eq!(s.statistic, "*");
+        assert!(s.matches_kstat("cpu", 3, "vm"));
+        assert!(!s.matches_kstat("cpu", 3, "sys"));
+
+        let s = KstatSelector::parse("").unwrap();
+        assert!(s.matches_kstat("unix", 0, "system_pages"));
+
+        let s = KstatSelector::parse("unix:0:*_pages:?reemem").unwrap();
+        assert!(s.matches_kstat("unix", 0, "system_pages"));
+        assert!(s.matches_statistic("freemem"));
+    }
+
+    #[test]
+    fn selector_too_many_fields() {
+        assert!(matches!(
+            KstatSelector::parse("a:b:c:d:e"),
+            Err(KstatError::InvalidPattern(_))
+        ));
+    }
+
+    fn cpus(counts: &[(i32, u64, u64)]) -> CpuStats {
+        CpuStats {
+            cpus: counts
+                .iter()
+                .map(|&(id, scan, syscall)| {
+                    (id, CpuCounters { scan, syscall, ..Default::default() })
+                })
+                .collect(),
+            snaptime: 0,
+        }
+    }
+
+    #[test]
+    fn cpu_stats_since() {
+        let a = cpus(&[(0, 10, 100), (1, 20, 200)]);
+        let b = cpus(&[(0, 15, 150), (1, 30, 260)]);
+        let d = b.since(&a);
+        assert_eq!((d.scan, d.syscall), (15, 110));
+        assert_eq!(b.total().scan, 45);
+
+        /*
+         * CPU 1 goes offline and CPU 2 comes online: only CPU 0 is present in
+         * both readings, so neither the lost nor the new counters count.
+         */
+        let c = cpus(&[(0, 17, 170), (2, 1000, 1000)]);
+        let d = c.since(&b);
+        assert_eq!((d.scan, d.syscall), (2, 20));
+
+        /*
+         * A CPU whose counters went backwards, e.g., because it was replaced
+         * between readings, contributes nothing rather than wrapping.
+         */
+        let e = cpus(&[(0, 5, 180)]);
+        let d = e.since(&c);
+        assert_eq!((d.scan, d.syscall), (0, 10));
+
+        let d = a.since(&CpuStats::default());
+        assert_eq!((d.scan, d.syscall), (0, 0));
+    }
+
+    #[test]
+    fn raw_vminfo() {
+        let raw: Vec<u8> =
+            (1..=6u64).flat_map(|v| (v * 1000).to_ne_bytes()).collect();
+        let v = KstatVminfo::from_raw(&raw).unwrap();
+        assert_eq!(v.freemem, 1000);
+        assert_eq!(v.swap_free, 5000);
+        assert_eq!(v.updates, 6000);
+
+        /*
+         * Trailing data beyond the structure is ignored, but a short buffer
+         * is not enough.
+         */
+        let mut long = raw.clone();
+        long.extend_from_slice(&[0xff; 8]);
+        assert_eq!(KstatVminfo::from_raw(&long).unwrap().updates, 6000);
+        assert!(KstatVminfo::from_raw(&raw[..raw.len() - 1]).is_none());
+        assert!(KstatVminfo::from_raw(&[]).is_none());
+    }
+
+    #[test]
+    fn raw_sysinfo() {
+        let raw: Vec<u8> = (1..=6u32).flat_map(|v| v.to_ne_bytes()).collect();
+        let s = KstatSysinfo::from_raw(&raw).unwrap();
+        assert_eq!((s.updates, s.runque, s.waiting), (1, 2, 6));
+        assert!(KstatSysinfo::from_raw(&raw[..23]).is_none());
+
+        assert_eq!(
+            raw_ints::<u32, 2, 4>(&raw, u32::from_ne_bytes),
+            Some([1, 2])
+        );
+        assert_eq!(raw_ints::<u32, 7, 4>(&raw, u32::from_ne_bytes), None);
+    }
+
+    #[test]
+    fn counter_rate() {
+        assert_eq!(rate(500, 500_000_000), Some(1000.0));
+        assert_eq!(rate(0, 1_000_000_000), Some(0.0));
+        assert_eq!(rate(500, 0), None);
+        assert_eq!(rate(0, 0), None);
+    }
+}
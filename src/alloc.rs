@@ -0,0 +1,162 @@
+/*
+ * The registry of memory allocated at the request of the user.
+ */
+
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+
+use crate::pattern::Pattern;
+use crate::region::{self, Protection, Region};
+
+pub struct Alloc {
+    pub id: usize,
+    pub created: DateTime<Utc>,
+    /// When this allocation was last completely touched, if ever.
+    pub touched: Option<DateTime<Utc>>,
+    pub buf: Region,
+    /// The pattern with which the allocation was filled, if any, so that the
+    /// contents can be verified.
+    pub pattern: Option<Pattern>,
+}
+
+impl Alloc {
+    pub fn size(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Describe the way in which this allocation was made, e.g.,
+    /// "locked,pgsz=2m", or "-" if there is nothing unusual about it.
+    pub fn flags(&self) -> String {
+        let mut flags = Vec::new();
+        if self.buf.locked() {
+            flags.push("locked".to_string());
+        }
+        if let Some(pgsz) = self.buf.pgsz() {
+            flags.push(format!("pgsz={}", region::size_name(pgsz)));
+        }
+        if self.buf.noreserve() {
+            flags.push("noreserve".to_string());
+        }
+        if let Some(path) = self.buf.path() {
+            flags.push(format!("file={}", path.display()));
+        }
+        if let Some(name) = self.buf.shm_name() {
+            flags.push(format!("shm={}", name.display()));
+        }
+        if self.buf.protection() != Protection::ReadWrite {
+            flags.push(format!("prot={}", self.buf.protection()));
+        }
+
+        if flags.is_empty() {
+            "-".into()
+        } else {
+            flags.join(",")
+        }
+    }
+
+    pub fn info(&self) -> AllocInfo {
+        AllocInfo {
+            id: self.id,
+            size: self.size(),
+            created: self.created,
+            touched: self.touched,
+            locked: self.buf.locked(),
+            pgsz: self.buf.pgsz(),
+            file: self.buf.path().map(Path::to_owned),
+        }
+    }
+}
+
+/// A description of an allocation, without the memory itself, that may be
+/// shared with other threads.
+#[derive(Clone)]
+pub struct AllocInfo {
+    pub id: usize,
+    pub size: usize,
+    pub created: DateTime<Utc>,
+    pub touched: Option<DateTime<Utc>>,
+    pub locked: bool,
+    pub pgsz: Option<usize>,
+    pub file: Option<PathBuf>,
+}
+
+#[derive(Default)]
+pub struct Registry {
+    allocs: Vec<Alloc>,
+    last_id: usize,
+}
+
+impl Registry {
+    /// The ID that will be given to the next allocation.
+    pub fn next_id(&self) -> usize {
+        self.last_id + 1
+    }
+
+    /// Track a new allocation, returning its ID.  IDs start at 1 and are
+    /// never reused.
+    pub fn insert(&mut self, buf: Region, pattern: Option<Pattern>) -> usize {
+        self.last_id += 1;
+        self.allocs.push(Alloc {
+            id: self.last_id,
+            created: Utc::now(),
+            touched: None,
+            buf,
+            pattern,
+        });
+        self.last_id
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Alloc> {
+        self.allocs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Alloc> {
+        self.allocs.iter_mut()
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Alloc> {
+        self.allocs.iter_mut().find(|a| a.id == id)
+    }
+
+    /// Stop tracking the most recently created allocation, and return it.
+    pub fn pop(&mut self) -> Option<Alloc> {
+        self.allocs.pop()
+    }
+
+    /// Track again an allocation that was taken out with pop() or remove(),
+    /// keeping its ID and the order of allocations by ID.
+    pub fn restore(&mut self, a: Alloc) {
+        let i = self.allocs.partition_point(|b| b.id < a.id);
+        self.allocs.insert(i, a);
+    }
+
+    /// Stop tracking the allocation with the given ID, and return it.
+    pub fn remove(&mut self, id: usize) -> Option<Alloc> {
+        let i = self.allocs.iter().position(|a| a.id == id)?;
+        Some(self.allocs.remove(i))
+    }
+
+    pub fn len(&self) -> usize {
+        self.allocs.len()
+    }
+
+    /// The total size of all allocations, in bytes.
+    pub fn total(&self) -> usize {
+        self.allocs.iter().map(Alloc::size).sum()
+    }
+
+    /// The total size of allocations backed by files rather than anonymous
+    /// memory, in bytes.
+    pub fn file_total(&self) -> usize {
+        self.allocs
+            .iter()
+            .filter(|a| a.buf.path().is_some())
+            .map(Alloc::size)
+            .sum()
+    }
+
+    pub fn info(&self) -> Vec<AllocInfo> {
+        self.allocs.iter().map(Alloc::info).collect()
+    }
+}
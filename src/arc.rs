@@ -0,0 +1,291 @@
+/*
+ * Reporting on the ZFS Adaptive Replacement Cache (ARC).
+ */
+
+use std::ffi::CStr;
+
+use anyhow::{bail, Result};
+
+use crate::kstat::{consts::*, KstatWrapper, Named};
+use crate::kvm::Kvm;
+
+fn mb(v: u64) -> String {
+    format!("{:.1} MB", v as f64 / 1024.0 / 1024.0)
+}
+
+/// Read a statistic from the "zfs:0:arcstats" kstat.
+fn arcstat(k: &KstatWrapper, stat: &CStr) -> Option<u64> {
+    k.lookup(Some(MODULE_ZFS), Some(NAME_ARCSTATS))?.data_u64(stat)
+}
+
+/// Take a single snapshot of the "zfs:0:arcstats" kstat, so that the figures
+/// in a report are consistent with one another and the kstat is read once.
+fn arcstats(k: &KstatWrapper) -> Result<Named<'_>> {
+    let Some(ks) = k.lookup(Some(MODULE_ZFS), Some(NAME_ARCSTATS)) else {
+        bail!("arcstats kstat not available");
+    };
+    Ok(ks.snapshot()?)
+}
+
+/// The current size of the ARC in bytes.
+pub fn size() -> Result<u64> {
+    let k = KstatWrapper::open()?;
+    match arcstat(&k, STAT_SIZE) {
+        Some(size) => Ok(size),
+        None => bail!("arcstats kstat not available"),
+    }
+}
+
+/// Report the state of the ARC: how large it is and how that is divided up,
+/// how well it is serving reads, and how much it has had to evict.
+pub fn panel() -> Result<Vec<String>> {
+    let k = KstatWrapper::open()?;
+    let n = arcstats(&k)?;
+    let get = |stat| n.counter(stat);
+    let mut out = Vec::new();
+
+    for (name, stat) in [
+        ("size", STAT_SIZE),
+        ("c", STAT_C),
+        ("p", STAT_P),
+        ("c_min", STAT_C_MIN),
+        ("c_max", STAT_C_MAX),
+        ("mru_size", STAT_MRU_SIZE),
+        ("mfu_size", STAT_MFU_SIZE),
+        ("arc_meta_used", STAT_ARC_META_USED),
+        ("arc_meta_limit", STAT_ARC_META_LIMIT),
+    ] {
+        let v = get(stat).map(mb).unwrap_or_else(|| "-".into());
+        out.push(format!("{name:<24} {v:>12}"));
+    }
+
+    let hits = get(STAT_HITS);
+    let misses = get(STAT_MISSES);
+    for (name, v) in [("hits", hits), ("misses", misses)] {
+        let v = v.map(|v| v.to_string()).unwrap_or_else(|| "-".into());
+        out.push(format!("{name:<24} {v:>12}"));
+    }
+    if let (Some(h), Some(m)) = (hits, misses) {
+        if h + m > 0 {
+            let ratio = h as f64 * 100.0 / (h + m) as f64;
+            out.push(format!("{:<24} {:>11.1}%", "hit ratio", ratio));
+        }
+    }
+
+    for (name, stat) in [
+        ("deleted", STAT_DELETED),
+        ("evict_skip", STAT_EVICT_SKIP),
+        ("mutex_miss", STAT_MUTEX_MISS),
+        ("memory_throttle_count", STAT_MEMORY_THROTTLE_COUNT),
+    ] {
+        let v = get(stat).map(|v| v.to_string()).unwrap_or_else(|| "-".into());
+        out.push(format!("{name:<24} {v:>12}"));
+    }
+
+    Ok(out)
+}
+
+/// Report the ARC tunables set in the kernel, alongside the live values that
+/// are actually in force.  A tunable of zero means the ARC has sized itself
+/// automatically.
+pub fn tunables() -> Result<Vec<String>> {
+    let k = KstatWrapper::open()?;
+    let n = arcstats(&k)?;
+    let get = |stat| n.counter(stat);
+    let mut out = Vec::new();
+
+    /*
+     * Reading kernel variables requires privilege that we may not have, in
+     * which case we can still report the live values.
+     */
+    let kvm = match Kvm::new_readonly() {
+        Ok(kvm) => Some(kvm),
+        Err(e) => {
+            out.push(format!("cannot read kernel tunables: {e}"));
+            None
+        }
+    };
+    let var = |name: &str, wide: bool| -> Option<Result<u64>> {
+        kvm.as_ref().map(|kvm| {
+            let addr = kvm.locate(name)?;
+            Ok(if wide {
+                kvm.read_usize(addr)? as u64
+            } else {
+                kvm.read_u32(addr)? as u64
+            })
+        })
+    };
+
+    for (tunable, live, live_name) in [
+        ("zfs_arc_max", Some(STAT_C_MAX), "c_max"),
+        ("zfs_arc_min", Some(STAT_C_MIN), "c_min"),
+        ("zfs_arc_meta_limit", Some(STAT_ARC_META_LIMIT), "arc_meta_limit"),
+    ] {
+        let live = live.and_then(get);
+        let live_str = live.map(mb).unwrap_or_else(|| "-".into());
+
+        let t = match var(tunable, true) {
+            None => {
+                out.push(format!("{live_name:<16} {live_str:>12}"));
+                continue;
+            }
+            Some(Err(e)) => format!("{tunable} unknown ({e})"),
+            Some(Ok(0)) => format!("{tunable} 0 (automatic)"),
+            Some(Ok(v)) => {
+                let mut t = format!("{tunable} {}", mb(v));
+                if live.is_some_and(|live| live != v) {
+                    t.push_str(" NOT IN FORCE");
+                }
+                t
+            }
+        };
+        out.push(format!("{live_name:<16} {live_str:>12}   {t}"));
+    }
+
+    for (name, stat) in [("c", STAT_C), ("p", STAT_P), ("size", STAT_SIZE)] {
+        let live = get(stat).map(mb).unwrap_or_else(|| "-".into());
+        out.push(format!("{name:<16} {live:>12}"));
+    }
+
+    for tunable in
+        ["zfs_arc_grow_retry", "zfs_arc_shrink_shift", "arc_lotsfree_percent"]
+    {
+        match var(tunable, false) {
+            Some(Ok(v)) => out.push(format!("{tunable} {v}")),
+            Some(Err(e)) => out.push(format!("{tunable} unknown ({e})")),
+            None => (),
+        }
+    }
+
+    Ok(out)
+}
+
+/// Report the ARC in the manner of arc_summary, from a single snapshot of the
+/// "zfs:0:arcstats" kstat so that the figures are consistent with one another.
+pub fn summary() -> Result<Vec<String>> {
+    let k = KstatWrapper::open()?;
+    let n = arcstats(&k)?;
+    let mut out = Vec::new();
+
+    let get = |stat| n.counter(stat);
+    let pct = |v: Option<u64>, of: Option<u64>| match (v, of) {
+        (Some(v), Some(of)) if of > 0 => {
+            format!("{:5.1}%", v as f64 * 100.0 / of as f64)
+        }
+        _ => String::new(),
+    };
+    let size = get(STAT_SIZE);
+
+    let sizes = |out: &mut Vec<String>, title: &str, rows: &[_]| {
+        out.push(format!("{title}:"));
+        for &(name, stat, of) in rows {
+            let v = get(stat);
+            let s = v.map(mb).unwrap_or_else(|| "-".into());
+            out.push(format!("    {name:<24} {s:>12} {}", pct(v, of)));
+        }
+    };
+
+    sizes(
+        &mut out,
+        "ARC size",
+        &[
+            ("current size", STAT_SIZE, get(STAT_C_MAX)),
+            ("target size (c)", STAT_C, get(STAT_C_MAX)),
+            ("minimum size (c_min)", STAT_C_MIN, get(STAT_C_MAX)),
+            ("maximum size (c_max)", STAT_C_MAX, None),
+        ],
+    );
+    sizes(
+        &mut out,
+        "ARC breakdown",
+        &[
+            ("MRU target (p)", STAT_P, get(STAT_C)),
+            ("MRU size", STAT_MRU_SIZE, size),
+            ("MFU size", STAT_MFU_SIZE, size),
+            ("data size", STAT_DATA_SIZE, size),
+            ("metadata size", STAT_METADATA_SIZE, size),
+        ],
+    );
+    sizes(
+        &mut out,
+        "ARC metadata",
+        &[
+            ("used", STAT_ARC_META_USED, get(STAT_ARC_META_LIMIT)),
+            ("limit", STAT_ARC_META_LIMIT, None),
+            ("high water mark", STAT_ARC_META_MAX, get(STAT_ARC_META_LIMIT)),
+        ],
+    );
+
+    /*
+     * Compression only applies to the data held in the ARC as it came from
+     * disk, so the ratio is of the uncompressed to the compressed size.
+     */
+    let comp = get(STAT_COMPRESSED_SIZE);
+    let uncomp = get(STAT_UNCOMPRESSED_SIZE);
+    sizes(
+        &mut out,
+        "ARC compression",
+        &[
+            ("compressed size", STAT_COMPRESSED_SIZE, None),
+            ("uncompressed size", STAT_UNCOMPRESSED_SIZE, None),
+            ("overhead size", STAT_OVERHEAD_SIZE, size),
+        ],
+    );
+    if let (Some(c), Some(u)) = (comp, uncomp) {
+        if c > 0 {
+            let ratio = u as f64 / c as f64;
+            out.push(format!("    {:<24} {ratio:>11.2}x", "compression ratio"));
+        }
+    }
+
+    let hits = get(STAT_HITS);
+    let total = hits.zip(get(STAT_MISSES)).map(|(h, m)| h + m);
+    out.push("ARC accesses:".into());
+    for (name, stat) in [
+        ("hits", STAT_HITS),
+        ("misses", STAT_MISSES),
+        ("demand data hits", STAT_DEMAND_DATA_HITS),
+        ("demand data misses", STAT_DEMAND_DATA_MISSES),
+        ("demand metadata hits", STAT_DEMAND_METADATA_HITS),
+        ("demand metadata misses", STAT_DEMAND_METADATA_MISSES),
+        ("prefetch data hits", STAT_PREFETCH_DATA_HITS),
+        ("prefetch data misses", STAT_PREFETCH_DATA_MISSES),
+        ("prefetch metadata hits", STAT_PREFETCH_METADATA_HITS),
+        ("prefetch metadata misses", STAT_PREFETCH_METADATA_MISSES),
+    ] {
+        let v = get(stat);
+        let s = v.map(|v| v.to_string()).unwrap_or_else(|| "-".into());
+        out.push(format!("    {name:<24} {s:>12} {}", pct(v, total)));
+    }
+
+    /*
+     * Hits in the ghost lists are for buffers that were recently evicted, and
+     * so show how much a larger ARC would have helped.
+     */
+    out.push("ARC hits by list:".into());
+    for (name, stat) in [
+        ("MRU", STAT_MRU_HITS),
+        ("MFU", STAT_MFU_HITS),
+        ("MRU ghost", STAT_MRU_GHOST_HITS),
+        ("MFU ghost", STAT_MFU_GHOST_HITS),
+    ] {
+        let v = get(stat);
+        let s = v.map(|v| v.to_string()).unwrap_or_else(|| "-".into());
+        out.push(format!("    {name:<24} {s:>12} {}", pct(v, hits)));
+    }
+
+    out.push("ARC eviction:".into());
+    for (name, stat) in [
+        ("deleted", STAT_DELETED),
+        ("evict_skip", STAT_EVICT_SKIP),
+        ("evict_not_enough", STAT_EVICT_NOT_ENOUGH),
+        ("mutex_miss", STAT_MUTEX_MISS),
+        ("hash_collisions", STAT_HASH_COLLISIONS),
+        ("memory_throttle_count", STAT_MEMORY_THROTTLE_COUNT),
+    ] {
+        let v = get(stat).map(|v| v.to_string()).unwrap_or_else(|| "-".into());
+        out.push(format!("    {name:<24} {v:>12}"));
+    }
+
+    Ok(out)
+}
@@ -0,0 +1,2373 @@
+/*
+ * Commands entered at the prompt.  Each command is run to completion on the
+ * command thread while the editor waits, so a command that runs for a long
+ * time should check periodically for an interrupt from the user.  Work that
+ * is started in the background reports back through the Activity channel.
+ */
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::alloc::Registry;
+use crate::pattern::{self, Pattern};
+use crate::region::{self, Protection, Region};
+use crate::settings::{Priority, Settings};
+use crate::term::Term;
+use crate::{arc, disk, fault, fileread, forks, grow, hold, kmem, kstat};
+use crate::{memstat, parse, snapshot};
+use crate::{oscillate, ramp, release, stack, stats, sys, worker};
+use crate::{Activity, FillMem};
+
+/// Touch about this many bytes at a time between checks for interruption.
+const TOUCH_CHUNK: usize = 1024 * 1024;
+
+/// The amount of an allocation compared at a time by "verify".
+const VERIFY_CHUNK: usize = 64 * 1024;
+
+/// The largest allocation, in megabytes, made by each step of "growuntil".
+const GROWUNTIL_CHUNK_MB: u64 = 64;
+
+/// The size of each allocation made by "leak", until one fails.
+const LEAK_CHUNK: usize = 64 * 1024 * 1024;
+
+/// The state that persists from one command to the next.
+pub struct Ctx<'a> {
+    pub ed: &'a Arc<Term>,
+    pub fm: &'a Arc<FillMem>,
+    pub settings: &'a mut Settings,
+    pub allocs: Registry,
+    pub snapshots: Option<snapshot::Snapshots>,
+    pub hold: Option<hold::Hold>,
+    pub ramp: Option<ramp::Ramp>,
+    pub oscillation: Option<oscillate::Oscillation>,
+    pub stack: Option<stack::Stack>,
+    /// Whether the whole process is locked in memory with mlockall(3C).
+    pub locked_all: bool,
+    /// Child processes sharing our allocations copy-on-write.
+    pub children: Vec<forks::Children>,
+    pub workers: Vec<worker::Worker>,
+    /// Used by background jobs to report back to the command loop.
+    pub tx: mpsc::Sender<Activity>,
+    pub last_job: usize,
+    /// The allocation made by the most recent grow, for "undo".
+    pub last_grow: Option<usize>,
+    /// If set by "timeout", the time at which the current command should
+    /// give up as if interrupted.
+    pub deadline: Option<Instant>,
+}
+
+impl Ctx<'_> {
+    fn log(&self, msg: &str) -> Result<()> {
+        self.ed.log(msg)
+    }
+
+    /// Determine whether the current command should stop early.
+    fn interrupted(&self) -> bool {
+        interrupted(self.ed, self.deadline)
+    }
+}
+
+/// Determine whether a command should stop early, either because the user
+/// pressed ^C or because its time is up.
+fn interrupted(ed: &Term, deadline: Option<Instant>) -> bool {
+    ed.take_ctrlc() || deadline.is_some_and(|d| Instant::now() >= d)
+}
+
+pub fn dispatch(c: &mut Ctx, t: &[&str]) -> Result<()> {
+    let Some((cmd, args)) = t.split_first() else {
+        return Ok(());
+    };
+
+    match *cmd {
+        "touch" => touch(c, args),
+        "churn" => churn(c, args),
+        "grow" => grow(c, args),
+        "growfile" => growfile(c, args),
+        "pshm" => pshm(c, args),
+        "growuntil" => growuntil(c, args),
+        "leak" => leak(c, args),
+        "fragment" => fragment(c, args),
+        "reserve" => reserve(c, args),
+        "free" => free(c, args),
+        "undo" => undo(c, args),
+        "list" => list(c),
+        "madvise" => madvise(c, args),
+        "protect" => protect(c, args),
+        "lock" => lock(c, args, true),
+        "unlock" => lock(c, args, false),
+        "copy" => copy(c, args),
+        "flush" => flush(c, args),
+        "verify" => verify(c, args),
+        "status" => status(c),
+        "arcfill" => arcfill(c, args),
+        "dread" => dread(c, args),
+        "arc" => match arc::panel() {
+            Ok(lines) => {
+                for l in lines {
+                    c.log(&l)?;
+                }
+                Ok(())
+            }
+            Err(e) => c.log(&format!("arc: {e}")),
+        },
+        "arcsummary" => match arc::summary() {
+            Ok(lines) => {
+                for l in lines {
+                    c.log(&l)?;
+                }
+                Ok(())
+            }
+            Err(e) => c.log(&format!("arcsummary: {e}")),
+        },
+        "arctune" => match arc::tunables() {
+            Ok(lines) => {
+                for l in lines {
+                    c.log(&l)?;
+                }
+                Ok(())
+            }
+            Err(e) => c.log(&format!("arctune: {e}")),
+        },
+        "kmem" => kmem_caches(c, args),
+        "disks" => disks(c, args),
+        "memstat" => match memstat::read() {
+            Ok(m) => {
+                for l in m.lines() {
+                    c.log(&l)?;
+                }
+                Ok(())
+            }
+            Err(e) => c.log(&format!("memstat: {e}")),
+        },
+        "kstat" if args.first() == Some(&"dump") => kstat_dump(c, &args[1..]),
+        "kstat" => kstat_query(c, args),
+        "snapshot" => snapshot(c, args),
+        "hold" => hold(c, args),
+        "stack" => stack(c, args),
+        "lockall" => lockall(c, args),
+        "ramp" => ramp(c, args),
+        "oscillate" => oscillate(c, args),
+        "lgrp" => lgroups(c),
+        "swap" => swap(c),
+        "zone" => zone(c),
+        "fork" => fork(c, args),
+        "spawn" => spawn(c, args),
+        "set" => set(c, args),
+        "interval" => interval(c, args),
+        "stats" => stats_columns(c, args),
+        "pause" => {
+            if c.fm.set_paused(true) {
+                c.log("statistics already paused")
+            } else {
+                c.log("statistics paused")
+            }
+        }
+        "resume" => {
+            if c.fm.set_paused(false) {
+                c.log("statistics resumed")
+            } else {
+                c.log("statistics not paused")
+            }
+        }
+        "timeout" => timeout(c, args),
+        other => c.log(&format!("{other:?} not understood")),
+    }
+}
+
+/// Run another command, treating it as interrupted if it has not finished
+/// within the given period.  Whatever it managed to do in that time is kept.
+fn timeout(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let (period, rest) = match args.split_first() {
+        Some((p, rest)) if !rest.is_empty() => (p, rest),
+        _ => return c.log("usage: timeout PERIOD COMMAND ..."),
+    };
+    let period = match parse::duration(period) {
+        Ok(p) => p,
+        Err(e) => return c.log(&e.to_string()),
+    };
+    if c.deadline.is_some() {
+        return c.log("timeout may not be nested");
+    }
+
+    let deadline = Instant::now() + period;
+    c.deadline = Some(deadline);
+    let res = dispatch(c, rest);
+    c.deadline = None;
+    res?;
+
+    if Instant::now() >= deadline {
+        c.log(&format!("timed out after {}s", period.as_secs_f64()))?;
+    }
+    Ok(())
+}
+
+fn touch(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    /*
+     * Modifying one byte in each page is enough to fault the whole page in,
+     * but a smaller stride, down to every byte, is available when the point is
+     * to measure memory bandwidth.  By default every allocation is touched,
+     * but the user may name just one of them.
+     */
+    let mut stride = region::pagesize();
+    let mut id = None;
+    let mut bind = None;
+    let mut opts = args.iter();
+    while let Some(a) = opts.next() {
+        match *a {
+            "stride" => match opts.next().map(|v| v.parse::<usize>()) {
+                Some(Ok(v)) if v > 0 => stride = v,
+                Some(Ok(_)) => return c.log("stride must be at least 1 byte"),
+                Some(Err(e)) => return c.log(&e.to_string()),
+                None => return c.log("touch with what stride?"),
+            },
+            "bind" => match opts.next().map(|v| v.parse::<i32>()) {
+                Some(Ok(v)) => bind = Some(v),
+                Some(Err(e)) => return c.log(&e.to_string()),
+                None => return c.log("touch bound to which CPU?"),
+            },
+            other => match other.parse::<usize>() {
+                Ok(v) if id.is_none() => id = Some(v),
+                _ => return c.log(&format!("touch option {other:?} unknown")),
+            },
+        }
+    }
+
+    if let Some(id) = id {
+        match c.allocs.iter().find(|a| a.id == id) {
+            None => return c.log(&format!("no allocation {id}")),
+            Some(a) if a.buf.protection() != Protection::ReadWrite => {
+                return c.log(&format!("allocation {id} is not writable"));
+            }
+            Some(_) => (),
+        }
+    }
+
+    /*
+     * Sample the system-wide paging counters on either side of the touch, so
+     * that we can say where the pages we faulted back in came from.  Other
+     * activity on the system will be included too, so this is only an
+     * approximation.
+     */
+    let mut source = stats::Source::open().ok();
+    let vm0 = source.as_mut().and_then(|s| s.vm().ok());
+
+    /*
+     * The touch happens on this thread, so bind it only for the duration and
+     * then put back whatever binding we had before.
+     */
+    let obind = match bind.map(|cpu| sys::bind_lwp(Some(cpu))) {
+        Some(Ok(obind)) => Some(obind),
+        Some(Err(e)) => return c.log(&e.to_string()),
+        None => None,
+    };
+
+    let start = Instant::now();
+    let res = touch_pass(c, id, stride, None);
+    let dur = Instant::now().checked_duration_since(start).unwrap();
+
+    if let Some(obind) = obind {
+        sys::bind_lwp(obind)?;
+    }
+    let Some(sz) = res else {
+        return c.log("interrupted!");
+    };
+
+    let mb = sz / 1024 / 1024;
+    let cpu = bind.map(|cpu| format!(", CPU {cpu}")).unwrap_or_default();
+    c.log(&format!(
+        "touched {mb} megabytes in {} msec (stride {stride}{cpu})",
+        dur.as_millis()
+    ))?;
+
+    let vm1 = source.as_mut().and_then(|s| s.vm().ok());
+    if let (Some(vm0), Some(vm1)) = (vm0, vm1) {
+        let d = vm1.since(&vm0);
+        c.log(&format!(
+            "page-ins: {} anon from swap, {} file, \
+            {} reclaimed from free list ({} page-in ops)",
+            d.anonpgin, d.fspgin, d.pgfrec, d.pgin,
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Parse a size argument, e.g., "512m", "4g", or "25%".  A plain number is a
+/// count of megabytes.
+fn size(s: &str) -> Result<usize> {
+    Ok(parse::size(s, &parse::SizeContext::system())? as usize)
+}
+
+/// Touch the selected allocations, or all of them if id is None, once every
+/// stride bytes and at no more than the given rate in MB/s.  Returns the number
+/// of bytes covered, or None if the user interrupted us.  Allocations that are
+/// not writable are always skipped, so callers should refuse one that is named
+/// explicitly.
+fn touch_pass(
+    c: &mut Ctx,
+    id: Option<usize>,
+    stride: usize,
+    rate: Option<f64>,
+) -> Option<u64> {
+    /*
+     * Each chunk is a whole number of strides, so that the stride carries on
+     * unbroken from one chunk to the next.
+     */
+    let chunk = stride * (TOUCH_CHUNK / stride).max(1);
+
+    let (ed, deadline) = (c.ed, c.deadline);
+    let pacer = grow::Pacer::new(rate);
+    let mut sz: u64 = 0;
+    for a in c.allocs.iter_mut().filter(|a| {
+        id.is_none_or(|id| a.id == id)
+            && a.buf.protection() == Protection::ReadWrite
+    }) {
+        for off in (0..a.buf.len()).step_by(chunk) {
+            let len = chunk.min(a.buf.len() - off);
+            if a.buf.touch(off, len, stride).is_err() {
+                break;
+            }
+
+            if interrupted(ed, deadline) {
+                return None;
+            }
+            pacer.wait(sz as usize + off + len, &|| interrupted(ed, deadline));
+        }
+        a.touched = Some(chrono::Utc::now());
+        sz += a.size() as u64;
+    }
+
+    Some(sz)
+}
+
+fn churn(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let mut id = None;
+    let mut rate = None;
+    let mut opts = args.iter();
+    while let Some(a) = opts.next() {
+        match *a {
+            "rate" => match opts.next().map(|v| parse::rate(v)) {
+                Some(Ok(v)) => rate = Some(v),
+                Some(Err(e)) => return c.log(&e.to_string()),
+                None => return c.log("churn at what rate?"),
+            },
+            /*
+             * Positional arguments are "churn [id] [rate]": a leading integer
+             * names the allocation, and anything after it is the rate.
+             */
+            other => match other.parse::<usize>() {
+                Ok(v) if id.is_none() && rate.is_none() => id = Some(v),
+                _ if rate.is_none() => match parse::rate(other) {
+                    Ok(v) => rate = Some(v),
+                    Err(e) => return c.log(&e.to_string()),
+                },
+                _ => return c.log(&format!("churn option {other:?} unknown")),
+            },
+        }
+    }
+
+    if let Some(id) = id {
+        match c.allocs.iter().find(|a| a.id == id) {
+            None => return c.log(&format!("no allocation {id}")),
+            Some(a) if a.buf.protection() != Protection::ReadWrite => {
+                return c.log(&format!("allocation {id} is not writable"));
+            }
+            Some(_) => (),
+        }
+    }
+    if c.allocs.len() == 0 {
+        return c.log("nothing to churn");
+    }
+
+    /*
+     * Walk the working set over and over, as an active process would, until
+     * the user has seen enough.
+     */
+    let start = Instant::now();
+    let mut passes = 0;
+    loop {
+        let pass = Instant::now();
+        let Some(sz) = touch_pass(c, id, region::pagesize(), rate) else {
+            break;
+        };
+        passes += 1;
+
+        c.log(&format!(
+            "pass {passes}: touched {} megabytes in {} msec",
+            sz / 1024 / 1024,
+            pass.elapsed().as_millis()
+        ))?;
+    }
+
+    c.log(&format!(
+        "interrupted! churned for {passes} passes in {} msec",
+        start.elapsed().as_millis()
+    ))
+}
+
+fn grow(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    /*
+     * A trailing "&" asks for the allocation to happen in the background,
+     * leaving the prompt free for other commands.
+     */
+    let (args, background) = match args.split_last() {
+        Some((&"&", rest)) => (rest, true),
+        _ => (args, false),
+    };
+
+    let spec = match grow::Spec::parse(args) {
+        Ok(spec) => spec,
+        Err(e) => return c.log(&e.to_string()),
+    };
+
+    /*
+     * In a zone with a physical memory cap, growing past the cap will have us
+     * paged out by the memory capper long before the system runs short, which
+     * is rarely what was intended.
+     */
+    if let Ok(z) = stats::Source::open().and_then(|mut s| s.zone()) {
+        let cap = z.physcap.filter(|_| z.id != 0);
+        let left = cap.map(|cap| cap.saturating_sub(z.rss));
+        if let Some(left) = left.filter(|&left| spec.size as u64 > left) {
+            c.log(&format!(
+                "warning: growing by {} exceeds the {:.1} MB left under \
+                the physical memory cap of zone {}",
+                parse::megabytes(spec.size as u64),
+                left as f64 / 1024.0 / 1024.0,
+                z.name,
+            ))?;
+        }
+    }
+
+    if background {
+        c.last_job += 1;
+        let job = c.last_job;
+        let tx = c.tx.clone();
+        let s = spec.clone();
+        let deadline = c.deadline;
+        std::thread::Builder::new().name(format!("grow-{job}")).spawn(
+            move || {
+                let res =
+                    s.run(&|| deadline.is_some_and(|d| Instant::now() >= d));
+                tx.send(Activity::Grown(job, Box::new(s), Box::new(res))).ok();
+            },
+        )?;
+
+        return c
+            .log(&format!("started job {job}: grow by {}", spec.describe()));
+    }
+
+    match spec.run(&|| c.interrupted()) {
+        Ok(Some(g)) => grown(c, None, &spec, g),
+        Ok(None) => c.log("interrupted!"),
+        Err(e) => c.log(&e.to_string()),
+    }
+}
+
+/// Report on a completed background job.
+pub fn job_done(
+    c: &mut Ctx,
+    job: usize,
+    spec: grow::Spec,
+    res: Result<Option<grow::Grown>>,
+) -> Result<()> {
+    match res {
+        Ok(Some(g)) => grown(c, Some(job), &spec, g),
+        Ok(None) => c.log(&format!("job {job}: interrupted!")),
+        Err(e) => c.log(&format!("job {job}: {e}")),
+    }
+}
+
+fn grown(
+    c: &mut Ctx,
+    job: Option<usize>,
+    spec: &grow::Spec,
+    g: grow::Grown,
+) -> Result<()> {
+    let prefix = job.map(|job| format!("job {job}: ")).unwrap_or_default();
+    let sz = g.region.len();
+    let id = c.allocs.insert(g.region, Some(g.pattern));
+    c.last_grow = Some(id);
+
+    /*
+     * Report the rate at which we were actually able to consume memory, which
+     * is mostly the rate at which the system can give us zeroed pages.
+     */
+    let mut rate = String::new();
+    if spec.threads > 1 {
+        rate.push_str(&format!(" with {} threads", spec.threads));
+    }
+    rate.push_str(&format!(", {:.1} MB/s", grow::bandwidth(sz, g.dur)));
+    if let Some(target) = spec.rate {
+        rate.push_str(&format!(" (target {target} MB/s)"));
+    }
+    let what = if g.partial {
+        format!(
+            "interrupted! grew by {} of {}",
+            parse::megabytes(sz as u64),
+            spec.describe()
+        )
+    } else {
+        format!("grew by {}", spec.describe())
+    };
+    c.log(&format!(
+        "{prefix}{what} in {} msec{rate} (allocation {id})",
+        g.dur.as_millis()
+    ))?;
+
+    /*
+     * Describe the pages that back the new region, and where they came from
+     * if the user asked for something specific.
+     */
+    let mb = |b: usize| b as f64 / 1024.0 / 1024.0;
+    match g.granted {
+        Ok(granted) => c.log(&format!(
+            "{prefix}page sizes: {}",
+            granted
+                .iter()
+                .rev()
+                .map(|(&pgsz, &b)| format!(
+                    "{} {:.1} MB",
+                    region::size_name(pgsz as usize),
+                    mb(b)
+                ))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))?,
+        Err(e) => c.log(&format!("{prefix}page sizes: {e}"))?,
+    }
+    match g.lgroups {
+        Some(Ok(lgroups)) => c.log(&format!(
+            "{prefix}placement: {}",
+            lgroups
+                .iter()
+                .map(|(&id, &b)| format!("lgrp {id} {:.1} MB", mb(b)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        Some(Err(e)) => c.log(&format!("{prefix}placement: {e}")),
+        None => Ok(()),
+    }
+}
+
+fn growuntil(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let target = match (args.first(), args.get(1)) {
+        (Some(&"free"), Some(sz)) => match size(sz) {
+            Ok(sz) => sz as u64,
+            Err(e) => return c.log(&e.to_string()),
+        },
+        _ => return c.log("usage: growuntil free SIZE"),
+    };
+
+    let mut source = match stats::Source::open() {
+        Ok(source) => source,
+        Err(e) => return c.log(&format!("growuntil: {e}")),
+    };
+
+    /*
+     * Grow a chunk at a time, checking freemem in between.  Each chunk becomes
+     * a separate allocation.  As we approach the target, shrink the chunks so
+     * that we do not overshoot by much.
+     */
+    let start = Instant::now();
+    let mut grown = 0;
+    let mut ids = Vec::new();
+    let free = loop {
+        let free = match source.sample() {
+            Ok(st) => st.freemem * region::pagesize() as u64,
+            Err(e) => return c.log(&format!("growuntil: {e}")),
+        };
+        if free < target {
+            break free;
+        }
+
+        let megs = ((free - target) / 1024 / 1024 + 1).min(GROWUNTIL_CHUNK_MB);
+        let spec = grow::Spec::new(megs as usize * 1024 * 1024);
+        match spec.run(&|| c.interrupted()) {
+            Ok(Some(g)) => {
+                let partial = g.partial;
+                grown += g.region.len() as u64;
+                ids.push(c.allocs.insert(g.region, Some(g.pattern)));
+                if partial {
+                    return c.log(&format!(
+                        "interrupted! grew by {}",
+                        parse::megabytes(grown)
+                    ));
+                }
+            }
+            Ok(None) => {
+                return c.log(&format!(
+                    "interrupted! grew by {}",
+                    parse::megabytes(grown)
+                ))
+            }
+            Err(e) => {
+                return c.log(&format!(
+                    "{e} (after growing by {})",
+                    parse::megabytes(grown)
+                ))
+            }
+        }
+    };
+
+    c.log(&format!(
+        "freemem {:.1} MB is below {:.1} MB after growing by {} in {} msec{}",
+        free as f64 / 1024.0 / 1024.0,
+        target as f64 / 1024.0 / 1024.0,
+        parse::megabytes(grown),
+        start.elapsed().as_millis(),
+        match (ids.first(), ids.last()) {
+            (Some(f), Some(l)) => format!(" (allocations {f}-{l})"),
+            _ => "".into(),
+        }
+    ))
+}
+
+fn leak(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let max = match args {
+        [] => None,
+        [max] => match size(max) {
+            Ok(max) => Some(max),
+            Err(e) => return c.log(&e.to_string()),
+        },
+        _ => return c.log("usage: leak [MAX]"),
+    };
+
+    /*
+     * Grow a chunk at a time until something gives.  When an allocation
+     * fails, try again with smaller chunks so that we find the limit to within
+     * a page before we report it.
+     */
+    let start = Instant::now();
+    let mut last_report = start;
+    let mut grown = 0;
+    let mut ids = Vec::new();
+    let mut chunk = LEAK_CHUNK;
+    let why = loop {
+        let want = match max {
+            Some(max) if grown >= max => {
+                break format!(
+                    "reaching the limit of {}",
+                    parse::megabytes(max as u64)
+                );
+            }
+            Some(max) => chunk.min(max - grown),
+            None => chunk,
+        };
+
+        match grow::Spec::new(want).run(&|| c.interrupted()) {
+            Ok(Some(g)) => {
+                let partial = g.partial;
+                grown += g.region.len();
+                ids.push(c.allocs.insert(g.region, Some(g.pattern)));
+                if partial {
+                    break "being interrupted".into();
+                }
+            }
+            Ok(None) => break "being interrupted".into(),
+            Err(e) if chunk <= region::pagesize() => break e.to_string(),
+            Err(_) => chunk = (chunk / 2).max(region::pagesize()),
+        }
+
+        let now = Instant::now();
+        if now.duration_since(last_report).as_secs() >= 1 {
+            last_report = now;
+            c.log(&format!(
+                "leaked {} so far...",
+                parse::megabytes(grown as u64)
+            ))?;
+        }
+    };
+
+    c.log(&format!(
+        "leaked {} in {} msec before {why}{}",
+        parse::megabytes(grown as u64),
+        start.elapsed().as_millis(),
+        match (ids.first(), ids.last()) {
+            (Some(f), Some(l)) => format!(" (allocations {f}-{l})"),
+            _ => "".into(),
+        }
+    ))
+}
+
+/// Make many allocations of random sizes, then free about half of them chosen
+/// at random, leaving the survivors scattered through the address space.
+fn fragment(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let [total, min, max] = args else {
+        return c.log("usage: fragment TOTAL MINSIZE MAXSIZE");
+    };
+    let (total, min, max) = match (size(total), size(min), size(max)) {
+        (Ok(total), Ok(min), Ok(max)) => (total, min, max),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            return c.log(&e.to_string())
+        }
+    };
+    if min == 0 || min > max {
+        return c.log(
+            "fragment sizes must be non-zero, and MINSIZE at most MAXSIZE",
+        );
+    }
+
+    let seed = pattern::clock_seed();
+    let mut n = 0;
+    let mut random = || {
+        n += 1;
+        pattern::splitmix64(seed, n)
+    };
+
+    let start = Instant::now();
+    let mut made = Vec::new();
+    let mut grown = 0;
+    while grown < total {
+        if c.interrupted() {
+            break;
+        }
+
+        let sz = min + (random() % (max - min + 1) as u64) as usize;
+        let mut a = match Region::new(sz) {
+            Ok(a) => a,
+            Err(e) => {
+                c.log(&format!("could not grow: {e}"))?;
+                break;
+            }
+        };
+        Pattern::A.fill(a.bytes_mut().expect("new region is writable"), 0);
+        grown += a.len();
+        made.push(c.allocs.insert(a, Some(Pattern::A)));
+    }
+
+    let mut freed = (0, 0);
+    for &id in &made {
+        if random() % 2 == 0 {
+            if let Some(a) = c.allocs.remove(id) {
+                freed.0 += 1;
+                freed.1 += a.size();
+            }
+        }
+    }
+
+    c.log(&format!(
+        "made {} allocations of {} and freed {} of them ({}) in {} msec, \
+        leaving {}{}",
+        made.len(),
+        parse::megabytes(grown as u64),
+        freed.0,
+        parse::megabytes(freed.1 as u64),
+        start.elapsed().as_millis(),
+        parse::megabytes((grown - freed.1) as u64),
+        match (made.first(), made.last()) {
+            (Some(f), Some(l)) => format!(" (allocations {f}-{l})"),
+            _ => "".into(),
+        }
+    ))
+}
+
+fn reserve(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let sz = match args.first().map(|sz| size(sz)) {
+        Some(Ok(sz)) => sz,
+        Some(Err(e)) => return c.log(&e.to_string()),
+        None => return c.log("reserve how much?"),
+    };
+    let noreserve = match args.get(1) {
+        None => false,
+        Some(&"noreserve") => true,
+        Some(other) => {
+            return c.log(&format!("reserve option {other:?} unknown"))
+        }
+    };
+
+    /*
+     * Map the memory but leave it untouched, so that it consumes a swap
+     * reservation (unless asked not to) but no physical pages.
+     */
+    let res =
+        if noreserve { Region::new_noreserve(sz) } else { Region::new(sz) };
+    match res {
+        Ok(a) => {
+            let id = c.allocs.insert(a, None);
+            c.log(&format!(
+                "mapped {} {} swap (allocation {id})",
+                parse::megabytes(sz as u64),
+                if noreserve { "without reserving" } else { "reserving" }
+            ))
+        }
+        Err(e) => c.log(&format!("could not reserve: {e}")),
+    }
+}
+
+fn growfile(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let sz = match args.first().map(|sz| size(sz)) {
+        Some(Ok(sz)) => sz,
+        Some(Err(e)) => return c.log(&e.to_string()),
+        None => return c.log("grow file by how much?"),
+    };
+    let path = match args.get(1) {
+        Some(p) => PathBuf::from(p),
+        None => PathBuf::from(format!(
+            "/var/tmp/fillmem.{}.{}",
+            std::process::id(),
+            c.allocs.next_id()
+        )),
+    };
+
+    /*
+     * Storing to every page of the shared mapping leaves the page cache full
+     * of dirty file pages, which the system must write back before it can
+     * reclaim them.
+     */
+    let start = Instant::now();
+    let mut a = match Region::file(sz, &path) {
+        Ok(a) => a,
+        Err(e) => return c.log(&format!("could not grow file: {e:#}")),
+    };
+    let mut filled = 0;
+    let buf = a.bytes_mut().expect("new region is writable");
+    for chunk in buf.chunks_mut(grow::FILL_CHUNK) {
+        chunk.fill(b'F');
+        filled += chunk.len();
+
+        if c.interrupted() {
+            break;
+        }
+    }
+
+    /*
+     * If we were interrupted, keep what we managed to write.
+     */
+    let what = if filled < sz {
+        a.truncate(filled);
+        format!(
+            "interrupted! grew file {} by {} of {}",
+            path.display(),
+            parse::megabytes(filled as u64),
+            parse::megabytes(sz as u64)
+        )
+    } else {
+        format!(
+            "grew file {} by {}",
+            path.display(),
+            parse::megabytes(sz as u64)
+        )
+    };
+    let id = c.allocs.insert(a, None);
+
+    let dur = Instant::now().checked_duration_since(start).unwrap();
+    c.log(&format!(
+        "{what} in {} msec, {:.1} MB/s (allocation {id})",
+        dur.as_millis(),
+        grow::bandwidth(filled, dur)
+    ))
+}
+
+/// Create a POSIX shared memory segment and fill it.
+fn pshm(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let sz = match args {
+        [sz] | [sz, _] => match size(sz) {
+            Ok(sz) => sz,
+            Err(e) => return c.log(&e.to_string()),
+        },
+        _ => return c.log("usage: pshm SIZE [NAME]"),
+    };
+    let name = match args.get(1) {
+        Some(n) => n.to_string(),
+        None => {
+            format!("/fillmem.{}.{}", std::process::id(), c.allocs.next_id())
+        }
+    };
+
+    /*
+     * Shared memory objects live in tmpfs, so their pages are anonymous
+     * memory that competes with the rest of the system for memory and swap,
+     * but that persist as long as the object does rather than as long as
+     * any mapping of it.
+     */
+    let start = Instant::now();
+    let mut a = match Region::shm(sz, &name) {
+        Ok(a) => a,
+        Err(e) => return c.log(&format!("could not create segment: {e:#}")),
+    };
+    let mut filled = 0;
+    let buf = a.bytes_mut().expect("new region is writable");
+    for chunk in buf.chunks_mut(grow::FILL_CHUNK) {
+        chunk.fill(b'P');
+        filled += chunk.len();
+
+        if c.interrupted() {
+            break;
+        }
+    }
+
+    let what = if filled < sz {
+        a.truncate(filled);
+        format!(
+            "interrupted! created segment {name} of {} of {}",
+            parse::megabytes(filled as u64),
+            parse::megabytes(sz as u64)
+        )
+    } else {
+        format!("created segment {name} of {}", parse::megabytes(sz as u64))
+    };
+    let id = c.allocs.insert(a, None);
+
+    let dur = start.elapsed();
+    c.log(&format!(
+        "{what} in {} msec, {:.1} MB/s (allocation {id})",
+        dur.as_millis(),
+        grow::bandwidth(filled, dur)
+    ))
+}
+
+fn free(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    /*
+     * A trailing "&" asks for the memory to be released in the background,
+     * leaving the prompt free for other commands.
+     */
+    let (args, background) = match args.split_last() {
+        Some((&"&", rest)) => (rest, true),
+        _ => (args, false),
+    };
+
+    /*
+     * With no argument, release everything we have allocated.  Otherwise,
+     * release the requested amount from the most recent allocations.  Memory
+     * goes back to the system a whole page at a time, so round up to a page.
+     */
+    let total = c.allocs.total();
+    let want = match args.first().map(|sz| size(sz)) {
+        Some(Ok(sz)) => sz.next_multiple_of(region::pagesize()).min(total),
+        Some(Err(e)) => return c.log(&e.to_string()),
+        None => total,
+    };
+
+    /*
+     * Take as many of the most recent allocations as we need out of the
+     * registry, and hand them to a thread of our own to release.  Whatever is
+     * left when it stops is tracked again once it is done.
+     */
+    let mut taken = Vec::new();
+    let mut covered = 0;
+    while covered < want {
+        let Some(a) = c.allocs.pop() else {
+            break;
+        };
+        covered += a.size();
+        taken.push(a);
+    }
+    taken.reverse();
+
+    if background {
+        c.last_job += 1;
+        let job = c.last_job;
+        let tx = c.tx.clone();
+        let ed = Arc::clone(c.ed);
+        let deadline = c.deadline;
+        std::thread::Builder::new().name(format!("free-{job}")).spawn(
+            move || {
+                let r = release::run(
+                    taken,
+                    want,
+                    &|| deadline.is_some_and(|d| Instant::now() >= d),
+                    &|freed| free_progress(&ed, Some(job), freed, want),
+                );
+                tx.send(Activity::Freed(job, Box::new(r))).ok();
+            },
+        )?;
+
+        return c.log(&format!(
+            "started job {job}: free {}",
+            parse::megabytes(want as u64)
+        ));
+    }
+
+    /*
+     * The thread cannot check for an interrupt itself, so poll on its behalf
+     * until it is done.
+     */
+    let ed = c.ed;
+    let stop = AtomicBool::new(false);
+    let r = std::thread::scope(|s| {
+        let w = s.spawn(|| {
+            release::run(
+                taken,
+                want,
+                &|| stop.load(Ordering::Relaxed),
+                &|freed| free_progress(ed, None, freed, want),
+            )
+        });
+        while !w.is_finished() {
+            if c.interrupted() {
+                stop.store(true, Ordering::Relaxed);
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        w.join().expect("free thread panicked")
+    });
+
+    released(c, None, r)
+}
+
+fn free_progress(ed: &Term, job: Option<usize>, freed: usize, want: usize) {
+    let prefix = job.map(|job| format!("job {job}: ")).unwrap_or_default();
+    ed.log(&format!(
+        "{prefix}freed {} of {} megabytes...",
+        freed / 1024 / 1024,
+        want / 1024 / 1024,
+    ))
+    .ok();
+}
+
+/// Report on memory released by "free", and track again whatever was left.
+pub fn released(
+    c: &mut Ctx,
+    job: Option<usize>,
+    r: release::Released,
+) -> Result<()> {
+    let prefix = job.map(|job| format!("job {job}: ")).unwrap_or_default();
+    for a in r.kept {
+        c.allocs.restore(a);
+    }
+
+    if r.interrupted {
+        c.log(&format!(
+            "{prefix}interrupted! freed {} of {}, {} remain allocated",
+            parse::megabytes(r.freed as u64),
+            parse::megabytes(r.want as u64),
+            parse::megabytes(c.allocs.total() as u64),
+        ))
+    } else {
+        c.log(&format!(
+            "{prefix}freed {} in {} msec",
+            parse::megabytes(r.freed as u64),
+            r.dur.as_millis()
+        ))
+    }
+}
+
+/// Release the allocation made by the most recent grow, whichever allocations
+/// have been made since.
+fn undo(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    if !args.is_empty() {
+        return c.log("usage: undo");
+    }
+
+    let Some(id) = c.last_grow.take() else {
+        return c.log("nothing to undo");
+    };
+    match c.allocs.remove(id) {
+        Some(a) => c.log(&format!(
+            "released allocation {id} ({})",
+            parse::megabytes(a.size() as u64)
+        )),
+        None => c.log(&format!("allocation {id} has already been freed")),
+    }
+}
+
+fn list(c: &mut Ctx) -> Result<()> {
+    if c.allocs.len() == 0 {
+        return c.log("no allocations");
+    }
+
+    c.log(&format!(
+        "{:>4} {:>12} {:<13} {:<13} {}",
+        "ID", "SIZE", "CREATED", "TOUCHED", "FLAGS"
+    ))?;
+    for a in c.allocs.iter() {
+        let fmt = |t: chrono::DateTime<chrono::Utc>| {
+            t.format("%H:%M:%S%.3fZ").to_string()
+        };
+        c.log(&format!(
+            "{:>4} {:>9.1} MB {:<13} {:<13} {}",
+            a.id,
+            a.size() as f64 / 1024.0 / 1024.0,
+            fmt(a.created),
+            a.touched.map(fmt).unwrap_or_else(|| "-".into()),
+            a.flags(),
+        ))?;
+    }
+
+    Ok(())
+}
+
+fn madvise(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let (Some(id), Some(advice)) = (args.first(), args.get(1)) else {
+        return c.log("usage: madvise ID dontneed|willneed|free");
+    };
+    let id = match id.parse::<usize>() {
+        Ok(id) => id,
+        Err(e) => return c.log(&e.to_string()),
+    };
+    let (flag, discards) = match *advice {
+        "dontneed" => (libc::MADV_DONTNEED, true),
+        "willneed" => (libc::MADV_WILLNEED, false),
+        "free" => (libc::MADV_FREE, true),
+        other => {
+            return c.log(&format!(
+                "advice {other:?} must be dontneed, willneed, or free"
+            ))
+        }
+    };
+
+    let Some(a) = c.allocs.get_mut(id) else {
+        return c.log(&format!("no allocation {id}"));
+    };
+
+    /*
+     * The allocation remains mapped, but after some kinds of advice the
+     * system is free to discard its contents, so it can no longer be said to
+     * have been touched.
+     */
+    match a.buf.advise(flag) {
+        Ok(()) => {
+            if discards {
+                a.touched = None;
+            }
+            c.log(&format!("advised allocation {id}: {advice}"))
+        }
+        Err(e) => c.log(&e.to_string()),
+    }
+}
+
+/// Copy an allocation into a new one, as a measure of memory bandwidth.
+fn copy(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let [id] = args else {
+        return c.log("usage: copy ID");
+    };
+    let id = match id.parse::<usize>() {
+        Ok(id) => id,
+        Err(e) => return c.log(&e.to_string()),
+    };
+    let Some(src) = c.allocs.iter().find(|a| a.id == id) else {
+        return c.log(&format!("no allocation {id}"));
+    };
+    let Ok(s) = src.buf.bytes() else {
+        return c.log(&format!("allocation {id} is not readable"));
+    };
+
+    /*
+     * Fault in the destination before we start the clock, so that we time
+     * only the copy and not the allocation of fresh pages.
+     */
+    let mut dst = match Region::new(src.size()) {
+        Ok(dst) => dst,
+        Err(e) => return c.log(&format!("could not copy: {e}")),
+    };
+    let d = dst.bytes_mut().expect("new region is writable");
+    d.iter_mut().step_by(region::pagesize()).for_each(|b| *b = 0);
+
+    let start = Instant::now();
+    for (d, s) in d.chunks_mut(grow::FILL_CHUNK).zip(s.chunks(grow::FILL_CHUNK))
+    {
+        d.copy_from_slice(s);
+
+        if c.interrupted() {
+            return c.log("interrupted!");
+        }
+    }
+    let dur = start.elapsed();
+
+    let (sz, pattern) = (src.size(), src.pattern);
+    let new = c.allocs.insert(dst, pattern);
+    c.log(&format!(
+        "copied allocation {id} ({}) to allocation {new} in {} msec, {:.2} GB/s",
+        parse::megabytes(sz as u64),
+        dur.as_millis(),
+        grow::bandwidth(sz, dur) / 1024.0
+    ))
+}
+
+/// Force the dirty pages of a file-backed allocation to be written back.
+fn flush(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let [id] = args else {
+        return c.log("usage: flush ID");
+    };
+    let id = match id.parse::<usize>() {
+        Ok(id) => id,
+        Err(e) => return c.log(&e.to_string()),
+    };
+    let Some(a) = c.allocs.iter().find(|a| a.id == id) else {
+        return c.log(&format!("no allocation {id}"));
+    };
+    if a.buf.path().is_none() {
+        return c.log(&format!("allocation {id} is not backed by a file"));
+    }
+
+    let start = Instant::now();
+    match a.buf.sync() {
+        Ok(()) => {
+            let dur = start.elapsed();
+            c.log(&format!(
+                "flushed allocation {id} ({}) in {} msec, {:.1} MB/s",
+                parse::megabytes(a.size() as u64),
+                dur.as_millis(),
+                grow::bandwidth(a.size(), dur)
+            ))
+        }
+        Err(e) => c.log(&e.to_string()),
+    }
+}
+
+fn protect(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let [id, prot] = args else {
+        return c.log("usage: protect ID none|ro|rw");
+    };
+    let id = match id.parse::<usize>() {
+        Ok(id) => id,
+        Err(e) => return c.log(&e.to_string()),
+    };
+    let prot = match prot.parse::<Protection>() {
+        Ok(prot) => prot,
+        Err(e) => return c.log(&e.to_string()),
+    };
+
+    let Some(a) = c.allocs.get_mut(id) else {
+        return c.log(&format!("no allocation {id}"));
+    };
+
+    /*
+     * Commands that visit every allocation will pass over this one while it
+     * is protected, and those that name it explicitly, e.g., "touch ID", will
+     * refuse it rather than take the fault.
+     */
+    match a.buf.protect(prot) {
+        Ok(()) => c.log(&format!("protected allocation {id}: {prot}")),
+        Err(e) => c.log(&e.to_string()),
+    }
+}
+
+/// Lock or unlock an existing allocation, reporting the change in availrmem
+/// that results.
+fn lock(c: &mut Ctx, args: &[&str], lock: bool) -> Result<()> {
+    let verb = if lock { "lock" } else { "unlock" };
+    let [id] = args else {
+        return c.log(&format!("usage: {verb} ID"));
+    };
+    let id = match id.parse::<usize>() {
+        Ok(id) => id,
+        Err(e) => return c.log(&e.to_string()),
+    };
+    let Some(a) = c.allocs.get_mut(id) else {
+        return c.log(&format!("no allocation {id}"));
+    };
+    if a.buf.locked() == lock {
+        return c.log(&format!("allocation {id} is already {verb}ed"));
+    }
+
+    /*
+     * Locking faults in any pages that are not already resident, so this may
+     * take some time for a large allocation that has been paged out.
+     */
+    let mut src = stats::Source::open().ok();
+    let mut avail = || {
+        src.as_mut().and_then(|s| s.sample().ok()).map(|st| {
+            (st.availrmem * region::pagesize() as u64) as f64 / 1024.0 / 1024.0
+        })
+    };
+    let before = avail();
+    let start = Instant::now();
+    let res = if lock { a.buf.lock() } else { a.buf.unlock() };
+    let dur = start.elapsed();
+    let size = a.size();
+    if let Err(e) = res {
+        return c.log(&e.to_string());
+    }
+    let after = avail();
+
+    c.log(&format!(
+        "{verb}ed allocation {id} ({}) in {} msec{}",
+        parse::megabytes(size as u64),
+        dur.as_millis(),
+        match (before, after) {
+            (Some(b), Some(a)) => {
+                format!("; availrmem {b:.1} MB -> {a:.1} MB")
+            }
+            _ => "".into(),
+        }
+    ))
+}
+
+fn verify(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let id = match args.first().map(|id| id.parse::<usize>()) {
+        Some(Ok(id)) => Some(id),
+        Some(Err(e)) => return c.log(&e.to_string()),
+        None => None,
+    };
+    if let Some(id) = id {
+        match c.allocs.iter().find(|a| a.id == id) {
+            None => return c.log(&format!("no allocation {id}")),
+            Some(a) if a.buf.protection() == Protection::None => {
+                return c.log(&format!("allocation {id} is not readable"));
+            }
+            Some(_) => (),
+        }
+    }
+
+    /*
+     * Regenerate the expected contents a chunk at a time and compare them with
+     * what is actually there, which will fault back in anything that has been
+     * paged out.
+     */
+    let start = Instant::now();
+    let mut expect = vec![0u8; VERIFY_CHUNK];
+    let mut failed = 0;
+    for a in c.allocs.iter().filter(|a| {
+        id.is_none_or(|id| a.id == id) && a.buf.protection() != Protection::None
+    }) {
+        let Some(pattern) = a.pattern else {
+            c.log(&format!("allocation {}: no known pattern to verify", a.id))?;
+            continue;
+        };
+
+        let Ok(buf) = a.buf.bytes() else {
+            continue;
+        };
+
+        let mut bad: usize = 0;
+        let mut first = None;
+        for (i, chunk) in buf.chunks(VERIFY_CHUNK).enumerate() {
+            let e = &mut expect[..chunk.len()];
+            pattern.fill(e, i * VERIFY_CHUNK);
+
+            if chunk != e {
+                for (j, (x, y)) in chunk.iter().zip(e.iter()).enumerate() {
+                    if x != y {
+                        bad += 1;
+                        first.get_or_insert(i * VERIFY_CHUNK + j);
+                    }
+                }
+            }
+
+            if c.interrupted() {
+                return c.log("interrupted!");
+            }
+        }
+
+        if let Some(first) = first {
+            failed += 1;
+            c.log(&format!(
+                "allocation {}: {bad} bytes differ, the first at offset {first}",
+                a.id
+            ))?;
+        } else {
+            c.log(&format!(
+                "allocation {}: {} megabytes ok",
+                a.id,
+                a.size() / 1024 / 1024
+            ))?;
+        }
+    }
+
+    c.log(&format!(
+        "verified in {} msec; {failed} allocations with errors",
+        start.elapsed().as_millis()
+    ))
+}
+
+fn status(c: &mut Ctx) -> Result<()> {
+    let file = c.allocs.file_total();
+    c.log(&format!(
+        "{} allocations, {}{}",
+        c.allocs.len(),
+        parse::megabytes(c.allocs.total() as u64),
+        if file > 0 {
+            format!(" ({} file-backed)", parse::megabytes(file as u64))
+        } else {
+            "".into()
+        }
+    ))?;
+
+    /*
+     * Reservations of availrmem made by the rest of the system are whatever is
+     * missing from physmem that we are not responsible for.
+     */
+    let l = c.fm.ledger();
+    let mb = |b: usize| b as f64 / 1024.0 / 1024.0;
+    c.log(&format!(
+        "fillmem holds {:.1} MB of availrmem \
+        ({:.1} MB in locked allocations, {:.1} MB of its own memory)",
+        mb(l.total()),
+        mb(l.locked),
+        mb(l.own),
+    ))?;
+    let mut src = match stats::Source::open() {
+        Ok(src) => src,
+        Err(e) => return c.log(&format!("status: {e}")),
+    };
+    match src.sample() {
+        Ok(st) => {
+            let pgsz = region::pagesize();
+            let avail = st.availrmem as usize * pgsz;
+            let used = (st.physmem as usize * pgsz).saturating_sub(avail);
+            c.log(&format!(
+                "availrmem {:.1} MB; rest of system holds {:.1} MB",
+                mb(avail),
+                mb(used.saturating_sub(l.total())),
+            ))?;
+            c.log(&format!(
+                "physmem {:.1} MB, freemem {:.1} MB",
+                mb(st.physmem as usize * pgsz),
+                mb(st.freemem as usize * pgsz),
+            ))?;
+        }
+        Err(e) => c.log(&format!("status: {e}"))?,
+    }
+
+    match src.system() {
+        Ok(sys) => {
+            let (days, hours, mins) = (
+                sys.uptime / 86400,
+                sys.uptime / 3600 % 24,
+                sys.uptime / 60 % 60,
+            );
+            let [l1, l5, l15] = sys.load;
+            c.log(&format!(
+                "up {}{hours}:{mins:02}, {} processes, \
+                load average {l1:.2}, {l5:.2}, {l15:.2}",
+                match days {
+                    0 => "".to_string(),
+                    1 => "1 day, ".to_string(),
+                    d => format!("{d} days, "),
+                },
+                sys.nproc,
+            ))
+        }
+        Err(e) => c.log(&format!("status: {e}")),
+    }
+}
+
+/// Read file data through the cache, so that the ARC grows to hold it.
+fn arcfill(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let (path, limit) = match args {
+        [path] => (path, None),
+        [path, sz] => match size(sz) {
+            Ok(sz) => (path, Some(sz)),
+            Err(e) => return c.log(&e.to_string()),
+        },
+        _ => return c.log("usage: arcfill PATH [SIZE]"),
+    };
+
+    let before = arc::size().ok();
+    let start = Instant::now();
+    let p = match fileread::read(path.as_ref(), limit, false, &|| {
+        c.interrupted()
+    }) {
+        Ok(p) => p,
+        Err(e) => return c.log(&format!("arcfill: {e:#}")),
+    };
+    let dur = start.elapsed();
+    let after = arc::size().ok();
+
+    let mb = |v: u64| format!("{:.1} MB", v as f64 / 1024.0 / 1024.0);
+    c.log(&format!(
+        "{}read {} from {} files in {} msec, {:.1} MB/s{}; ARC size {}",
+        if p.interrupted { "interrupted! " } else { "" },
+        parse::megabytes(p.bytes as u64),
+        p.files,
+        dur.as_millis(),
+        grow::bandwidth(p.bytes, dur),
+        if p.skipped > 0 {
+            format!(" ({} skipped)", p.skipped)
+        } else {
+            "".into()
+        },
+        match (before, after) {
+            (Some(b), Some(a)) => format!("{} -> {}", mb(b), mb(a)),
+            _ => "unknown".into(),
+        }
+    ))
+}
+
+/// Read file data with direct I/O, which should not grow the ARC.
+fn dread(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let [path, sz] = args else {
+        return c.log("usage: dread PATH SIZE");
+    };
+    let limit = match size(sz) {
+        Ok(sz) => sz,
+        Err(e) => return c.log(&e.to_string()),
+    };
+
+    let vm0 = stats::Source::open().and_then(|mut s| s.sample()).ok();
+    let start = Instant::now();
+    let p = match fileread::read(path.as_ref(), Some(limit), true, &|| {
+        c.interrupted()
+    }) {
+        Ok(p) => p,
+        Err(e) => return c.log(&format!("dread: {e:#}")),
+    };
+    let dur = start.elapsed();
+    let vm1 = stats::Source::open().and_then(|mut s| s.sample()).ok();
+
+    c.log(&format!(
+        "{}read {} from {} files in {} msec, {:.1} MB/s{}",
+        if p.interrupted { "interrupted! " } else { "" },
+        parse::megabytes(p.bytes as u64),
+        p.files,
+        dur.as_millis(),
+        grow::bandwidth(p.bytes, dur),
+        if p.skipped > 0 {
+            format!(" ({} skipped)", p.skipped)
+        } else {
+            "".into()
+        },
+    ))?;
+    if p.cached > 0 {
+        c.log(&format!(
+            "{} files do not support direct I/O and were read through the cache",
+            p.cached
+        ))?;
+    }
+
+    /*
+     * Compare freemem on either side, which for cached reads would fall by
+     * about as much as we read.
+     */
+    if let (Some(s0), Some(s1)) = (vm0, vm1) {
+        let pg = region::pagesize() as f64 / 1024.0 / 1024.0;
+        c.log(&format!(
+            "freemem {:.1} MB -> {:.1} MB",
+            s0.freemem as f64 * pg,
+            s1.freemem as f64 * pg
+        ))?;
+    }
+    Ok(())
+}
+
+/// Print the value and type of each named statistic matching a pattern.
+fn kstat_query(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let [pattern] = args else {
+        return c.log("usage: kstat MODULE:INSTANCE:NAME[:STATISTIC]");
+    };
+
+    let stats = match kstat::query(pattern) {
+        Ok(stats) => stats,
+        Err(e) => return c.log(&format!("kstat: {e}")),
+    };
+    if stats.is_empty() {
+        return c.log(&format!("no statistics match {pattern:?}"));
+    }
+    for s in stats {
+        c.log(&format!(
+            "{}:{}:{}:{} {} ({})",
+            s.module,
+            s.instance,
+            s.name,
+            s.statistic,
+            s.value,
+            s.value.type_name()
+        ))?;
+    }
+    Ok(())
+}
+
+fn kstat_dump(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    /*
+     * The output file may be given either as "> file", ">file", or "--out
+     * file".
+     */
+    let mut pattern = None;
+    let mut out = None;
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        if *a == ">" || *a == "--out" {
+            out = args.next().copied();
+        } else if let Some(f) = a.strip_prefix('>') {
+            out = Some(f);
+        } else {
+            pattern = Some(*a);
+        }
+    }
+
+    let Some(out) = out else {
+        return c.log("dump to which file? (use \"> file\")");
+    };
+
+    let start = Instant::now();
+    let res = std::fs::File::create(out)
+        .map_err(kstat::KstatError::Io)
+        .and_then(|f| {
+            let mut w = std::io::BufWriter::new(f);
+            kstat::dump(&mut w, pattern)
+        });
+    match res {
+        Ok(n) => c.log(&format!(
+            "wrote {n} kstats to {out} in {} msec",
+            start.elapsed().as_millis()
+        )),
+        Err(e) => c.log(&format!("kstat dump: {e}")),
+    }
+}
+
+fn snapshot(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    match args.first() {
+        None => match &c.snapshots {
+            Some(s) => c.log(&format!(
+                "snapshot every {}s to {}",
+                s.interval.as_secs_f64(),
+                s.dir.display()
+            )),
+            None => c.log("no snapshots scheduled"),
+        },
+        Some(&"stop") => {
+            if c.snapshots.take().is_some() {
+                c.log("snapshots stopped")
+            } else {
+                c.log("no snapshots scheduled")
+            }
+        }
+        Some(&"every") => {
+            let (Some(period), Some(&"--out"), Some(dir)) =
+                (args.get(1), args.get(2), args.get(3))
+            else {
+                return c.log("usage: snapshot every PERIOD --out DIR");
+            };
+
+            /*
+             * Replacing any existing schedule stops the previous snapshot
+             * thread.
+             */
+            c.snapshots = None;
+            match parse::duration(period).and_then(|p| {
+                snapshot::Snapshots::start(
+                    Arc::clone(c.ed),
+                    Arc::clone(c.fm),
+                    p,
+                    dir.into(),
+                )
+            }) {
+                Ok(s) => {
+                    c.snapshots = Some(s);
+                    Ok(())
+                }
+                Err(e) => c.log(&format!("snapshot: {e}")),
+            }
+        }
+        Some(other) => c.log(&format!("snapshot {other:?} not understood")),
+    }
+}
+
+/// List the lgroups that contain memory, and how much of it is free.
+fn lgroups(c: &mut Ctx) -> Result<()> {
+    let lgroups = match stats::Source::open().and_then(|mut s| s.lgroups()) {
+        Ok(lgroups) => lgroups,
+        Err(e) => return c.log(&format!("lgrp: {e}")),
+    };
+
+    for l in lgroups {
+        c.log(&format!(
+            "lgrp {}: {:.1} MB installed, {:.1} MB free",
+            l.id,
+            l.installed as f64 / 1024.0 / 1024.0,
+            l.free as f64 / 1024.0 / 1024.0,
+        ))?;
+    }
+    Ok(())
+}
+
+fn hold(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    match args.first() {
+        None => match &c.hold {
+            Some(h) => {
+                let s = h.state();
+                c.log(&format!(
+                    "holding {} every {}s since {}: {:.1} MB resident after \
+                    {} passes, {:.1} MB faulted back in",
+                    parse::megabytes(h.size as u64),
+                    h.interval.as_secs_f64(),
+                    h.since.format("%H:%M:%S%.3fZ"),
+                    s.resident as f64 / 1024.0 / 1024.0,
+                    s.passes,
+                    s.refaulted as f64 / 1024.0 / 1024.0,
+                ))
+            }
+            None => c.log("not holding"),
+        },
+        Some(&"stop") => {
+            if let Some(h) = c.hold.take() {
+                c.log(&format!(
+                    "released hold of {}",
+                    parse::megabytes(h.size as u64)
+                ))
+            } else {
+                c.log("not holding")
+            }
+        }
+        Some(sz) => {
+            let sz = match size(sz) {
+                Ok(sz) => sz,
+                Err(e) => return c.log(&e.to_string()),
+            };
+            let interval = match (args.get(1), args.get(2)) {
+                (None, _) => Ok(std::time::Duration::from_secs(1)),
+                (Some(&"every"), Some(p)) => parse::duration(p),
+                _ => return c.log("usage: hold SIZE [every PERIOD]"),
+            };
+
+            /*
+             * Replacing an existing hold releases the memory it held.
+             */
+            c.hold = None;
+            match interval
+                .and_then(|i| hold::Hold::start(Arc::clone(c.ed), sz, i))
+            {
+                Ok(h) => {
+                    c.hold = Some(h);
+                    c.log(&format!("holding {}", parse::megabytes(sz as u64)))
+                }
+                Err(e) => c.log(&format!("hold: {e}")),
+            }
+        }
+    }
+}
+
+fn stack(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    match args {
+        [] => match &c.stack {
+            Some(s) => c.log(&format!(
+                "holding {} of stack since {}",
+                parse::megabytes(s.size as u64),
+                s.since.format("%H:%M:%S%.3fZ"),
+            )),
+            None => c.log("no stack"),
+        },
+        ["stop"] => match c.stack.take() {
+            Some(s) => c.log(&format!(
+                "released {} of stack",
+                parse::megabytes(s.size as u64)
+            )),
+            None => c.log("no stack"),
+        },
+        [sz] => {
+            let sz = match size(sz) {
+                Ok(sz) => sz,
+                Err(e) => return c.log(&e.to_string()),
+            };
+
+            /*
+             * Replacing an existing stack releases the memory it held.
+             */
+            c.stack = None;
+            match stack::Stack::start(sz) {
+                Ok(s) => {
+                    let msg = format!(
+                        "touched {} of stack in {} msec, {:.1} MB/s",
+                        parse::megabytes(sz as u64),
+                        s.dur.as_millis(),
+                        grow::bandwidth(sz, s.dur)
+                    );
+                    c.stack = Some(s);
+                    c.log(&msg)
+                }
+                Err(e) => c.log(&format!("stack: {e}")),
+            }
+        }
+        _ => c.log("usage: stack [SIZE | stop]"),
+    }
+}
+
+/// Show how much memory the kernel's object caches are using, and which caches
+/// are using the most.
+fn kmem_caches(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let top = match args {
+        [] => 10,
+        [n] => match n.parse::<usize>() {
+            Ok(n) => n,
+            Err(e) => return c.log(&e.to_string()),
+        },
+        _ => return c.log("usage: kmem [COUNT]"),
+    };
+
+    let mut caches = match kmem::caches() {
+        Ok(caches) => caches,
+        Err(e) => return c.log(&format!("kmem: {e}")),
+    };
+    caches.sort_by_key(|k| std::cmp::Reverse(k.memory));
+
+    let mb = |v: u64| v as f64 / 1024.0 / 1024.0;
+    c.log(&format!(
+        "{} caches using {:.1} MB",
+        caches.len(),
+        mb(caches.iter().map(|k| k.memory).sum())
+    ))?;
+    for k in caches.iter().take(top) {
+        c.log(&format!(
+            "{:<32} {:>10.1} MB {:>12} bufs of {} bytes in use",
+            k.name,
+            mb(k.memory),
+            k.inuse,
+            k.buf_size
+        ))?;
+    }
+    Ok(())
+}
+
+/// Show the I/O activity on each disk over a short period.
+fn disks(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let period = match args {
+        [] => Duration::from_secs(1),
+        [p] => match parse::duration(p) {
+            Ok(p) if !p.is_zero() => p,
+            Ok(_) => return c.log("period must be non-zero"),
+            Err(e) => return c.log(&e.to_string()),
+        },
+        _ => return c.log("usage: disks [PERIOD]"),
+    };
+
+    let before = match disk::disks() {
+        Ok(d) => d,
+        Err(e) => return c.log(&format!("disks: {e}")),
+    };
+    std::thread::sleep(period);
+    let after = match disk::disks() {
+        Ok(d) => d,
+        Err(e) => return c.log(&format!("disks: {e}")),
+    };
+
+    if after.is_empty() {
+        return c.log("no disks");
+    }
+
+    c.log(&format!(
+        "{:<12} {:>8} {:>8} {:>10} {:>10} {:>5} {:>5}",
+        "device", "r/s", "w/s", "kr/s", "kw/s", "%w", "%b"
+    ))?;
+    for d in &after {
+        let Some(r) =
+            before.iter().find(|b| b.name == d.name).and_then(|b| d.since(b))
+        else {
+            continue;
+        };
+
+        c.log(&format!(
+            "{:<12} {:>8.1} {:>8.1} {:>10.1} {:>10.1} {:>5.0} {:>5.0}",
+            d.name, r.reads, r.writes, r.kread, r.kwritten, r.wait, r.busy
+        ))?;
+    }
+    Ok(())
+}
+
+/// Show the memory used by our zone, and any caps that apply to it.
+fn zone(c: &mut Ctx) -> Result<()> {
+    let z = match stats::Source::open().and_then(|mut src| src.zone()) {
+        Ok(z) => z,
+        Err(e) => return c.log(&format!("zone: {e}")),
+    };
+    let mb = |v: u64| v as f64 / 1024.0 / 1024.0;
+    let cap = |v: Option<u64>| match v {
+        None => "no cap".to_string(),
+        Some(v) => format!("cap {:.1} MB", mb(v)),
+    };
+
+    c.log(&format!("zone {} (id {})", z.name, z.id))?;
+    c.log(&format!("rss {:.1} MB, {}", mb(z.rss), cap(z.physcap)))?;
+    c.log(&format!("swap {:.1} MB, {}", mb(z.swap), cap(z.swapcap)))?;
+    c.log(&format!(
+        "over cap {} times, {:.1} MB paged out",
+        z.nover,
+        mb(z.pagedout)
+    ))
+}
+
+/// Show the swap accounting in the style of swap(8) -s, then each device.
+fn swap(c: &mut Ctx) -> Result<()> {
+    let s = match stats::Source::open().and_then(|mut src| src.swap()) {
+        Ok(s) => s,
+        Err(e) => return c.log(&format!("swap: {e}")),
+    };
+    let pgsz = region::pagesize() as u64;
+    let mb = |pages: u64| (pages * pgsz) as f64 / 1024.0 / 1024.0;
+
+    c.log(&format!(
+        "{:.1} MB allocated + {:.1} MB reserved = {:.1} MB used, \
+        {:.1} MB available",
+        mb(s.allocated()),
+        mb(s.reserved()),
+        mb(s.resv),
+        mb(s.available()),
+    ))?;
+
+    if s.devices.is_empty() {
+        return c.log("no swap devices");
+    }
+    for d in &s.devices {
+        c.log(&format!(
+            "{}: {:.1} MB, {:.1} MB free{}",
+            d.path,
+            mb(d.pages),
+            mb(d.free),
+            if d.deleting { " (being deleted)" } else { "" },
+        ))?;
+    }
+    Ok(())
+}
+
+/// Lock the entire process in memory, so that neither our own text and data
+/// nor any allocation can be paged out while an experiment runs.
+fn lockall(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let on = match args {
+        [] => {
+            return c.log(if c.locked_all {
+                "all memory locked"
+            } else {
+                "memory not locked"
+            });
+        }
+        ["on"] => true,
+        ["off"] => false,
+        _ => return c.log("usage: lockall [on | off]"),
+    };
+
+    let start = Instant::now();
+    let before = stats::availrmem();
+    if let Err(e) = region::lock_all(on) {
+        return c.log(&e.to_string());
+    }
+    let dur = start.elapsed();
+    c.locked_all = on;
+
+    /*
+     * Whatever availrmem we lost beyond the allocations that were not already
+     * locked is our own text, data, and stacks.
+     */
+    if on {
+        let drop = before
+            .zip(stats::availrmem())
+            .map_or(0, |(b, a)| b.saturating_sub(a) as usize);
+        let unlocked = c
+            .allocs
+            .iter()
+            .filter(|a| !a.buf.locked())
+            .map(|a| a.size())
+            .sum::<usize>();
+        c.fm.locked_all(drop.saturating_sub(unlocked), true);
+    } else {
+        c.fm.unlocked_all();
+    }
+
+    /*
+     * Unlocking everything also unlocks any allocation that was locked on its
+     * own, so we must stop saying that it is.
+     */
+    let mut released = 0;
+    if !on {
+        for a in c.allocs.iter_mut().filter(|a| a.buf.locked()) {
+            a.buf.unlocked_all();
+            released += 1;
+        }
+    }
+
+    if on {
+        c.log(&format!(
+            "locked all memory in {} msec; new allocations will be locked too",
+            dur.as_millis()
+        ))
+    } else {
+        c.log(&format!(
+            "unlocked all memory in {} msec{}",
+            dur.as_millis(),
+            match released {
+                0 => "".to_string(),
+                n => format!(", including {n} allocations locked on their own"),
+            }
+        ))
+    }
+}
+
+fn ramp(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    match args {
+        [] => match &c.ramp {
+            Some(r) => c.log(&format!(
+                "ramping by {} every {}s since {} (job {}): {} steps{}",
+                r.spec.describe(),
+                r.interval.as_secs_f64(),
+                r.since.format("%H:%M:%S%.3fZ"),
+                r.job,
+                r.steps(),
+                r.count.map(|n| format!(" of {n}")).unwrap_or_default(),
+            )),
+            None => c.log("not ramping"),
+        },
+        ["stop"] => match c.ramp.take() {
+            Some(r) => c.log(&format!(
+                "stopped ramp (job {}) after {} steps",
+                r.job,
+                r.steps()
+            )),
+            None => c.log("not ramping"),
+        },
+        [sz, "every", period, rest @ ..] if rest.len() <= 1 => {
+            let spec = match grow::Spec::parse(&[sz]) {
+                Ok(spec) => spec,
+                Err(e) => return c.log(&e.to_string()),
+            };
+            let interval = match parse::duration(period) {
+                Ok(i) => i,
+                Err(e) => return c.log(&e.to_string()),
+            };
+            let count = match rest.first().map(|n| n.parse::<usize>()) {
+                None => None,
+                Some(Ok(n)) if n > 0 => Some(n),
+                Some(Ok(_)) => return c.log("ramp at least one step"),
+                Some(Err(e)) => return c.log(&e.to_string()),
+            };
+
+            /*
+             * Replacing an existing ramp stops it, but leaves the memory it
+             * has allocated so far.
+             */
+            c.ramp = None;
+            c.last_job += 1;
+            let job = c.last_job;
+            let describe = spec.describe();
+            match ramp::Ramp::start(job, spec, interval, count, c.tx.clone()) {
+                Ok(r) => {
+                    c.ramp = Some(r);
+                    c.log(&format!(
+                        "started job {job}: ramp by {describe} every {}s",
+                        interval.as_secs_f64()
+                    ))
+                }
+                Err(e) => c.log(&format!("ramp: {e}")),
+            }
+        }
+        _ => c.log("usage: ramp [SIZE every PERIOD [COUNT] | stop]"),
+    }
+}
+
+fn oscillate(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    /*
+     * In the foreground, we oscillate until interrupted.  A trailing "&" runs
+     * the oscillation as a background job instead, until it is stopped.
+     */
+    let (args, background) = match args.split_last() {
+        Some((&"&", rest)) => (rest, true),
+        _ => (args, false),
+    };
+
+    let (sz, period) = match args {
+        [] if !background => {
+            return match &c.oscillation {
+                Some(o) => c.log(&format!(
+                    "oscillating by {} every {}s since {} (job {}): {} cycles",
+                    parse::megabytes(o.size as u64),
+                    o.period.as_secs_f64(),
+                    o.since.format("%H:%M:%S%.3fZ"),
+                    o.job,
+                    o.cycles(),
+                )),
+                None => c.log("not oscillating"),
+            };
+        }
+        ["stop"] if !background => {
+            return match c.oscillation.take() {
+                Some(o) => c.log(&format!(
+                    "stopped oscillation (job {}) after {} cycles",
+                    o.job,
+                    o.cycles()
+                )),
+                None => c.log("not oscillating"),
+            };
+        }
+        [sz, "every", period] => (sz, period),
+        _ => return c.log("usage: oscillate [SIZE every PERIOD [&] | stop]"),
+    };
+    let sz = match size(sz) {
+        Ok(sz) => sz,
+        Err(e) => return c.log(&e.to_string()),
+    };
+    let period = match parse::duration(period) {
+        Ok(p) => p,
+        Err(e) => return c.log(&e.to_string()),
+    };
+
+    if background {
+        c.oscillation = None;
+        c.last_job += 1;
+        let job = c.last_job;
+        return match oscillate::Oscillation::start(
+            Arc::clone(c.ed),
+            job,
+            sz,
+            period,
+        ) {
+            Ok(o) => {
+                c.oscillation = Some(o);
+                c.log(&format!(
+                    "started job {job}: oscillate by {} every {}s",
+                    parse::megabytes(sz as u64),
+                    period.as_secs_f64()
+                ))
+            }
+            Err(e) => c.log(&format!("oscillate: {e}")),
+        };
+    }
+
+    let start = Instant::now();
+    let cycles = Default::default();
+    match oscillate::run(c.ed, "", sz, period, &|| c.interrupted(), &cycles) {
+        Ok(n) => c.log(&format!(
+            "interrupted! oscillated for {n} cycles in {} msec",
+            start.elapsed().as_millis()
+        )),
+        Err(e) => c.log(&format!("oscillate: {e}")),
+    }
+}
+
+fn fork(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    match args.first() {
+        None => {
+            if c.children.is_empty() {
+                return c.log("no children");
+            }
+            for ch in &c.children {
+                c.log(&format!(
+                    "{} children{}: pids {}",
+                    ch.pids.len(),
+                    if ch.write { " (written)" } else { "" },
+                    ch.pids
+                        .iter()
+                        .map(|p| p.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))?;
+            }
+            Ok(())
+        }
+        Some(&"stop") => {
+            let n = c.children.iter().map(|ch| ch.pids.len()).sum::<usize>();
+            c.children.clear();
+            c.log(&format!("released {n} children"))
+        }
+        Some(n) => {
+            let n = match n.parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                Ok(_) => return c.log("fork at least one child"),
+                Err(e) => return c.log(&e.to_string()),
+            };
+            let write = match args.get(1) {
+                None => false,
+                Some(&"write") => true,
+                Some(other) => {
+                    return c.log(&format!("fork option {other:?} unknown"))
+                }
+            };
+
+            /*
+             * If the children write to every page, each of them ends up with
+             * a private copy of everything we have allocated.  Shared mappings
+             * are not copied, and a store by a child would change our data and
+             * the file behind it, so leave those alone.
+             */
+            let regions = c
+                .allocs
+                .iter_mut()
+                .filter(|a| {
+                    a.buf.protection() == Protection::ReadWrite
+                        && !a.buf.shared()
+                })
+                .map(|a| (a.buf.as_mut_ptr(), a.size()))
+                .collect::<Vec<_>>();
+            let start = Instant::now();
+            match forks::Children::fork(n, &regions, write, &|| c.interrupted())
+            {
+                Ok(Some(ch)) => {
+                    let msg = if write {
+                        format!(
+                            "forked {n} children, which copied {} megabytes \
+                            each in {} msec",
+                            regions.iter().map(|r| r.1).sum::<usize>()
+                                / 1024
+                                / 1024,
+                            start.elapsed().as_millis()
+                        )
+                    } else {
+                        format!("forked {n} children")
+                    };
+                    c.children.push(ch);
+                    c.log(&msg)
+                }
+                Ok(None) => c.log("interrupted!"),
+                Err(e) => c.log(&format!("fork: {e}")),
+            }
+        }
+    }
+}
+
+fn spawn(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    match (args.first(), args.get(1)) {
+        (None, _) => {
+            if c.workers.is_empty() {
+                return c.log("no workers");
+            }
+            for w in &c.workers {
+                c.log(&format!(
+                    "worker pid {}: {}",
+                    w.pid(),
+                    parse::megabytes(w.size as u64)
+                ))?;
+            }
+            Ok(())
+        }
+        (Some(&"stop"), None) => {
+            let n = c.workers.len();
+            c.workers.clear();
+            c.log(&format!("stopped {n} workers"))
+        }
+        (Some(n), Some(sz)) => {
+            let n = match n.parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                Ok(_) => return c.log("spawn at least one worker"),
+                Err(e) => return c.log(&e.to_string()),
+            };
+            let sz = match size(sz) {
+                Ok(sz) => sz,
+                Err(e) => return c.log(&e.to_string()),
+            };
+
+            /*
+             * Start all of the workers growing before we wait for any of them,
+             * so that they allocate in parallel.
+             */
+            let start = Instant::now();
+            let mut new = Vec::new();
+            for _ in 0..n {
+                let res = worker::Worker::spawn().and_then(|mut w| {
+                    w.send(&format!("grow {sz}")).map(|()| w)
+                });
+                match res {
+                    Ok(w) => new.push(w),
+                    Err(e) => return c.log(&format!("spawn: {e}")),
+                }
+            }
+            for w in new.iter_mut() {
+                if let Err(e) = w.reply() {
+                    return c.log(&format!("spawn: {e}"));
+                }
+                w.size += sz;
+            }
+
+            c.workers.extend(new);
+            c.log(&format!(
+                "spawned {n} workers of {} each in {} msec",
+                parse::megabytes(sz as u64),
+                start.elapsed().as_millis()
+            ))
+        }
+        _ => c.log("usage: spawn [N SIZE | stop]"),
+    }
+}
+
+/// Show or change the columns included in the periodic statistics line.
+fn stats_columns(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    let mut cols = c.fm.columns();
+
+    match args {
+        [] => {
+            c.log(&format!("showing: {}", cols.join(" ")))?;
+            for col in stats::COLUMNS {
+                c.log(&format!("{:>6}  {}", col.name, col.desc))?;
+            }
+            Ok(())
+        }
+        ["add", name] => {
+            let Some(col) = stats::column(name) else {
+                return c.log(&format!("no column {name:?}"));
+            };
+            if cols.contains(&col.name) {
+                return c.log(&format!("column {name:?} already shown"));
+            }
+            cols.push(col.name);
+            c.fm.set_columns(cols);
+            c.log(&format!("added column {name:?}"))
+        }
+        ["del", name] => {
+            if !cols.contains(name) {
+                return c.log(&format!("column {name:?} not shown"));
+            }
+            cols.retain(|n| n != name);
+            c.fm.set_columns(cols);
+            c.log(&format!("removed column {name:?}"))
+        }
+        _ => c.log("usage: stats [add NAME | del NAME]"),
+    }
+}
+
+/// Show or change how often a line of statistics is emitted.
+fn interval(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    match args {
+        [] => {
+            let i = c.fm.interval();
+            c.log(&format!("statistics every {} msec", i.as_millis()))
+        }
+        [p] => match parse::duration(p) {
+            Ok(p) if p.is_zero() => c.log("interval must be non-zero"),
+            Ok(p) => {
+                c.fm.set_interval(p);
+                c.log(&format!("statistics now every {} msec", p.as_millis()))
+            }
+            Err(e) => c.log(&e.to_string()),
+        },
+        _ => c.log("usage: interval [PERIOD]"),
+    }
+}
+
+fn set(c: &mut Ctx, args: &[&str]) -> Result<()> {
+    match (args.first(), args.get(1)) {
+        (None, _) => {
+            for l in c.settings.describe() {
+                c.log(&l)?;
+            }
+            Ok(())
+        }
+        (Some(&"worker-priority"), Some(v)) => match v.parse::<Priority>() {
+            /*
+             * Growing and touching memory is performed on this thread, so
+             * adjust our own LWP.
+             */
+            Ok(p) => match sys::set_lwp_nice(p.nice()) {
+                Ok(()) => {
+                    c.settings.worker_priority = p;
+                    c.log(&format!("worker-priority now {p}"))
+                }
+                Err(e) => c.log(&e.to_string()),
+            },
+            Err(e) => c.log(&e.to_string()),
+        },
+        (Some(&"vmem-cap"), Some(v)) => {
+            let cap = if *v == "none" {
+                Ok(None)
+            } else {
+                parse::size(v, &parse::SizeContext::system()).map(Some)
+            };
+
+            match cap.and_then(|cap| sys::set_vmem_cap(cap).map(|()| cap)) {
+                Ok(cap) => {
+                    c.settings.vmem_cap = cap;
+                    c.log(&format!("vmem-cap now {v}"))
+                }
+                Err(e) => c.log(&e.to_string()),
+            }
+        }
+        (Some(&"quiet"), Some(v)) => {
+            let quiet = match *v {
+                "on" => true,
+                "off" => false,
+                other => {
+                    return c.log(&format!("quiet {other:?} must be on or off"))
+                }
+            };
+            c.settings.quiet = quiet;
+            c.ed.set_quiet(quiet);
+            c.log(&format!("quiet now {v}"))
+        }
+        (Some(&"faults"), Some(v)) => match fault::arm(v) {
+            Ok(()) => c.log(&format!("faults now {}", fault::armed())),
+            Err(e) => c.log(&e.to_string()),
+        },
+        (Some(name), None) => c.log(&format!("set {name} to what?")),
+        (Some(other), Some(_)) => c.log(&format!("unknown setting {other:?}")),
+    }
+}
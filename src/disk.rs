@@ -0,0 +1,102 @@
+/*
+ * Disk I/O statistics, in the style of iostat(8).  Each disk driver instance
+ * has a KSTAT_TYPE_IO kstat of class "disk", with cumulative counts of bytes
+ * and operations and the time spent with I/O waiting or in progress.  Once
+ * memory runs short, paging to and from swap shows up here first.
+ */
+
+use anyhow::Result;
+
+use crate::kstat::{self, consts::*, KstatDataIo, KstatWrapper};
+
+/// The cumulative I/O counters of one disk at a point in time.
+pub struct Disk {
+    pub name: String,
+    pub io: KstatDataIo,
+    /// When the counters were read, in nanoseconds of gethrtime(3C).
+    pub snaptime: u64,
+}
+
+/// Activity on a disk between two readings.
+pub struct Rates {
+    pub reads: f64,
+    pub writes: f64,
+    /// Kilobytes per second.
+    pub kread: f64,
+    pub kwritten: f64,
+    /// The percentage of time with I/O waiting to be issued.
+    pub wait: f64,
+    /// The percentage of time with I/O in progress.
+    pub busy: f64,
+}
+
+impl Disk {
+    /// Work out the rates since an earlier reading of the same disk, or None
+    /// if no time has passed.
+    pub fn since(&self, earlier: &Disk) -> Option<Rates> {
+        let nsec = self.snaptime.checked_sub(earlier.snaptime)?;
+        let rate = |l: u64, e: u64| kstat::rate(l.saturating_sub(e), nsec);
+        let pct = |l: i64, e: i64| {
+            rate(l.max(0) as u64, e.max(0) as u64).map(|r| r / 1e7)
+        };
+        let (io, eio) = (&self.io, &earlier.io);
+
+        Some(Rates {
+            reads: rate(io.reads.into(), eio.reads.into())?,
+            writes: rate(io.writes.into(), eio.writes.into())?,
+            kread: rate(io.nread, eio.nread)? / 1024.0,
+            kwritten: rate(io.nwritten, eio.nwritten)? / 1024.0,
+            wait: pct(io.wtime, eio.wtime)?,
+            busy: pct(io.rtime, eio.rtime)?,
+        })
+    }
+}
+
+/// Read the I/O counters of every disk.  Partitions and slices have kstats of
+/// their own, in class "partition", which are not included.
+pub fn disks() -> Result<Vec<Disk>> {
+    let ks = KstatWrapper::open()?;
+    let mut out = Vec::new();
+
+    for k in ks.walk_class(CLASS_DISK) {
+        /*
+         * A device may be detached between walking the chain and reading it.
+         */
+        if k.read().is_err() {
+            continue;
+        }
+
+        if let Some(io) = k.io() {
+            out.push(Disk {
+                name: k.name().to_string_lossy().into_owned(),
+                io,
+                snaptime: k.snaptime(),
+            });
+        }
+    }
+
+    Ok(out)
+}
+
+/// Read the I/O counters of every ZFS pool.  Each imported pool has a kstat
+/// of class "disk" in the "zfs" module, named for the pool, that counts the
+/// logical I/O issued to the pool as a whole.
+pub fn pools(ks: &KstatWrapper) -> Vec<Disk> {
+    let mut out = Vec::new();
+
+    for k in ks.walk_class(CLASS_DISK) {
+        if k.module() != MODULE_ZFS || k.read().is_err() {
+            continue;
+        }
+
+        if let Some(io) = k.io() {
+            out.push(Disk {
+                name: k.name().to_string_lossy().into_owned(),
+                io,
+                snaptime: k.snaptime(),
+            });
+        }
+    }
+
+    out
+}
@@ -0,0 +1,88 @@
+/*
+ * Fault injection, so that error handling paths can be exercised on demand.
+ * Faults are armed at startup through the FILLMEM_FAULTS environment variable,
+ * or at runtime with "set faults", using a comma-separated list of fault names.
+ */
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use anyhow::{bail, Result};
+
+use crate::kstat::{self, KstatFault};
+
+static ARMED: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// kstat_read(3KSTAT) fails.
+    KstatRead,
+    /// kstat_chain_update(3KSTAT) fails.
+    ChainUpdate,
+    /// Writes to the terminal fail.
+    TermWrite,
+}
+
+const ALL: [Fault; 3] =
+    [Fault::KstatRead, Fault::ChainUpdate, Fault::TermWrite];
+
+impl Fault {
+    fn bit(&self) -> u32 {
+        1 << (*self as u32)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fault::KstatRead => "kstat-read",
+            Fault::ChainUpdate => "chain-update",
+            Fault::TermWrite => "term-write",
+        }
+    }
+}
+
+/// Returns true if the specified fault has been armed, in which case the caller
+/// should fail the operation.
+pub fn injected(f: Fault) -> bool {
+    ARMED.load(Ordering::Relaxed) & f.bit() != 0
+}
+
+/// Arm exactly the faults in the comma-separated list, disarming any others.
+/// The list may also be "none" to disarm everything.
+pub fn arm(list: &str) -> Result<()> {
+    let mut bits = 0;
+
+    if list != "none" {
+        for name in list.split(',') {
+            let Some(f) = ALL.iter().find(|f| f.name() == name) else {
+                bail!(
+                    "unknown fault {name:?} (valid faults: {})",
+                    ALL.iter().map(|f| f.name()).collect::<Vec<_>>().join(", ")
+                );
+            };
+            bits |= f.bit();
+        }
+    }
+
+    ARMED.store(bits, Ordering::Relaxed);
+
+    /*
+     * The kstat library keeps its own record of the faults it can inject.
+     */
+    kstat::inject_fault(KstatFault::Read, injected(Fault::KstatRead));
+    kstat::inject_fault(KstatFault::ChainUpdate, injected(Fault::ChainUpdate));
+    Ok(())
+}
+
+/// Describe the armed faults as a list suitable for passing back to arm().
+pub fn armed() -> String {
+    let names = ALL
+        .iter()
+        .filter(|f| injected(**f))
+        .map(|f| f.name())
+        .collect::<Vec<_>>();
+
+    if names.is_empty() {
+        "none".into()
+    } else {
+        names.join(",")
+    }
+}
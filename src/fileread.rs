@@ -0,0 +1,99 @@
+/*
+ * Reading file data, either through the cache so that it grows the ARC, or
+ * with direct I/O so that it generates I/O without doing so.
+ */
+
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::region::Region;
+use crate::sys;
+
+/// Read this many bytes at a time, checking for an interrupt in between.
+const READ_CHUNK: usize = 1024 * 1024;
+
+/// What we managed to read.
+#[derive(Default)]
+pub struct Progress {
+    pub files: usize,
+    pub bytes: usize,
+    /// Files and directories that we could not read.
+    pub skipped: usize,
+    /// Files that we read through the cache even though we asked for direct
+    /// I/O, because the file system does not support it.
+    pub cached: usize,
+    pub interrupted: bool,
+}
+
+/// Read the file at path, or every file beneath it if it is a directory, until
+/// we have read limit bytes or run out of files.  If direct is true, try to
+/// bypass the cache.
+pub fn read(
+    path: &Path,
+    limit: Option<usize>,
+    direct: bool,
+    interrupted: &dyn Fn() -> bool,
+) -> Result<Progress> {
+    std::fs::metadata(path)
+        .with_context(|| format!("could not read {path:?}"))?;
+
+    let mut p = Progress::default();
+    /*
+     * Direct I/O generally requires an aligned buffer, so use a mapping
+     * rather than the heap.
+     */
+    let mut buf = Region::new(READ_CHUNK)?;
+    let mut todo: Vec<PathBuf> = vec![path.into()];
+    while let Some(path) = todo.pop() {
+        let Ok(md) = std::fs::symlink_metadata(&path) else {
+            p.skipped += 1;
+            continue;
+        };
+
+        if md.is_dir() {
+            match std::fs::read_dir(&path) {
+                Ok(rd) => todo.extend(rd.flatten().map(|e| e.path())),
+                Err(_) => p.skipped += 1,
+            }
+            continue;
+        } else if !md.is_file() {
+            continue;
+        }
+
+        let Ok(mut f) = File::open(&path) else {
+            p.skipped += 1;
+            continue;
+        };
+        p.files += 1;
+        if direct && sys::directio_on(f.as_raw_fd()).is_err() {
+            p.cached += 1;
+        }
+
+        loop {
+            if interrupted() {
+                p.interrupted = true;
+                return Ok(p);
+            }
+
+            let want = match limit {
+                Some(limit) if p.bytes >= limit => return Ok(p),
+                Some(limit) => buf.len().min(limit - p.bytes),
+                None => buf.len(),
+            };
+            match f.read(&mut buf.bytes_mut()?[..want]) {
+                Ok(0) => break,
+                Ok(n) => p.bytes += n,
+                Err(_) => {
+                    p.skipped += 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(p)
+}
@@ -0,0 +1,166 @@
+/*
+ * Child processes that share our allocations copy-on-write.  After fork(2),
+ * every anonymous page is shared between parent and child until one of them
+ * writes to it, at which point the page must be duplicated.  The children do
+ * nothing but optionally write to each page, and then wait to be released.
+ *
+ * The child side of fork(2) in a multi-threaded process may only do things
+ * that are async-signal-safe, so everything the children need is prepared
+ * before forking, and they use only raw system calls and memory stores.
+ */
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use anyhow::{bail, Result};
+
+/// How often to check for an interrupt while waiting for children.
+const POLL_MSEC: libc::c_int = 100;
+
+pub struct Children {
+    pub pids: Vec<libc::pid_t>,
+    pub write: bool,
+    /*
+     * Each child blocks reading from the other end of this pipe, and exits
+     * once we close it.
+     */
+    release: Option<OwnedFd>,
+}
+
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not create pipe: {e}");
+    }
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+impl Children {
+    /// Fork n children.  If write is true, each child stores to every page of
+    /// the given regions, and we wait until they have all done so.  The stores
+    /// change the contents, so the regions must be private mappings, of which
+    /// each child gets its own copy.  Returns None if the interrupted function
+    /// returns true before the children are finished, in which case they have
+    /// been released.
+    pub fn fork(
+        n: usize,
+        regions: &[(*mut u8, usize)],
+        write: bool,
+        interrupted: &dyn Fn() -> bool,
+    ) -> Result<Option<Children>> {
+        let pagesize = crate::region::pagesize();
+        let (release_r, release_w) = pipe()?;
+        let (done_r, done_w) = pipe()?;
+
+        let mut children =
+            Children { pids: Vec::new(), write, release: Some(release_w) };
+
+        for _ in 0..n {
+            match unsafe { libc::fork() } {
+                -1 => {
+                    let e = std::io::Error::last_os_error();
+                    bail!("could not fork: {e}");
+                }
+                0 => unsafe {
+                    child(
+                        regions,
+                        pagesize,
+                        write,
+                        release_r.as_raw_fd(),
+                        children.release.as_ref().unwrap().as_raw_fd(),
+                        done_w.as_raw_fd(),
+                    )
+                },
+                pid => children.pids.push(pid),
+            }
+        }
+
+        /*
+         * Wait for a byte from each child to say that it has finished writing.
+         * Once our copy of the write end of the pipe is closed, we will see
+         * end-of-file early if a child has died.
+         */
+        drop(done_w);
+        let mut reported = 0;
+        while write && reported < n {
+            if interrupted() {
+                return Ok(None);
+            }
+
+            let mut pfd = libc::pollfd {
+                fd: done_r.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pfd, 1, POLL_MSEC) } <= 0 {
+                continue;
+            }
+
+            let mut buf = [0u8; 64];
+            let r = unsafe {
+                libc::read(done_r.as_raw_fd(), buf.as_mut_ptr() as *mut _, 64)
+            };
+            if r <= 0 {
+                bail!("child exited before writing");
+            }
+            reported += r as usize;
+        }
+
+        Ok(Some(children))
+    }
+}
+
+/// The body of each child process.  This never returns.
+unsafe fn child(
+    regions: &[(*mut u8, usize)],
+    pagesize: usize,
+    write: bool,
+    release: libc::c_int,
+    release_w: libc::c_int,
+    done: libc::c_int,
+) -> ! {
+    /*
+     * Close our copy of the write end of the release pipe, or we would never
+     * see end-of-file on the read end.
+     */
+    libc::close(release_w);
+
+    if write {
+        for &(base, len) in regions {
+            let mut off = 0;
+            while off < len {
+                let p = base.add(off);
+                p.write_volatile(p.read_volatile().wrapping_add(1));
+                off += pagesize;
+            }
+        }
+
+        let b = 0u8;
+        libc::write(done, &b as *const u8 as *const _, 1);
+    }
+    libc::close(done);
+
+    let mut b = 0u8;
+    while libc::read(release, &mut b as *mut u8 as *mut _, 1) < 0
+        && std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR)
+    {
+    }
+    libc::_exit(0);
+}
+
+impl Drop for Children {
+    fn drop(&mut self) {
+        /*
+         * Closing the release pipe causes each child to exit, but children
+         * forked later may also hold the pipe open, so make sure.
+         */
+        self.release.take();
+        for &pid in &self.pids {
+            let mut status = 0;
+            unsafe {
+                libc::kill(pid, libc::SIGKILL);
+                libc::waitpid(pid, &mut status, 0);
+            }
+        }
+    }
+}
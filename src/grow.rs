@@ -0,0 +1,351 @@
+/*
+ * Growing our memory footprint, either at the prompt or in a background job.
+ */
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::lgrp;
+use crate::parse;
+use crate::pattern::Pattern;
+use crate::region::{self, Region};
+use crate::sys;
+
+/// Fill this many bytes at a time, checking for an interrupt and holding to
+/// the requested rate in between.  This is large enough that the checks cost
+/// nothing next to the fill, and small enough that ^C takes effect at once.
+pub const FILL_CHUNK: usize = 1024 * 1024;
+
+/// The longest that a Pacer sleeps before checking whether it should stop.
+const PACE_SLICE: Duration = Duration::from_millis(100);
+
+/// The most places in a new region at which we sample the physical pages
+/// behind it, so that reporting on a very large region stays quick.
+const MAX_SAMPLES: usize = 65536;
+
+/// What the user asked "grow" to do.
+#[derive(Clone)]
+pub struct Spec {
+    /// The size of the growth in bytes.
+    pub size: usize,
+    pub locked: bool,
+    pub pgsz: Option<usize>,
+    /// The number of threads that fill the new region in parallel.
+    pub threads: usize,
+    /// If set, the rate in megabytes per second that we should not exceed.
+    pub rate: Option<f64>,
+    pub pattern: Pattern,
+    /// The lgroup from which the memory should be allocated.
+    pub lgrp: Option<i32>,
+    /// The CPU to which the threads doing the fill should be bound.
+    pub bind: Option<i32>,
+}
+
+/// The outcome of a completed grow.
+pub struct Grown {
+    pub region: Region,
+    pub dur: Duration,
+    /// The pattern with which the region was filled.
+    pub pattern: Pattern,
+    /// The sizes of the physical pages that back the region.
+    pub granted: Result<BTreeMap<u64, usize>>,
+    /// If an lgroup was requested, the lgroups the pages actually came from.
+    pub lgroups: Option<Result<BTreeMap<u64, usize>>>,
+    /// Whether we were interrupted, in which case the region holds only what
+    /// was filled before then.
+    pub partial: bool,
+}
+
+/// Parse the value given for a grow option, naming the option and what it
+/// expects if the value is not valid.
+fn value<T: std::str::FromStr>(opt: &str, what: &str, v: &str) -> Result<T> {
+    match v.parse() {
+        Ok(v) => Ok(v),
+        Err(_) => bail!("grow option {opt:?} expects {what}, not {v:?}"),
+    }
+}
+
+impl Spec {
+    /// A plain growth by the given number of bytes.
+    pub fn new(size: usize) -> Spec {
+        Spec {
+            size,
+            locked: false,
+            pgsz: None,
+            threads: 1,
+            rate: None,
+            pattern: Pattern::A,
+            lgrp: None,
+            bind: None,
+        }
+    }
+
+    pub fn parse(args: &[&str]) -> Result<Spec> {
+        let ctx = parse::SizeContext::system();
+        let size = match args.first() {
+            Some(size) => parse::size(size, &ctx)?,
+            None => bail!("grow by how much?"),
+        };
+
+        let mut spec = Spec::new(size as usize);
+        let mut opts = args[1..].iter();
+        while let Some(a) = opts.next() {
+            match *a {
+                "locked" => spec.locked = true,
+                "pgsz" => {
+                    let Some(v) = opts.next() else {
+                        bail!("grow option \"pgsz\" requires a page size");
+                    };
+                    let v = parse::size(v, &ctx)?;
+                    spec.pgsz = Some(v as usize);
+                }
+                "-j" => {
+                    let Some(v) = opts.next() else {
+                        bail!("grow option \"-j\" requires a thread count");
+                    };
+                    spec.threads = value("-j", "a thread count", v)?;
+                }
+                "rate" => {
+                    let Some(v) = opts.next() else {
+                        bail!("grow option \"rate\" requires a rate in MB/s");
+                    };
+                    spec.rate = Some(parse::rate(v)?);
+                }
+                "pattern" => {
+                    let Some(v) = opts.next() else {
+                        bail!("grow option \"pattern\" requires a pattern");
+                    };
+                    spec.pattern = v.parse()?;
+                }
+                "seed" => {
+                    let Some(v) = opts.next() else {
+                        bail!("grow option \"seed\" requires a number");
+                    };
+                    spec.pattern =
+                        Pattern::Random(value("seed", "a number", v)?);
+                }
+                "lgrp" => {
+                    let Some(v) = opts.next() else {
+                        bail!("grow option \"lgrp\" requires an lgroup ID");
+                    };
+                    spec.lgrp = Some(value("lgrp", "an lgroup ID", v)?);
+                }
+                "bind" => {
+                    let Some(v) = opts.next() else {
+                        bail!("grow option \"bind\" requires a CPU ID");
+                    };
+                    spec.bind = Some(value("bind", "a CPU ID", v)?);
+                }
+                other if other.starts_with("-j") => {
+                    spec.threads = value("-j", "a thread count", &other[2..])?;
+                }
+                other => bail!("grow option {other:?} unknown"),
+            }
+        }
+
+        if spec.threads == 0 {
+            bail!("grow needs at least one thread");
+        }
+
+        Ok(spec)
+    }
+
+    /// Describe the growth, e.g., "2 megabytes (locked, random seed 1)".
+    pub fn describe(&self) -> String {
+        let mut notes = Vec::new();
+        if self.locked {
+            notes.push("locked".to_string());
+        }
+        if self.pattern != Pattern::A {
+            notes.push(self.pattern.to_string());
+        }
+        if let Some(id) = self.lgrp {
+            notes.push(format!("lgrp {id}"));
+        }
+        if let Some(cpu) = self.bind {
+            notes.push(format!("bound to CPU {cpu}"));
+        }
+
+        let size = parse::megabytes(self.size as u64);
+        if notes.is_empty() {
+            size
+        } else {
+            format!("{size} ({})", notes.join(", "))
+        }
+    }
+
+    /// Allocate and fill a new region.  The interrupted function is checked
+    /// periodically, and if it returns true we stop, keeping whatever we have
+    /// filled so far, or returning None if that is nothing at all.
+    pub fn run(&self, interrupted: &dyn Fn() -> bool) -> Result<Option<Grown>> {
+        let start = Instant::now();
+        let mut a = match self.pgsz {
+            Some(pgsz) => Region::with_pagesize(self.size, pgsz),
+            None => Region::new(self.size),
+        }
+        .map_err(|e| anyhow!("could not grow: {e}"))?;
+
+        /*
+         * Locking the region faults in every page at once, so do it before we
+         * fill the pages rather than after.
+         */
+        if self.locked {
+            a.lock()?;
+        }
+
+        let pattern = self.pattern.reseed();
+        let filled = fill(a.bytes_mut()?, self, pattern, interrupted)?;
+        let partial = filled < a.len();
+        if partial {
+            let keep = filled - filled % region::pagesize();
+            if keep == 0 {
+                return Ok(None);
+            }
+            a.truncate(keep);
+        }
+        let dur = start.elapsed();
+
+        /*
+         * Even without a request, the system may use large pages for some or
+         * all of the region, and the page size and lgroup are only advice in
+         * any case.  Once the pages have been faulted in, report on what we
+         * actually got.  Sampling more often than once per requested page
+         * tells us nothing more.
+         */
+        let stride = (a.len() / MAX_SAMPLES)
+            .next_multiple_of(region::pagesize())
+            .max(self.pgsz.unwrap_or(region::pagesize()));
+        let granted = a.page_sizes(stride);
+        let lgroups = self.lgrp.map(|_| a.lgroups(stride));
+
+        Ok(Some(Grown { region: a, dur, pattern, granted, lgroups, partial }))
+    }
+}
+
+/// Fill the region with the pattern, divided as evenly as we can between the
+/// requested number of threads, and at no more than the requested rate in MB/s.
+/// Returns the length of the part of the region, from the start, that was
+/// filled without any gaps, which is all of it unless we were interrupted.
+fn fill(
+    a: &mut [u8],
+    spec: &Spec,
+    pattern: Pattern,
+    interrupted: &dyn Fn() -> bool,
+) -> Result<usize> {
+    let stop = AtomicBool::new(false);
+
+    /*
+     * Each thread is responsible for an equal share of the rate.
+     */
+    let threads = spec.threads;
+    let rate = spec.rate.map(|r| r / threads as f64);
+    let lgrp = spec.lgrp;
+    let bind = spec.bind;
+    let part = a.len().div_ceil(threads).next_multiple_of(region::pagesize());
+
+    let parts = std::thread::scope(|s| {
+        let workers = a
+            .chunks_mut(part.max(1))
+            .enumerate()
+            .map(|(i, buf)| {
+                let stop = &stop;
+                s.spawn(move || -> Result<(usize, usize)> {
+                    /*
+                     * Pages are allocated from the home lgroup of the thread
+                     * that first touches them.
+                     */
+                    if let Some(id) = lgrp {
+                        lgrp::set_home(id)?;
+                    }
+                    if bind.is_some() {
+                        sys::bind_lwp(bind)?;
+                    }
+
+                    let pacer = Pacer::new(rate);
+                    let mut done = 0;
+                    for chunk in buf.chunks_mut(FILL_CHUNK) {
+                        pattern.fill(chunk, i * part + done);
+                        done += chunk.len();
+
+                        if stop.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        pacer.wait(done, &|| stop.load(Ordering::Relaxed));
+                    }
+                    Ok((done, buf.len()))
+                })
+            })
+            .collect::<Vec<_>>();
+
+        /*
+         * The workers cannot check for an interrupt themselves, so poll on
+         * their behalf until they are all done.
+         */
+        while !workers.iter().all(|w| w.is_finished()) {
+            if interrupted() {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        workers
+            .into_iter()
+            .map(|w| w.join().expect("fill thread panicked"))
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    let mut filled = 0;
+    for (done, len) in parts {
+        filled += done;
+        if done < len {
+            break;
+        }
+    }
+    Ok(filled)
+}
+
+/// The rate, in megabytes per second, at which some number of bytes were
+/// processed in the given time.
+pub fn bandwidth(bytes: usize, dur: Duration) -> f64 {
+    bytes as f64 / 1024.0 / 1024.0 / dur.as_secs_f64().max(1e-6)
+}
+
+/// Holds a loop to a target rate, in megabytes per second, by sleeping
+/// whenever the loop gets ahead of where that rate says it should be.
+pub struct Pacer {
+    start: Instant,
+    bytes_per_sec: Option<f64>,
+}
+
+impl Pacer {
+    /// A pacer for the given rate in MB/s, or for no limit at all if None.
+    pub fn new(rate: Option<f64>) -> Pacer {
+        Pacer {
+            start: Instant::now(),
+            bytes_per_sec: rate.map(|r| r * 1024.0 * 1024.0),
+        }
+    }
+
+    /// Sleep, if needed, now that done bytes have been processed.  The sleep
+    /// is taken in short slices, and is cut short as soon as stop returns true.
+    pub fn wait(&self, done: usize, stop: &dyn Fn() -> bool) {
+        let Some(bps) = self.bytes_per_sec else {
+            return;
+        };
+        let Ok(due) = Duration::try_from_secs_f64(done as f64 / bps) else {
+            return;
+        };
+
+        while let Some(d) = due.checked_sub(self.start.elapsed()) {
+            if stop() {
+                return;
+            }
+            std::thread::sleep(d.min(PACE_SLICE));
+        }
+    }
+}
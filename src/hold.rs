@@ -0,0 +1,100 @@
+/*
+ * Holding a steady working set.  A background thread owns a region of the
+ * requested size and, once per interval, checks how much of it remains
+ * resident, then touches every page so that any that were paged out are
+ * faulted back in and the rest look recently used to the page scanner.
+ */
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+
+use crate::region::{self, Region};
+use crate::term::Term;
+
+/// What the hold thread found on its most recent pass.
+#[derive(Clone, Copy, Default)]
+pub struct State {
+    pub passes: u64,
+    /// Bytes that were resident at the start of the last pass.
+    pub resident: usize,
+    /// Bytes faulted back in, over all passes.
+    pub refaulted: usize,
+}
+
+pub struct Hold {
+    /// The size of the held region in bytes.
+    pub size: usize,
+    pub interval: Duration,
+    pub since: DateTime<Utc>,
+    state: Arc<Mutex<State>>,
+    /*
+     * The thread exits, releasing the region, once this channel is closed.
+     */
+    _stop: mpsc::Sender<()>,
+}
+
+impl Hold {
+    /// Allocate and fill a region of the given size, then keep it resident
+    /// until the returned object is dropped.
+    pub fn start(
+        ed: Arc<Term>,
+        size: usize,
+        interval: Duration,
+    ) -> Result<Hold> {
+        if interval.is_zero() {
+            bail!("hold interval must be non-zero");
+        }
+
+        let mut r = Region::new(size)?;
+        r.bytes_mut()?.fill(b'H');
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let (tx, rx) = mpsc::channel::<()>();
+
+        let st = Arc::clone(&state);
+        std::thread::Builder::new().name("hold".into()).spawn(move || {
+            let pagesize = region::pagesize();
+
+            loop {
+                match rx.recv_timeout(interval) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    _ => return,
+                }
+
+                let resident = match r.resident() {
+                    Ok(resident) => resident,
+                    Err(e) => {
+                        ed.log(&format!("hold: {e}")).ok();
+                        continue;
+                    }
+                };
+                let missing = resident.iter().filter(|&&r| !r).count();
+
+                r.touch(0, r.len(), pagesize).ok();
+
+                let mut s = st.lock().unwrap();
+                s.passes += 1;
+                s.resident = (resident.len() - missing) * pagesize;
+                s.refaulted += missing * pagesize;
+                drop(s);
+
+                if missing > 0 {
+                    ed.log(&format!(
+                        "hold: faulted {:.1} MB back in",
+                        (missing * pagesize) as f64 / 1024.0 / 1024.0
+                    ))
+                    .ok();
+                }
+            }
+        })?;
+
+        Ok(Hold { size, interval, since: Utc::now(), state, _stop: tx })
+    }
+
+    pub fn state(&self) -> State {
+        *self.state.lock().unwrap()
+    }
+}
@@ -0,0 +1,29 @@
+/*
+ * Just enough JSON to write out the data we collect, without pulling in a
+ * serialisation framework.
+ */
+
+use std::fmt::Write;
+
+/// Quote and escape a string for inclusion in a JSON document.
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", c as u32).unwrap();
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+
+    out
+}
@@ -0,0 +1,42 @@
+/*
+ * The kernel's object caches, from kmem_cache_create(9F).  Most kernel memory
+ * is allocated from these caches, each of which has a "unix:0:<cache>" kstat
+ * of class "kmem_cache".  Growth here takes memory away from both userland and
+ * the ARC.
+ */
+
+use anyhow::Result;
+
+use crate::kstat::{consts::*, KstatWrapper};
+
+pub struct Cache {
+    pub name: String,
+    /// The size of each object in the cache.
+    pub buf_size: u64,
+    /// Objects currently allocated from the cache.
+    pub inuse: u64,
+    /// Bytes of memory held by the slabs of the cache, whether or not the
+    /// objects in them are allocated.
+    pub memory: u64,
+}
+
+/// List every kmem cache, as ::kmastat in mdb(1) would.
+pub fn caches() -> Result<Vec<Cache>> {
+    let ks = KstatWrapper::open()?;
+    let mut out = Vec::new();
+
+    for k in ks.walk_class(CLASS_KMEM_CACHE) {
+        let slabs = k
+            .data_u64(STAT_SLAB_CREATE)
+            .unwrap_or(0)
+            .saturating_sub(k.data_u64(STAT_SLAB_DESTROY).unwrap_or(0));
+        out.push(Cache {
+            name: k.name().to_string_lossy().into_owned(),
+            buf_size: k.data_u64(STAT_BUF_SIZE).unwrap_or(0),
+            inuse: k.data_u64(STAT_BUF_INUSE).unwrap_or(0),
+            memory: slabs * k.data_u64(STAT_SLAB_SIZE).unwrap_or(0),
+        });
+    }
+
+    Ok(out)
+}
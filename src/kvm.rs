@@ -55,12 +55,22 @@ impl Drop for Kvm {
 
 impl Kvm {
     pub fn new() -> Result<Kvm> {
+        Kvm::open(libc::O_RDWR)
+    }
+
+    /// Open the kernel for reading only, which is all that is required to
+    /// inspect kernel variables.
+    pub fn new_readonly() -> Result<Kvm> {
+        Kvm::open(libc::O_RDONLY)
+    }
+
+    fn open(flag: c_int) -> Result<Kvm> {
         let kvm = unsafe {
             kvm_open(
                 std::ptr::null(),
                 std::ptr::null(),
                 std::ptr::null(),
-                libc::O_RDWR,
+                flag,
                 std::ptr::null_mut(),
             )
         };
@@ -143,6 +153,14 @@ impl Kvm {
         Ok(())
     }
 
+    pub fn read_u32(&self, addr: usize) -> Result<u32> {
+        let mut buf = [0u8; size_of::<u32>()];
+
+        self.read_buf(addr, &mut buf)?;
+
+        Ok(u32::from_ne_bytes(buf))
+    }
+
     pub fn read_u16(&self, addr: usize) -> Result<u16> {
         let mut buf = [0u8; size_of::<u16>()];
 
@@ -0,0 +1,113 @@
+/*
+ * Locality groups (lgroups), from liblgrp(3LIB).  On a machine with more than
+ * one socket, memory is closer to some CPUs than others, and the system tries
+ * to allocate memory from the home lgroup of the thread that first touches
+ * it.
+ */
+
+use anyhow::{bail, Result};
+use libc::{c_int, c_longlong, c_uint, uintptr_t};
+
+use crate::sys::{P_LWPID, P_MYID};
+
+#[allow(non_camel_case_types)]
+type lgrp_cookie_t = uintptr_t;
+#[allow(non_camel_case_types)]
+type lgrp_id_t = c_int;
+
+/*
+ * From <sys/lgrp_user.h>:
+ */
+const LGRP_VIEW_OS: c_int = 1;
+const LGRP_MEM_SZ_FREE: c_int = 0;
+const LGRP_MEM_SZ_INSTALLED: c_int = 1;
+const LGRP_CONTENT_DIRECT: c_int = 1;
+const LGRP_AFF_STRONG: c_int = 0x100;
+
+#[link(name = "lgrp")]
+extern "C" {
+    fn lgrp_init(view: c_int) -> lgrp_cookie_t;
+    fn lgrp_fini(cookie: lgrp_cookie_t) -> c_int;
+    fn lgrp_root(cookie: lgrp_cookie_t) -> lgrp_id_t;
+    fn lgrp_children(
+        cookie: lgrp_cookie_t,
+        parent: lgrp_id_t,
+        lgrp_array: *mut lgrp_id_t,
+        lgrp_array_size: c_uint,
+    ) -> c_int;
+    fn lgrp_mem_size(
+        cookie: lgrp_cookie_t,
+        lgrp: lgrp_id_t,
+        kind: c_int,
+        content: c_int,
+    ) -> c_longlong;
+    fn lgrp_affinity_set(
+        idtype: c_int,
+        id: c_int,
+        lgrp: lgrp_id_t,
+        affinity: c_int,
+    ) -> c_int;
+}
+
+/// The memory that is directly contained in a single lgroup.
+pub struct Lgroup {
+    pub id: i32,
+    pub installed: u64,
+    pub free: u64,
+}
+
+/// List the lgroups that directly contain memory.
+pub fn list() -> Result<Vec<Lgroup>> {
+    let cookie = unsafe { lgrp_init(LGRP_VIEW_OS) };
+    if cookie == 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get lgroup hierarchy: {e}");
+    }
+
+    /*
+     * Walk the whole hierarchy from the root.  On a machine with only one
+     * lgroup, the root is also the only leaf and it contains all the memory.
+     */
+    let mut out = Vec::new();
+    let mut todo = vec![unsafe { lgrp_root(cookie) }];
+    while let Some(id) = todo.pop() {
+        let size = |kind| unsafe {
+            lgrp_mem_size(cookie, id, kind, LGRP_CONTENT_DIRECT)
+        };
+        let installed = size(LGRP_MEM_SZ_INSTALLED);
+        if installed > 0 {
+            out.push(Lgroup {
+                id,
+                installed: installed as u64,
+                free: size(LGRP_MEM_SZ_FREE).max(0) as u64,
+            });
+        }
+
+        let n = unsafe { lgrp_children(cookie, id, std::ptr::null_mut(), 0) };
+        if n > 0 {
+            let mut children = vec![0; n as usize];
+            let n = unsafe {
+                lgrp_children(cookie, id, children.as_mut_ptr(), n as c_uint)
+            };
+            children.truncate(n.max(0) as usize);
+            todo.extend(children);
+        }
+    }
+
+    unsafe { lgrp_fini(cookie) };
+
+    out.sort_by_key(|l| l.id);
+    Ok(out)
+}
+
+/// Give the calling LWP a strong affinity for the given lgroup, which makes
+/// that lgroup its home.  Memory it touches first will be allocated there if
+/// possible.
+pub fn set_home(id: i32) -> Result<()> {
+    if unsafe { lgrp_affinity_set(P_LWPID, P_MYID, id, LGRP_AFF_STRONG) } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not set affinity for lgroup {id}: {e}");
+    }
+
+    Ok(())
+}
@@ -8,40 +8,291 @@ use std::{
 use anyhow::{bail, Result};
 use chrono::prelude::*;
 
-mod kvm;
-use kstat::consts::*;
+mod alloc;
+mod arc;
+mod cmd;
+mod disk;
+mod fault;
+mod fileread;
+mod forks;
+mod grow;
+mod hold;
+mod json;
+mod kmem;
 mod kstat;
+mod kvm;
+mod lgrp;
+mod memstat;
+mod oscillate;
+mod parse;
+mod pattern;
+mod ramp;
+mod redact;
+mod region;
+mod release;
+mod session;
+mod settings;
+mod snapshot;
+mod stack;
+mod stats;
+mod swap;
+mod sys;
 mod term;
+mod worker;
+use settings::Settings;
 use term::Line;
 
 enum Activity {
     Line(term::Line),
     Error(String),
+    /// A background grow job has finished.
+    Grown(usize, Box<grow::Spec>, Box<Result<Option<grow::Grown>>>),
+    /// A background free job has finished.
+    Freed(usize, Box<release::Released>),
 }
 
 struct FillMem {
     inner: Arc<(Mutex<Inner>, Condvar)>,
 }
 
+/// An account of the memory that fillmem has removed from availrmem, which is
+/// pressure of a different kind to that exerted by merely resident pages.
+#[derive(Clone, Copy, Default)]
+struct Ledger {
+    /// Bytes of allocations locked in memory, either on their own with
+    /// mlock(3C) or along with everything else by "lockall on".
+    locked: usize,
+    /// Bytes of fillmem's own text, data, and stacks locked by mlockall(3C),
+    /// with "lockall on" or --protect-self.  This is measured as the fall in
+    /// availrmem at the time, less any allocations that were locked with it.
+    own: usize,
+    /// Whether every allocation is locked, as it is while "lockall on" is in
+    /// effect.
+    all: bool,
+}
+
+impl Ledger {
+    fn total(&self) -> usize {
+        self.locked + self.own
+    }
+}
+
 impl FillMem {
     fn unbusy(&self) {
         self.inner.0.lock().unwrap().busy = false;
         self.inner.1.notify_all();
     }
+
+    /// Publish a description of our allocations, for use by other threads.
+    fn record_allocs(&self, allocs: Vec<alloc::AllocInfo>) {
+        let mut i = self.inner.0.lock().unwrap();
+        let all = i.ledger.all;
+        i.ledger.locked =
+            allocs.iter().filter(|a| all || a.locked).map(|a| a.size).sum();
+        i.allocs = allocs;
+    }
+
+    /// Record that own bytes of our memory, beyond any allocation, have just
+    /// been locked with mlockall(3C), and whether every allocation, including
+    /// those made later, is now locked as well.
+    fn locked_all(&self, own: usize, all: bool) {
+        let mut i = self.inner.0.lock().unwrap();
+        i.ledger.own += own;
+        i.ledger.all |= all;
+    }
+
+    /// Record that munlockall(3C) has unlocked everything.
+    fn unlocked_all(&self) {
+        let mut i = self.inner.0.lock().unwrap();
+        i.ledger.own = 0;
+        i.ledger.all = false;
+    }
+
+    fn allocs(&self) -> Vec<alloc::AllocInfo> {
+        self.inner.0.lock().unwrap().allocs.clone()
+    }
+
+    fn ledger(&self) -> Ledger {
+        self.inner.0.lock().unwrap().ledger
+    }
+
+    /// Whether the periodic statistics line is silenced.
+    fn paused(&self) -> bool {
+        self.inner.0.lock().unwrap().paused
+    }
+
+    /// Silence or resume the statistics line, returning the previous state.
+    fn set_paused(&self, paused: bool) -> bool {
+        std::mem::replace(&mut self.inner.0.lock().unwrap().paused, paused)
+    }
+
+    /// The names of the columns to include in the statistics line, in order.
+    fn columns(&self) -> Vec<&'static str> {
+        self.inner.0.lock().unwrap().columns.clone()
+    }
+
+    fn set_columns(&self, columns: Vec<&'static str>) {
+        self.inner.0.lock().unwrap().columns = columns;
+    }
+
+    /// The period between lines of statistics.
+    fn interval(&self) -> Duration {
+        self.inner.0.lock().unwrap().interval
+    }
+
+    fn set_interval(&self, interval: Duration) {
+        self.inner.0.lock().unwrap().interval = interval;
+        self.inner.1.notify_all();
+    }
+
+    /// Sleep until the statistics interval has passed since start, but for
+    /// no longer than max.  Returns true once the interval has passed.  If the
+    /// interval is changed in the meantime, the new one takes effect at once
+    /// rather than after the rest of a long sleep.
+    fn wait_interval(&self, start: Instant, max: Duration) -> bool {
+        let wake = Instant::now() + max;
+        let mut i = self.inner.0.lock().unwrap();
+        loop {
+            let end = start + i.interval;
+            let Some(left) = end.checked_duration_since(Instant::now()) else {
+                return true;
+            };
+            let Some(left) = wake
+                .checked_duration_since(Instant::now())
+                .map(|w| w.min(left))
+            else {
+                return false;
+            };
+            i = self.inner.1.wait_timeout(i, left).unwrap().0;
+        }
+    }
 }
 
 struct Inner {
     busy: bool,
     interrupt: bool,
+    allocs: Vec<alloc::AllocInfo>,
+    ledger: Ledger,
+    interval: Duration,
+    columns: Vec<&'static str>,
+    paused: bool,
 }
 
 fn main() -> Result<()> {
-    //let kvm = kvm::Kvm::new()?;
-    let mut ks = kstat::KstatWrapper::open()?;
+    let mut opts = getopts::Options::new();
+    opts.optopt(
+        "",
+        "vmem-cap",
+        "limit the virtual size of the process",
+        "SIZE",
+    );
+    opts.optopt(
+        "",
+        "session-file",
+        &format!(
+            "record this session in FILE (default {})",
+            session::DEFAULT_PATH
+        ),
+        "FILE",
+    );
+    opts.optflag("", "force", "run even if another session is active");
+    opts.optopt(
+        "",
+        "interval",
+        "emit statistics once per PERIOD (default 500ms)",
+        "PERIOD",
+    );
+    opts.optflag(
+        "",
+        "protect-self",
+        "lock fillmem's own memory so that it stays responsive under pressure",
+    );
+    opts.optflag(
+        "",
+        "worker",
+        "run as a worker process for \"spawn\", driven over stdin",
+    );
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let mat = match opts.parse(&args) {
+        Ok(mat) => mat,
+        Err(e) => {
+            eprintln!("{}", opts.usage(&format!("fillmem: {e}")));
+            std::process::exit(2);
+        }
+    };
+
+    if mat.opt_present("worker") {
+        return worker::run();
+    }
+
+    if let Ok(list) = std::env::var("FILLMEM_FAULTS") {
+        fault::arm(&list)?;
+    }
+
+    let interval = match mat.opt_str("interval") {
+        Some(p) => match parse::duration(&p)? {
+            p if p.is_zero() => bail!("interval must be non-zero"),
+            p => p,
+        },
+        None => Duration::from_millis(500),
+    };
+
+    let mut settings = Settings::default();
+    if let Some(cap) = mat.opt_str("vmem-cap") {
+        let cap = parse::size(&cap, &parse::SizeContext::system())?;
+        sys::set_vmem_cap(Some(cap))?;
+        settings.vmem_cap = Some(cap);
+    }
+
+    let (_session, notes) = session::Session::create(
+        std::path::Path::new(
+            &mat.opt_str("session-file")
+                .unwrap_or_else(|| session::DEFAULT_PATH.into()),
+        ),
+        mat.opt_present("force"),
+    )?;
+
+    let mut source = stats::Source::open()?;
     let ed0 = Arc::new(term::Term::start()?);
+    for n in notes {
+        ed0.log(&n)?;
+    }
+    match source.identity() {
+        Ok(id) => ed0.log(&id.to_string())?,
+        Err(e) => ed0.log(&format!("could not identify system: {e}"))?,
+    }
+
+    /*
+     * In a non-global zone, the zone's caps are what will stop us, so sizes
+     * given as a percentage are taken from the physical memory cap rather
+     * than from the memory of the whole machine.
+     */
+    let zone = source.zone().ok().filter(|z| z.id != 0);
+    if let Some(z) = &zone {
+        let cap = |v: Option<u64>| match v {
+            None => "no cap".to_string(),
+            Some(v) => format!("cap {:.1} MB", v as f64 / 1024.0 / 1024.0),
+        };
+        ed0.log(&format!(
+            "running in zone {}: physical memory {}, swap {}",
+            z.name,
+            cap(z.physcap),
+            cap(z.swapcap)
+        ))?;
+        parse::set_physmem_limit(z.physcap);
+    }
     let fm0 = Arc::new(FillMem {
         inner: Arc::new((
-            Mutex::new(Inner { busy: false, interrupt: false }),
+            Mutex::new(Inner {
+                busy: false,
+                interrupt: false,
+                allocs: Vec::new(),
+                ledger: Ledger::default(),
+                interval,
+                columns: stats::default_columns(zone.is_some()),
+                paused: false,
+            }),
             Condvar::new(),
         )),
     });
@@ -50,14 +301,80 @@ fn main() -> Result<()> {
 
     let tx = tx0.clone();
     let ed = Arc::clone(&ed0);
+    let fm = Arc::clone(&fm0);
     std::thread::Builder::new()
         .name("timer".into())
         .spawn(move || {
-            let interval = Duration::from_millis(500);
             let mut last_run = Instant::now();
+            let mut failing = false;
+            let mut vminfo = None;
+            let mut swap = None;
+            let mut vm: Option<kstat::CpuStats> = None;
+            let mut net: Option<(stats::Net, Instant)> = None;
+            let pagevars = stats::PageVars::open().ok();
+            let mut throttled: Option<Instant> = None;
+            let thresholds = source.thresholds(pagevars.as_ref()).ok();
+            let mut below = None;
+            if let Some(t) = &thresholds {
+                let mb = |v: u64| {
+                    (v * region::pagesize() as u64) as f64 / 1024.0 / 1024.0
+                };
+                ed.log(&format!(
+                    "lotsfree {:.1} MB, desfree {:.1} MB, minfree {:.1} MB, \
+                    throttlefree {:.1} MB",
+                    mb(t.lotsfree),
+                    mb(t.desfree),
+                    mb(t.minfree),
+                    mb(t.throttlefree),
+                ))
+                .ok();
+            }
+            let mut arc: Option<(u64, u64)> = None;
+            let mut arcrates = kstat::KstatRates::new(
+                kstat::consts::MODULE_ZFS,
+                0,
+                kstat::consts::NAME_ARCSTATS,
+                &[kstat::consts::STAT_HITS, kstat::consts::STAT_MISSES],
+            );
+            let mut zfetchrates = kstat::KstatRates::new(
+                kstat::consts::MODULE_ZFS,
+                0,
+                kstat::consts::NAME_ZFETCHSTATS,
+                &[kstat::consts::STAT_HITS, kstat::consts::STAT_MISSES],
+            );
+            let mut pools: Vec<disk::Disk> = Vec::new();
+
+            /*
+             * Each interval is measured from when the last one was due to end,
+             * rather than from when we got around to starting it, so that time
+             * taken to collect the statistics does not accumulate into drift.
+             */
+            let mut start = Instant::now();
 
             loop {
-                std::thread::sleep(interval);
+                /*
+                 * A single reading of free memory at the end of the interval
+                 * will miss any short dips in between, so read the cheap page
+                 * counts several times a second while we wait.
+                 */
+                let mut free = stats::Range::default();
+                let mut avrm = stats::Range::default();
+                while !fm.wait_interval(start, Duration::from_millis(100)) {
+                    if let Ok((f, a)) = source.pages() {
+                        free.add(f);
+                        avrm.add(a);
+                    }
+                }
+
+                /*
+                 * If we have fallen more than a whole interval behind, skip
+                 * the samples we missed rather than emitting them in a burst.
+                 */
+                let interval = fm.interval();
+                start += interval;
+                while start + interval <= Instant::now() {
+                    start += interval;
+                }
 
                 /*
                  * Warn the user if we appear to be sluggish.
@@ -65,62 +382,336 @@ fn main() -> Result<()> {
                 let now = Instant::now();
                 let msec =
                     now.checked_duration_since(last_run).unwrap().as_millis();
-                if msec > 3 * interval.as_millis() {
+                if msec > 3 * fm.interval().as_millis() {
                     ed.log(&format!("{msec} msec since last stats; sluggish?"))
                         .ok();
                 }
                 last_run = now;
 
-                let mut arc_c: u64 = 0;
-                let mut arc_c_min: u64 = 0;
-                let mut arc_c_max: u64 = 0;
-                let mut availrmem: u64 = 0;
-                let mut freemem: u64 = 0;
-
                 /*
                  * Read some statistics from the kernel to emit.
                  */
                 let now = Utc::now();
-                if ks.chain_update().is_err() {
-                    continue;
+                let hrt = sys::hrtime();
+                let st = match source.sample() {
+                    Ok(st) => {
+                        if failing {
+                            failing = false;
+                            ed.log("statistics available again").ok();
+                        }
+                        st
+                    }
+                    Err(e) => {
+                        /*
+                         * Report only the first failure in a run, rather
+                         * than flooding the screen.
+                         */
+                        if !failing {
+                            failing = true;
+                            ed.log(&format!("statistics unavailable: {e}"))
+                                .ok();
+                        }
+                        continue;
+                    }
+                };
+
+                /*
+                 * The ARC gives memory back by lowering its target size, and
+                 * the size follows as buffers are evicted.  A lower target is
+                 * the event worth reporting; the size alone drifts down and
+                 * up all the time.
+                 */
+                if let Some((c, size)) = arc {
+                    if st.arc_c < c {
+                        let mb = |v: u64| v as f64 / 1024.0 / 1024.0;
+                        ed.log(&format!(
+                            "ARC shrank by {:.1} MB: c {:.1} -> {:.1} MB, \
+                            size {:.1} -> {:.1} MB, arc_no_grow {}",
+                            mb(c - st.arc_c),
+                            mb(c),
+                            mb(st.arc_c),
+                            mb(size),
+                            mb(st.arc_size),
+                            match st.arc_no_grow {
+                                Some(true) => "set",
+                                Some(false) => "clear",
+                                None => "unknown",
+                            }
+                        ))
+                        .ok();
+                    }
+                }
+                arc = Some((st.arc_c, st.arc_size));
+
+                /*
+                 * Entering page_create() throttling is usually the moment of
+                 * interest, so call it out as soon as we see it rather than
+                 * leaving it to be inferred from the figures.
+                 */
+                if let Ok(t) = source.throttle(pagevars.as_ref()) {
+                    let mb = |v: u64| {
+                        (v * region::pagesize() as u64) as f64 / 1024.0 / 1024.0
+                    };
+                    match (t.throttled(), throttled) {
+                        (true, None) => {
+                            throttled = Some(Instant::now());
+                            ed.alert(&format!(
+                                "page creation throttled: free {:.1} MB, \
+                                throttlefree {:.1} MB, needfree {:.1} MB, \
+                                deficit {:.1} MB",
+                                mb(t.freemem),
+                                mb(t.throttlefree),
+                                mb(t.needfree),
+                                mb(t.deficit),
+                            ))
+                            .ok();
+                        }
+                        (false, Some(since)) => {
+                            throttled = None;
+                            ed.log(&format!(
+                                "page creation no longer throttled after {} \
+                                msec",
+                                since.elapsed().as_millis()
+                            ))
+                            .ok();
+                        }
+                        _ => (),
+                    }
                 }
 
-                ks.lookup(Some(MODULE_ZFS), Some(NAME_ARCSTATS));
-                while ks.step() {
-                    if ks.module() != MODULE_ZFS || ks.name() != NAME_ARCSTATS {
-                        break;
+                /*
+                 * A figure for free memory means little without the levels at
+                 * which the kernel starts to act, so say whenever it crosses
+                 * one of them.
+                 */
+                let now_below =
+                    thresholds.as_ref().and_then(|t| t.below(st.freemem));
+                if now_below != below {
+                    let mb = |v: u64| {
+                        (v * region::pagesize() as u64) as f64 / 1024.0 / 1024.0
+                    };
+                    let free = mb(st.freemem);
+                    match (now_below, below) {
+                        (Some((n, v)), old)
+                            if old.is_none_or(|(_, ov)| v < ov) =>
+                        {
+                            ed.alert(&format!(
+                                "free memory {free:.1} MB fell below {n} \
+                                ({:.1} MB)",
+                                mb(v)
+                            ))
+                            .ok();
+                        }
+                        (_, Some((n, v))) => {
+                            ed.log(&format!(
+                                "free memory {free:.1} MB back above {n} \
+                                ({:.1} MB)",
+                                mb(v)
+                            ))
+                            .ok();
+                        }
+                        _ => (),
                     }
+                    below = now_below;
+                }
 
-                    arc_c = ks.data_u64(STAT_C).unwrap_or(0);
-                    arc_c_min = ks.data_u64(STAT_C_MIN).unwrap_or(0);
-                    arc_c_max = ks.data_u64(STAT_C_MAX).unwrap_or(0);
-                    break;
+                /*
+                 * The swap figures are only updated once per second, which is
+                 * less often than we sample, so hold on to the earlier reading
+                 * until there is a new update and keep showing the last
+                 * average in the meantime.
+                 */
+                if let Ok(vi) = source.vminfo() {
+                    match &vminfo {
+                        Some(prev) => {
+                            if let Some(avg) = stats::Swap::average(&vi, prev) {
+                                swap = Some(avg);
+                                vminfo = Some(vi);
+                            }
+                        }
+                        None => vminfo = Some(vi),
+                    }
                 }
 
-                ks.lookup(Some(MODULE_UNIX), Some(NAME_SYSTEM_PAGES));
-                while ks.step() {
-                    if ks.module() != MODULE_UNIX
-                        || ks.name() != NAME_SYSTEM_PAGES
-                    {
-                        break;
+                /*
+                 * Produce every column we can, and then emit only those the
+                 * user has asked for.
+                 */
+                let mb = |v: u64| format!("{:7.1}", v as f64 / 1024.0 / 1024.0);
+                let pgsz = region::pagesize() as u64;
+                let mut cols: Vec<(&str, String)> = vec![
+                    ("hrt", hrt.to_string()),
+                    ("drop", format!("{:7}", source.dropped())),
+                    (
+                        "below",
+                        format!("{:>7}", below.map(|(n, _)| n).unwrap_or("-")),
+                    ),
+                    ("c", mb(st.arc_c)),
+                    ("min", mb(st.arc_c_min)),
+                    ("max", mb(st.arc_c_max)),
+                    ("free", mb(st.freemem * pgsz)),
+                    ("avrm", mb(st.availrmem * pgsz)),
+                    ("mine", mb(fm.ledger().total() as u64)),
+                ];
+
+                free.add(st.freemem);
+                avrm.add(st.availrmem);
+                for (n, v) in [
+                    ("fmin", free.min),
+                    ("favg", free.mean()),
+                    ("fmax", free.max),
+                    ("amin", avrm.min),
+                    ("aavg", avrm.mean()),
+                    ("amax", avrm.max),
+                ] {
+                    cols.push((n, mb(v * pgsz)));
+                }
+
+                if let Some(sw) = &swap {
+                    cols.push(("resv", mb(sw.resv * pgsz)));
+                    cols.push(("alloc", mb(sw.alloc * pgsz)));
+                    cols.push(("avail", mb(sw.avail * pgsz)));
+                    cols.push(("sfree", mb(sw.free * pgsz)));
+                }
+
+                /*
+                 * Show how much remains on the swap devices themselves, which
+                 * is what runs out first once memory is exhausted and pages
+                 * must be written out.
+                 */
+                if let Ok(s) = source.swap() {
+                    cols.push(("sdev", mb(s.device_free() * pgsz)));
+                }
+
+                /*
+                 * The page scanner only runs once free memory is short, so any
+                 * scanning at all means the system has begun to reclaim pages.
+                 * Anonymous page-outs then show when that reclaim has reached
+                 * memory like ours, which can only be written to swap.
+                 * File page-ins, on the other hand, show cached data being
+                 * read back after eviction.
+                 */
+                if let Ok(v) = source.vm() {
+                    if let Some(prev) = &vm {
+                        let d = v.since(prev);
+                        let nsec = v.snaptime.saturating_sub(prev.snaptime);
+                        for (n, v) in [
+                            ("scan", d.scan),
+                            ("apgi", d.anonpgin),
+                            ("apgo", d.anonpgout),
+                            ("swpi", d.pgswapin),
+                            ("swpo", d.pgswapout),
+                            ("pgin", d.pgpgin),
+                            ("pgout", d.pgpgout),
+                            ("fpgi", d.fspgin),
+                            ("fpgo", d.fspgout),
+                        ] {
+                            let rate = kstat::rate(v, nsec).unwrap_or(0.0);
+                            cols.push((n, format!("{rate:7.0}")));
+                        }
+                    }
+                    vm = Some(v);
+                }
+
+                /*
+                 * If our pressure is squeezing the ARC, its hit ratio will
+                 * fall as reads that it used to satisfy go to disk.
+                 */
+                if let Ok(Some(d)) = source.rates(&mut arcrates) {
+                    if let Some(v) = hit_ratio(&d) {
+                        cols.push(("hit", v));
+                    }
+                }
+
+                /*
+                 * As the ARC shrinks, blocks it would have kept must be read
+                 * from the pool again, and prefetched blocks are more often
+                 * evicted before they are used.
+                 */
+                if let Ok(Some(d)) = source.rates(&mut zfetchrates) {
+                    if let Some(v) = hit_ratio(&d) {
+                        cols.push(("pfh", v));
+                    }
+                }
+                if let Ok(cur) = source.pools() {
+                    let (mut rd, mut wr) = (0.0, 0.0);
+                    for p in &cur {
+                        let r = pools
+                            .iter()
+                            .find(|e| e.name == p.name)
+                            .and_then(|e| p.since(e));
+                        if let Some(r) = r {
+                            rd += r.kread / 1024.0;
+                            wr += r.kwritten / 1024.0;
+                        }
                     }
+                    if !pools.is_empty() {
+                        cols.push(("prd", format!("{rd:7.1}")));
+                        cols.push(("pwr", format!("{wr:7.1}")));
+                    }
+                    pools = cur;
+                }
 
-                    availrmem = ks.data_u64(STAT_AVAILRMEM).unwrap_or(0);
-                    freemem = ks.data_u64(STAT_FREEMEM).unwrap_or(0);
-                    break;
+                /*
+                 * In a zone with a memory cap, the cap is reached long before
+                 * the system runs short of memory, so show how close we are.
+                 */
+                if let Ok(z) = source.zone() {
+                    if z.id != 0 {
+                        cols.push(("zrss", mb(z.rss)));
+                        if let Some(cap) = z.physcap {
+                            cols.push(("zcap", mb(cap)));
+                            cols.push(("zfree", mb(cap.saturating_sub(z.rss))));
+                        }
+                        cols.push(("zswap", mb(z.swap)));
+                        if let Some(cap) = z.swapcap {
+                            cols.push(("zscap", mb(cap)));
+                            cols.push((
+                                "zsfree",
+                                mb(cap.saturating_sub(z.swap)),
+                            ));
+                        }
+                    }
+                }
+
+                /*
+                 * Socket buffers are kernel memory too, and can compete with
+                 * us for it when an experiment also drives network traffic.
+                 */
+                let shown = fm.columns();
+                if ["tcpe", "udpo", "strm"].iter().any(|c| shown.contains(c)) {
+                    if let Ok(n) = source.net() {
+                        let t = Instant::now();
+                        cols.push(("tcpe", format!("{:7}", n.tcp_estab)));
+                        if let Some((prev, pt)) = &net {
+                            let d = n
+                                .udp_overflows
+                                .saturating_sub(prev.udp_overflows);
+                            let secs = t.duration_since(*pt).as_secs_f64();
+                            cols.push((
+                                "udpo",
+                                format!("{:7.0}", d as f64 / secs),
+                            ));
+                        }
+                        cols.push(("strm", mb(n.streams)));
+                        net = Some((n, t));
+                    }
+                }
+
+                /*
+                 * While paused we still take samples, so that the rates are
+                 * correct again as soon as the line resumes.
+                 */
+                if fm.paused() {
+                    continue;
                 }
 
                 let mut out = now.format("%H:%M:%S%.3fZ").to_string();
-                for (n, v, p) in [
-                    ("c", arc_c, false),
-                    ("min", arc_c_min, false),
-                    ("max", arc_c_max, false),
-                    ("free", freemem, true),
-                    ("avrm", availrmem, true),
-                ] {
-                    let v =
-                        if p { v * 4096 } else { v } as f64 / 1024.0 / 1024.0;
-                    out.push_str(&format!(" {n} {v:7.1}"));
+                for name in fm.columns() {
+                    if let Some((n, v)) = cols.iter().find(|(n, _)| *n == name)
+                    {
+                        out.push_str(&format!(" {n} {v}"));
+                    }
                 }
 
                 if ed.log(&format!("{out}")).is_err() {
@@ -162,86 +753,87 @@ fn main() -> Result<()> {
         })
         .unwrap();
 
-    let mut allocs: Vec<Vec<u8>> = Vec::new();
+    /*
+     * Now that every thread we start up front has a stack, lock whatever we
+     * have mapped.  Locking faults in every page first, including the code
+     * the timer and editor threads are going to need, so that neither waits
+     * on a page-in while the system is short of memory.
+     */
+    if mat.opt_present("protect-self") {
+        let start = Instant::now();
+        let before = stats::availrmem();
+        match region::lock_self() {
+            Ok(()) => {
+                let after = stats::availrmem();
+                let own =
+                    before.zip(after).map_or(0, |(b, a)| b.saturating_sub(a));
+                fm0.locked_all(own as usize, false);
+                ed0.log(&format!(
+                    "locked fillmem's own memory in {} msec",
+                    start.elapsed().as_millis()
+                ))?
+            }
+            Err(e) => ed0.log(&format!("could not protect fillmem: {e}"))?,
+        }
+    }
 
-    let ed = ed0;
-    let fm = fm0;
-    'cmd: loop {
-        match rx.recv().unwrap() {
-            Activity::Line(Line::Line(l)) => {
-                let t = l.split_whitespace().collect::<Vec<_>>();
-
-                match t.get(0) {
-                    Some(&"touch") => {
-                        let start = Instant::now();
-                        let mut sz: u64 = 0;
-                        let mut c: u64 = 0;
-                        for a in allocs.iter_mut() {
-                            for i in 0..a.len() {
-                                a[i] += 1;
-                                sz += 1;
-
-                                c += 1;
-                                if c % 10000 == 0 {
-                                    if ed.take_ctrlc() {
-                                        ed.log("interrupted!")?;
-                                        fm.unbusy();
-                                        continue 'cmd;
-                                    }
-                                }
-                            }
-                        }
+    /*
+     * Make sure the terminal is restored even if a command fails.
+     */
+    let res = command_loop(&ed0, &fm0, tx0, rx, &mut settings);
+    ed0.cleanup();
+    res
+}
 
-                        let dur = Instant::now()
-                            .checked_duration_since(start)
-                            .unwrap();
-                        let mb = sz / 1024 / 1024;
-                        ed.log(&format!(
-                            "touched {mb} megabytes in {} msec",
-                            dur.as_millis()
-                        ))?;
-                    }
-                    Some(&"grow") => match t.get(1) {
-                        Some(megs) => match megs.parse::<usize>() {
-                            Ok(megs) => {
-                                fm.inner.0.lock().unwrap().busy = true;
-                                fm.inner.1.notify_all();
-
-                                let start = Instant::now();
-                                let sz = megs * 1024 * 1024;
-                                let mut c: u64 = 0;
-                                let mut a = Vec::with_capacity(sz);
-                                while a.len() < sz {
-                                    a.push(b'A');
-
-                                    c += 1;
-                                    if c % 10000 == 0 {
-                                        if ed.take_ctrlc() {
-                                            ed.log("interrupted!")?;
-                                            fm.unbusy();
-                                            continue 'cmd;
-                                        }
-                                    }
-                                }
-                                allocs.push(a);
-
-                                let dur = Instant::now()
-                                    .checked_duration_since(start)
-                                    .unwrap();
-                                ed.log(&format!(
-                                    "grew by {megs} megabytes in {} msec",
-                                    dur.as_millis()
-                                ))?;
-                            }
-                            Err(e) => ed.log(&e.to_string())?,
-                        },
-                        None => ed.log("grow by how much?")?,
-                    },
-                    Some(other) => {
-                        ed.log(&format!("{other:?} not understood"))?;
-                    }
-                    None => (),
+fn command_loop(
+    ed: &Arc<term::Term>,
+    fm: &Arc<FillMem>,
+    tx: mpsc::Sender<Activity>,
+    rx: mpsc::Receiver<Activity>,
+    settings: &mut Settings,
+) -> Result<()> {
+    let mut c = cmd::Ctx {
+        ed,
+        fm,
+        settings,
+        allocs: Default::default(),
+        snapshots: None,
+        hold: None,
+        ramp: None,
+        oscillation: None,
+        stack: None,
+        locked_all: false,
+        children: Vec::new(),
+        workers: Vec::new(),
+        tx,
+        last_job: 0,
+        last_grow: None,
+        deadline: None,
+    };
+
+    loop {
+        match rx.recv().unwrap() {
+            Activity::Line(Line::Line(l)) => match parse::tokenize(&l) {
+                Ok(words) => {
+                    let t =
+                        words.iter().map(String::as_str).collect::<Vec<_>>();
+                    cmd::dispatch(&mut c, &t)?;
                 }
+                Err(e) => ed.log(&e.to_string())?,
+            },
+            Activity::Grown(job, spec, res) => {
+                /*
+                 * The editor is not waiting on a background job, so there is
+                 * nobody to wake up.
+                 */
+                cmd::job_done(&mut c, job, *spec, *res)?;
+                fm.record_allocs(c.allocs.info());
+                continue;
+            }
+            Activity::Freed(job, r) => {
+                cmd::released(&mut c, Some(job), *r)?;
+                fm.record_allocs(c.allocs.info());
+                continue;
             }
             Activity::Line(Line::End) => {
                 ed.log(" * end!")?;
@@ -253,9 +845,24 @@ fn main() -> Result<()> {
             }
         }
 
+        fm.record_allocs(c.allocs.info());
         fm.unbusy();
     }
 
-    ed.cleanup();
     Ok(())
 }
+
+/// Format the hit ratio from the change in a pair of hits and misses counters
+/// as a statistics column, or "-" if there were no accesses at all.
+fn hit_ratio(d: &[Option<kstat::Delta>]) -> Option<String> {
+    let [Some(hits), Some(misses)] = d[..] else {
+        return None;
+    };
+
+    let total = hits.delta + misses.delta;
+    Some(if total == 0 {
+        format!("{:>7}", "-")
+    } else {
+        format!("{:7.1}", 100.0 * hits.delta as f64 / total as f64)
+    })
+}
@@ -0,0 +1,144 @@
+/*
+ * A breakdown of physical memory by use, in the style of "::memstat" in
+ * mdb(1).  The debugger walks every page in the system to classify it, which
+ * is too slow to do while the system is under pressure.  Instead we use the
+ * counters the kernel keeps as it goes: pages allocated to the kernel, the
+ * size of the ARC, and free memory.  What remains is in use by processes and
+ * the page cache, which cannot be told apart without a page walk.  Anonymous
+ * memory reservations are read from the kernel with libkvm(3LIB), if we have
+ * the privilege to do so.
+ */
+
+use anyhow::{bail, Result};
+
+use crate::kstat::{consts::*, KstatWrapper};
+use crate::kvm::Kvm;
+
+/// Page counts for each kind of use.
+pub struct Memstat {
+    pub pagesize: u64,
+    pub physmem: u64,
+    /// Pages allocated to the kernel, not including the ARC.
+    pub kernel: u64,
+    /// Pages holding ZFS file data and metadata in the ARC.
+    pub zfs: u64,
+    /// Pages in use by processes (anonymous memory, executables and
+    /// libraries) and by the page cache.
+    pub user: u64,
+    pub free: u64,
+    /// Anonymous memory reserved against swap, or an explanation of why it
+    /// could not be read.
+    pub anon: Result<Anon>,
+}
+
+/// From "k_anoninfo" in the kernel, in pages.
+pub struct Anon {
+    /// Reserved against disk swap.
+    pub disk: u64,
+    /// Reserved against physical memory, when disk swap is exhausted.
+    pub mem: u64,
+    /// Disk swap slots allocated to anonymous pages that have been paged out
+    /// or that may be in the future.
+    pub allocated: u64,
+}
+
+/*
+ * From <vm/anon.h>:
+ */
+#[repr(C)]
+#[derive(Default)]
+struct k_anoninfo {
+    ani_max: usize,
+    ani_free: usize,
+    ani_phys_resv: usize,
+    ani_mem_resv: usize,
+    ani_locked_swap: usize,
+}
+
+fn anon() -> Result<Anon> {
+    let kvm = Kvm::new_readonly()?;
+    let addr = kvm.locate("k_anoninfo")?;
+
+    let mut buf = [0u8; std::mem::size_of::<k_anoninfo>()];
+    kvm.read_buf(addr, &mut buf)?;
+    let ai: k_anoninfo =
+        unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const _) };
+
+    Ok(Anon {
+        disk: ai.ani_phys_resv as u64,
+        mem: ai.ani_mem_resv as u64,
+        allocated: ai.ani_max.saturating_sub(ai.ani_free) as u64,
+    })
+}
+
+pub fn read() -> Result<Memstat> {
+    let ks = KstatWrapper::open()?;
+
+    let Some(k) = ks.lookup(Some(MODULE_UNIX), Some(NAME_SYSTEM_PAGES)) else {
+        bail!("system pages kstat not available");
+    };
+    let (Some(physmem), Some(freemem), Some(pp_kernel)) = (
+        k.data_ulong(STAT_PHYSMEM),
+        k.data_ulong(STAT_FREEMEM),
+        k.data_ulong(STAT_PP_KERNEL),
+    ) else {
+        bail!("system pages kstat incomplete");
+    };
+
+    /*
+     * The ARC is allocated from the kernel heap, so it is counted in the
+     * kernel's pages as well.  Systems without ZFS have no arcstats.
+     */
+    let pagesize = crate::region::pagesize() as u64;
+    let arc = ks
+        .lookup(Some(MODULE_ZFS), Some(NAME_ARCSTATS))
+        .and_then(|k| k.data_u64(STAT_SIZE))
+        .unwrap_or(0);
+    let zfs = arc.div_ceil(pagesize).min(pp_kernel);
+
+    Ok(Memstat {
+        pagesize,
+        physmem,
+        kernel: pp_kernel - zfs,
+        zfs,
+        user: physmem.saturating_sub(pp_kernel).saturating_sub(freemem),
+        free: freemem,
+        anon: anon(),
+    })
+}
+
+impl Memstat {
+    /// Format the breakdown as a table like that printed by mdb.
+    pub fn lines(&self) -> Vec<String> {
+        let row = |name: &str, pages: u64| {
+            format!(
+                "{name:<24} {pages:>12} {:>12} {:>4}%",
+                pages * self.pagesize / 1024 / 1024,
+                (pages * 100).checked_div(self.physmem).unwrap_or(0),
+            )
+        };
+
+        let mut out = vec![
+            format!(
+                "{:<24} {:>12} {:>12} {:>5}",
+                "Page Summary", "Pages", "MB", "%Tot"
+            ),
+            row("Kernel", self.kernel),
+            row("ZFS File Data", self.zfs),
+            row("Anon/Exec/Page cache", self.user),
+            row("Free", self.free),
+            row("Total", self.physmem),
+        ];
+
+        match &self.anon {
+            Ok(a) => {
+                out.push(row("Anon reserved (disk)", a.disk));
+                out.push(row("Anon reserved (memory)", a.mem));
+                out.push(row("Anon on disk swap", a.allocated));
+            }
+            Err(e) => out.push(format!("cannot read anon reservations: {e}")),
+        }
+
+        out
+    }
+}
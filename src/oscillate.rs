@@ -0,0 +1,142 @@
+/*
+ * Allocating and releasing the same amount of memory over and over.  In each
+ * period we grow for the first half and release it for the second, so that
+ * the system sees a repeated swing in demand.  This is useful for studying
+ * how quickly the ARC and the kernel cage give memory up and take it back.
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+
+use crate::grow;
+use crate::parse;
+use crate::term::Term;
+
+/// How often to check whether we should stop while waiting between swings.
+const POLL: Duration = Duration::from_millis(100);
+
+/// Swing by size bytes once per period until stopped returns true, logging
+/// each cycle with the given prefix.  Returns the number of cycles completed.
+pub fn run(
+    ed: &Term,
+    prefix: &str,
+    size: usize,
+    period: Duration,
+    stopped: &dyn Fn() -> bool,
+    cycles: &AtomicUsize,
+) -> Result<usize> {
+    if period.is_zero() {
+        bail!("oscillate period must be non-zero");
+    }
+
+    /*
+     * Wait until the given time, unless we are asked to stop first.
+     */
+    let wait = |until: Instant| -> bool {
+        loop {
+            if stopped() {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= until {
+                return true;
+            }
+            std::thread::sleep(POLL.min(until - now));
+        }
+    };
+
+    loop {
+        let start = Instant::now();
+        let Some(g) = grow::Spec::new(size).run(stopped)? else {
+            break;
+        };
+        if g.partial {
+            break;
+        }
+
+        if !wait(start + period / 2) {
+            break;
+        }
+
+        let freeing = Instant::now();
+        drop(g.region);
+        let n = cycles.fetch_add(1, Ordering::Relaxed) + 1;
+        ed.log(&format!(
+            "{prefix}cycle {n}: grew by {} in {} msec, released in {} msec",
+            parse::megabytes(size as u64),
+            g.dur.as_millis(),
+            freeing.elapsed().as_millis()
+        ))?;
+
+        if !wait(start + period) {
+            break;
+        }
+    }
+
+    Ok(cycles.load(Ordering::Relaxed))
+}
+
+/// An oscillation running in the background.
+pub struct Oscillation {
+    pub job: usize,
+    pub size: usize,
+    pub period: Duration,
+    pub since: DateTime<Utc>,
+    cycles: Arc<AtomicUsize>,
+    /*
+     * The thread exits, releasing any memory it holds, once this channel is
+     * closed.
+     */
+    _stop: mpsc::Sender<()>,
+}
+
+impl Oscillation {
+    pub fn start(
+        ed: Arc<Term>,
+        job: usize,
+        size: usize,
+        period: Duration,
+    ) -> Result<Oscillation> {
+        if period.is_zero() {
+            bail!("oscillate period must be non-zero");
+        }
+
+        let cycles = Arc::new(AtomicUsize::new(0));
+        let (stop, rx) = mpsc::channel::<()>();
+
+        let c = Arc::clone(&cycles);
+        std::thread::Builder::new().name(format!("oscillate-{job}")).spawn(
+            move || {
+                let stopped = || {
+                    matches!(
+                        rx.try_recv(),
+                        Err(mpsc::TryRecvError::Disconnected)
+                    )
+                };
+
+                let prefix = format!("job {job}: ");
+                if let Err(e) = run(&ed, &prefix, size, period, &stopped, &c) {
+                    ed.log(&format!("{prefix}{e}")).ok();
+                }
+            },
+        )?;
+
+        Ok(Oscillation {
+            job,
+            size,
+            period,
+            since: Utc::now(),
+            cycles,
+            _stop: stop,
+        })
+    }
+
+    /// The number of cycles completed so far.
+    pub fn cycles(&self) -> usize {
+        self.cycles.load(Ordering::Relaxed)
+    }
+}
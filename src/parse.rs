@@ -0,0 +1,438 @@
+/*
+ * Parsing of the commands typed at the prompt, and of the sizes and periods of
+ * time given as arguments to them.  Everything here is a pure function of its
+ * input, so that the interactive layer's behaviour can be pinned down by tests.
+ */
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+/// Split a command line into words at whitespace.  A word may be quoted with
+/// single or double quotes in order to include whitespace, and a backslash
+/// outside single quotes escapes the next character.
+pub fn tokenize(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.get_or_insert_with(String::new).push(c),
+            (_, '\\') => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => bail!("backslash at end of line"),
+            },
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if let Some(q) = quote {
+        bail!("unterminated {q} quote");
+    }
+    words.extend(word.take());
+
+    Ok(words)
+}
+
+/// A limit on the physical memory that sizes given as a percentage are taken
+/// from, e.g., the physical memory cap of the zone, or zero for none.
+static PHYSMEM_LIMIT: AtomicU64 = AtomicU64::new(0);
+
+/// Take sizes given as a percentage from no more than the given amount of
+/// memory, in bytes, rather than from all of physical memory.
+pub fn set_physmem_limit(limit: Option<u64>) {
+    PHYSMEM_LIMIT.store(limit.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Facts about the system needed to interpret some sizes.
+#[derive(Clone, Copy, Debug)]
+pub struct SizeContext {
+    /// Physical memory in bytes, or the limit set with set_physmem_limit(),
+    /// for sizes given as a percentage.
+    pub physmem: u64,
+    /// Page size in bytes, for sizes given as a count of pages.
+    pub pagesize: u64,
+}
+
+impl SizeContext {
+    /// Determine the physical memory and page size of this system.
+    pub fn system() -> SizeContext {
+        let pagesize =
+            unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as u64;
+        let pages =
+            unsafe { libc::sysconf(libc::_SC_PHYS_PAGES) }.max(0) as u64;
+
+        let physmem = pages.saturating_mul(pagesize);
+        let limit = match PHYSMEM_LIMIT.load(Ordering::Relaxed) {
+            0 => None,
+            limit => Some(limit),
+        };
+
+        SizeContext { physmem, pagesize }.limited(limit)
+    }
+
+    /// Take sizes given as a percentage from no more than the given amount of
+    /// memory, in bytes.
+    fn limited(self, limit: Option<u64>) -> SizeContext {
+        SizeContext {
+            physmem: limit.map_or(self.physmem, |l| self.physmem.min(l)),
+            ..self
+        }
+    }
+}
+
+/// A single number with an optional unit, as part of a size expression.
+struct Factor {
+    value: u64,
+    /// Whether the factor was given in units of bytes, rather than as a
+    /// plain number.
+    sized: bool,
+}
+
+fn factor(s: &str, ctx: &SizeContext) -> Result<Factor> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+
+    if num.is_empty() {
+        bail!("expected a number, not {s:?}");
+    }
+    let Ok(num) = num.parse::<u64>() else {
+        bail!("number {num:?} is too large");
+    };
+
+    let (value, sized) = match unit {
+        "" => (Some(num), false),
+        "b" => (Some(num), true),
+        "k" => (num.checked_mul(1 << 10), true),
+        "m" => (num.checked_mul(1 << 20), true),
+        "g" => (num.checked_mul(1 << 30), true),
+        "t" => (num.checked_mul(1 << 40), true),
+        "p" => (num.checked_mul(ctx.pagesize), true),
+        "%" => {
+            ((ctx.physmem as u128 * num as u128 / 100).try_into().ok(), true)
+        }
+        other => bail!("unknown unit {other:?} in {s:?}"),
+    };
+
+    match value {
+        Some(value) => Ok(Factor { value, sized }),
+        None => bail!("{s:?} is too large"),
+    }
+}
+
+/// Parse a size in bytes.  A size is an expression of terms added or
+/// subtracted, e.g., "4g+512m".  Each term is a number with an optional unit,
+/// or several multiplied together, e.g., "3*2g".  The units are:
+///
+///  b          bytes
+///  k, m, g, t kilobytes, megabytes, gigabytes, terabytes
+///  p          pages
+///  %          percent of physical memory
+///
+/// A term without any unit is a count of megabytes.
+pub fn size(s: &str, ctx: &SizeContext) -> Result<u64> {
+    let expr = s.trim().to_ascii_lowercase();
+    if expr.is_empty() {
+        bail!("size must not be empty");
+    }
+
+    let mut total: i128 = 0;
+    let mut sign = 1;
+    let mut rest = expr.as_str();
+    loop {
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let (term, next) = rest.split_at(end);
+        if term.is_empty() {
+            bail!("invalid size {s:?}: missing term");
+        }
+
+        let mut value: u64 = 1;
+        let mut sized = false;
+        for f in term.split('*') {
+            let f = match factor(f, ctx) {
+                Ok(f) => f,
+                Err(e) => bail!("invalid size {s:?}: {e}"),
+            };
+            if f.sized && sized {
+                bail!("invalid size {s:?}: cannot multiply two sizes");
+            }
+            sized |= f.sized;
+            let Some(v) = value.checked_mul(f.value) else {
+                bail!("invalid size {s:?}: too large");
+            };
+            value = v;
+        }
+        if !sized {
+            /*
+             * A plain number is a count of megabytes.
+             */
+            let Some(v) = value.checked_mul(1 << 20) else {
+                bail!("invalid size {s:?}: too large");
+            };
+            value = v;
+        }
+
+        total += sign * value as i128;
+
+        let mut next = next.chars();
+        match next.next() {
+            None => break,
+            Some('+') => sign = 1,
+            Some(_) => sign = -1,
+        }
+        rest = next.as_str();
+    }
+
+    if total < 0 {
+        bail!("invalid size {s:?}: result is negative");
+    }
+    match u64::try_from(total) {
+        Ok(total) => Ok(total),
+        Err(_) => bail!("invalid size {s:?}: too large"),
+    }
+}
+
+/// Describe a size in bytes as a count of megabytes, with one decimal place
+/// only when it is not a whole number, e.g., "512 megabytes".
+pub fn megabytes(sz: u64) -> String {
+    if sz.is_multiple_of(1 << 20) {
+        format!("{} megabytes", sz >> 20)
+    } else {
+        format!("{:.1} megabytes", sz as f64 / (1 << 20) as f64)
+    }
+}
+
+/// Parse a period of time, where a bare number is a count of seconds and an
+/// "ms", "s", "m", or "h" suffix selects the unit explicitly.
+pub fn duration(s: &str) -> Result<Duration> {
+    let s = s.trim().to_ascii_lowercase();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+
+    if num.is_empty() {
+        bail!("invalid period {s:?}: expected a number");
+    }
+    let Ok(num) = num.parse::<u64>() else {
+        bail!("invalid period {s:?}: too large");
+    };
+
+    Ok(match unit {
+        "ms" => Duration::from_millis(num),
+        "" | "s" => Duration::from_secs(num),
+        "m" => Duration::from_secs(num.saturating_mul(60)),
+        "h" => Duration::from_secs(num.saturating_mul(3600)),
+        _ => bail!("invalid period {s:?}: unknown unit {unit:?}"),
+    })
+}
+
+/// The slowest rate, in MB/s, that may be asked for.  Anything slower would
+/// take minutes to get through a single chunk, and is not a rate at all so much
+/// as a very long pause.
+pub const MIN_RATE: f64 = 0.01;
+
+/// Parse a rate in megabytes per second, e.g., "4" or "0.5".
+pub fn rate(s: &str) -> Result<f64> {
+    match s.trim().parse::<f64>() {
+        Ok(v) if v.is_finite() && v >= MIN_RATE => Ok(v),
+        Ok(_) => bail!("invalid rate {s:?}: must be at least {MIN_RATE} MB/s"),
+        Err(_) => bail!("invalid rate {s:?}: expected a number"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CTX: SizeContext = SizeContext { physmem: 16 << 30, pagesize: 4096 };
+
+    fn err(s: &str) -> String {
+        size(s, &CTX).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn tokenize_words() {
+        assert_eq!(tokenize("").unwrap(), Vec::<String>::new());
+        assert_eq!(tokenize("   ").unwrap(), Vec::<String>::new());
+        assert_eq!(tokenize("grow 100").unwrap(), ["grow", "100"]);
+        assert_eq!(tokenize("  grow\t100  ").unwrap(), ["grow", "100"]);
+    }
+
+    #[test]
+    fn tokenize_quotes() {
+        assert_eq!(
+            tokenize("kstat dump > \"my file\"").unwrap(),
+            ["kstat", "dump", ">", "my file"]
+        );
+        assert_eq!(tokenize("a 'b \"c\" d'").unwrap(), ["a", "b \"c\" d"]);
+        assert_eq!(tokenize("a''b").unwrap(), ["ab"]);
+        assert_eq!(tokenize("''").unwrap(), [""]);
+        assert_eq!(tokenize(r"a\ b c").unwrap(), ["a b", "c"]);
+        assert_eq!(tokenize(r#""a\"b""#).unwrap(), ["a\"b"]);
+        assert_eq!(tokenize(r"'a\b'").unwrap(), [r"a\b"]);
+    }
+
+    #[test]
+    fn tokenize_malformed() {
+        assert_eq!(
+            tokenize("grow \"100").unwrap_err().to_string(),
+            "unterminated \" quote"
+        );
+        assert_eq!(
+            tokenize("'x").unwrap_err().to_string(),
+            "unterminated ' quote"
+        );
+        assert_eq!(
+            tokenize("grow \\").unwrap_err().to_string(),
+            "backslash at end of line"
+        );
+    }
+
+    #[test]
+    fn size_suffixes() {
+        assert_eq!(size("100", &CTX).unwrap(), 100 << 20);
+        assert_eq!(size("100b", &CTX).unwrap(), 100);
+        assert_eq!(size("4k", &CTX).unwrap(), 4 << 10);
+        assert_eq!(size("512m", &CTX).unwrap(), 512 << 20);
+        assert_eq!(size("4g", &CTX).unwrap(), 4 << 30);
+        assert_eq!(size("4G", &CTX).unwrap(), 4 << 30);
+        assert_eq!(size("2t", &CTX).unwrap(), 2 << 40);
+        assert_eq!(size("100000p", &CTX).unwrap(), 100000 * 4096);
+        assert_eq!(size(" 1g ", &CTX).unwrap(), 1 << 30);
+        assert_eq!(size("0", &CTX).unwrap(), 0);
+    }
+
+    #[test]
+    fn size_percentages() {
+        assert_eq!(size("25%", &CTX).unwrap(), 4 << 30);
+        assert_eq!(size("100%", &CTX).unwrap(), 16 << 30);
+        assert_eq!(size("150%", &CTX).unwrap(), 24 << 30);
+        assert_eq!(size("0%", &CTX).unwrap(), 0);
+    }
+
+    #[test]
+    fn size_expressions() {
+        assert_eq!(size("1g+512m", &CTX).unwrap(), (1 << 30) + (512 << 20));
+        assert_eq!(size("1g-512m", &CTX).unwrap(), 512 << 20);
+        assert_eq!(size("3*2g", &CTX).unwrap(), 6 << 30);
+        assert_eq!(size("2g*3", &CTX).unwrap(), 6 << 30);
+        assert_eq!(size("2*3", &CTX).unwrap(), 6 << 20);
+        assert_eq!(size("50%-1g", &CTX).unwrap(), 7 << 30);
+        assert_eq!(size("1+1+1", &CTX).unwrap(), 3 << 20);
+        assert_eq!(size("1g-2g+2g", &CTX).unwrap(), 1 << 30);
+    }
+
+    #[test]
+    fn size_malformed() {
+        assert_eq!(err(""), "size must not be empty");
+        assert_eq!(
+            err("lots"),
+            "invalid size \"lots\": expected a number, not \"lots\""
+        );
+        assert_eq!(
+            err("12q"),
+            "invalid size \"12q\": unknown unit \"q\" in \"12q\""
+        );
+        assert_eq!(
+            err("1.5g"),
+            "invalid size \"1.5g\": unknown unit \".5g\" in \"1.5g\""
+        );
+        assert_eq!(err("1g+"), "invalid size \"1g+\": missing term");
+        assert_eq!(err("+1g"), "invalid size \"+1g\": missing term");
+        assert_eq!(
+            err("1g**2"),
+            "invalid size \"1g**2\": expected a number, not \"\""
+        );
+        assert_eq!(
+            err("1g*1g"),
+            "invalid size \"1g*1g\": cannot multiply two sizes"
+        );
+        assert_eq!(err("1g-2g"), "invalid size \"1g-2g\": result is negative");
+        assert_eq!(err("-1"), "invalid size \"-1\": missing term");
+    }
+
+    #[test]
+    fn size_overflow() {
+        assert_eq!(
+            err("99999999999999999999"),
+            "invalid size \"99999999999999999999\": \
+            number \"99999999999999999999\" is too large"
+        );
+        assert_eq!(
+            err("99999999t"),
+            "invalid size \"99999999t\": \"99999999t\" is too large"
+        );
+        assert_eq!(
+            err("99999999999999"),
+            "invalid size \"99999999999999\": too large"
+        );
+        assert_eq!(
+            err("16777215t+16777215t"),
+            "invalid size \"16777215t+16777215t\": too large"
+        );
+    }
+
+    #[test]
+    fn megabytes_whole_and_fractional() {
+        assert_eq!(megabytes(0), "0 megabytes");
+        assert_eq!(megabytes(512 << 20), "512 megabytes");
+        assert_eq!(megabytes(100000 * 4096), "390.6 megabytes");
+        assert_eq!(megabytes(1 << 19), "0.5 megabytes");
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(duration("60").unwrap(), Duration::from_secs(60));
+        assert_eq!(duration("60s").unwrap(), Duration::from_secs(60));
+        assert_eq!(duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(duration("2h").unwrap(), Duration::from_secs(7200));
+        assert_eq!(
+            duration("").unwrap_err().to_string(),
+            "invalid period \"\": expected a number"
+        );
+        assert_eq!(
+            duration("5d").unwrap_err().to_string(),
+            "invalid period \"5d\": unknown unit \"d\""
+        );
+        assert_eq!(
+            duration("s").unwrap_err().to_string(),
+            "invalid period \"s\": expected a number"
+        );
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(rate("4").unwrap(), 4.0);
+        assert_eq!(rate("0.5").unwrap(), 0.5);
+        assert_eq!(rate("0.01").unwrap(), 0.01);
+        assert_eq!(
+            rate("fast").unwrap_err().to_string(),
+            "invalid rate \"fast\": expected a number"
+        );
+        for s in ["0", "-1", "1e-300", "inf", "NaN"] {
+            assert_eq!(
+                rate(s).unwrap_err().to_string(),
+                format!("invalid rate {s:?}: must be at least 0.01 MB/s")
+            );
+        }
+    }
+
+    #[test]
+    fn physmem_limit() {
+        let ctx = CTX.limited(Some(1 << 30));
+        assert_eq!(ctx.pagesize, CTX.pagesize);
+        assert_eq!(size("50%", &ctx).unwrap(), 512 << 20);
+        assert_eq!(size("50%", &CTX.limited(None)).unwrap(), 8 << 30);
+        assert_eq!(size("50%", &CTX.limited(Some(64 << 30))).unwrap(), 8 << 30);
+    }
+}
@@ -0,0 +1,193 @@
+/*
+ * The data with which new allocations are filled.  The choice matters for
+ * experiments with ZFS compression and deduplication of anything that is
+ * paged out, and for whether the system can share or elide zero pages.
+ */
+
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+
+/// Compressible, but not so trivially as a single repeated byte.
+const TEXT: &[u8] = b"It was a bright cold day in April, and the clocks were \
+    striking thirteen.  ";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    /// Every byte is "A"; the historical behaviour.
+    A,
+    Zero,
+    /// Repeated English text.
+    Text,
+    /// Pseudo-random data from the given seed.  The same seed always produces
+    /// the same contents, so separate allocations can be deduplicated.
+    Random(u64),
+    /// Pseudo-random data from a seed chosen afresh for each allocation, so
+    /// that nothing can be compressed or deduplicated.
+    Incompressible(u64),
+}
+
+impl Pattern {
+    /// Fill buf, which begins offset bytes into the allocation.  The contents
+    /// depend only on the position within the allocation, so a region may be
+    /// filled in pieces, and by several threads, with the same result.
+    pub fn fill(&self, buf: &mut [u8], offset: usize) {
+        match self {
+            Pattern::A => buf.fill(b'A'),
+            Pattern::Zero => buf.fill(0),
+            Pattern::Text => {
+                /*
+                 * Copy as much of the text as fits at a time, rather than
+                 * looking up each byte.
+                 */
+                let mut i = 0;
+                while i < buf.len() {
+                    let o = (offset + i) % TEXT.len();
+                    let n = (TEXT.len() - o).min(buf.len() - i);
+                    buf[i..i + n].copy_from_slice(&TEXT[o..o + n]);
+                    i += n;
+                }
+            }
+            Pattern::Random(seed) | Pattern::Incompressible(seed) => {
+                /*
+                 * Each 8 byte word of the allocation gets its own value from
+                 * the generator.  The buffer may begin or end part way through
+                 * a word, so deal with any partial words one byte at a time,
+                 * and store whole words in between.
+                 */
+                let head = ((8 - offset % 8) % 8).min(buf.len());
+                let (head, rest) = buf.split_at_mut(head);
+                for (i, b) in head.iter_mut().enumerate() {
+                    *b = random_byte(*seed, offset + i);
+                }
+
+                let offset = offset + head.len();
+                let whole = rest.len() / 8 * 8;
+                let mut words = rest.chunks_exact_mut(8);
+                for (i, w) in words.by_ref().enumerate() {
+                    let n = (offset / 8 + i) as u64;
+                    w.copy_from_slice(&splitmix64(*seed, n).to_le_bytes());
+                }
+
+                let tail = words.into_remainder();
+                let offset = offset + whole;
+                for (i, b) in tail.iter_mut().enumerate() {
+                    *b = random_byte(*seed, offset + i);
+                }
+            }
+        }
+    }
+
+    /// The same pattern, but with a fresh seed if it is one that should never
+    /// repeat itself.
+    pub fn reseed(&self) -> Pattern {
+        match self {
+            Pattern::Incompressible(_) => Pattern::Incompressible(clock_seed()),
+            other => *other,
+        }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Pattern> {
+        Ok(match s {
+            "a" => Pattern::A,
+            "zero" => Pattern::Zero,
+            "text" => Pattern::Text,
+            "random" => Pattern::Random(1),
+            "incompressible" => Pattern::Incompressible(clock_seed()),
+            other => bail!(
+                "pattern {other:?} must be a, zero, text, random, or \
+                incompressible"
+            ),
+        })
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::A => write!(f, "a"),
+            Pattern::Zero => write!(f, "zero"),
+            Pattern::Text => write!(f, "text"),
+            Pattern::Random(seed) => write!(f, "random seed {seed}"),
+            Pattern::Incompressible(_) => write!(f, "incompressible"),
+        }
+    }
+}
+
+/// A seed taken from the clock, which differs from one call to the next.
+pub fn clock_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// A single byte of a pseudo-random pattern.
+fn random_byte(seed: u64, offset: usize) -> u8 {
+    splitmix64(seed, (offset / 8) as u64).to_le_bytes()[offset % 8]
+}
+
+/// The SplitMix64 generator, evaluated at an arbitrary point in its sequence.
+pub fn splitmix64(seed: u64, n: u64) -> u64 {
+    let mut z =
+        seed.wrapping_add(n.wrapping_add(1).wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fill_in_pieces() {
+        for p in [Pattern::A, Pattern::Text, Pattern::Random(7)] {
+            let mut whole = vec![0u8; 1000];
+            p.fill(&mut whole, 0);
+
+            let mut pieces = vec![0u8; 1000];
+            for (i, chunk) in pieces.chunks_mut(333).enumerate() {
+                p.fill(chunk, i * 333);
+            }
+
+            assert_eq!(whole, pieces, "pattern {p}");
+        }
+    }
+
+    #[test]
+    fn fill_unaligned() {
+        for p in [Pattern::Text, Pattern::Random(7)] {
+            let mut whole = vec![0u8; 1000];
+            p.fill(&mut whole, 0);
+
+            for (start, end) in [(3, 5), (5, 900), (8, 16), (1, 999)] {
+                let mut part = vec![0u8; end - start];
+                p.fill(&mut part, start);
+                assert_eq!(part, whole[start..end], "pattern {p} at {start}");
+            }
+        }
+    }
+
+    #[test]
+    fn random_seeds() {
+        let mut a = vec![0u8; 64];
+        let mut b = vec![0u8; 64];
+        Pattern::Random(1).fill(&mut a, 0);
+        Pattern::Random(1).fill(&mut b, 0);
+        assert_eq!(a, b);
+        Pattern::Random(2).fill(&mut b, 0);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("zero".parse::<Pattern>().unwrap(), Pattern::Zero);
+        assert_eq!("random".parse::<Pattern>().unwrap(), Pattern::Random(1));
+        assert!("stripes".parse::<Pattern>().is_err());
+    }
+}
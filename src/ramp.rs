@@ -0,0 +1,97 @@
+/*
+ * Growing in steps on a timer.  A background thread grows by the same amount
+ * once per interval, reporting each step to the command loop as a background
+ * job, which adds the new region to our allocations just as for "grow &".
+ */
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+
+use crate::grow;
+use crate::Activity;
+
+pub struct Ramp {
+    pub job: usize,
+    pub spec: grow::Spec,
+    pub interval: Duration,
+    /// The number of steps to take, or None to carry on until stopped.
+    pub count: Option<usize>,
+    pub since: DateTime<Utc>,
+    steps: Arc<AtomicUsize>,
+    /*
+     * The thread exits once this channel is closed.
+     */
+    _stop: mpsc::Sender<()>,
+}
+
+impl Ramp {
+    /// Start growing according to spec once per interval, beginning at once.
+    /// Each step is reported through tx as part of the given job.
+    pub fn start(
+        job: usize,
+        spec: grow::Spec,
+        interval: Duration,
+        count: Option<usize>,
+        tx: mpsc::Sender<Activity>,
+    ) -> Result<Ramp> {
+        if interval.is_zero() {
+            bail!("ramp interval must be non-zero");
+        }
+
+        let steps = Arc::new(AtomicUsize::new(0));
+        let (stop, rx) = mpsc::channel::<()>();
+
+        let s = spec.clone();
+        let st = Arc::clone(&steps);
+        std::thread::Builder::new().name(format!("ramp-{job}")).spawn(
+            move || loop {
+                let stopped = || {
+                    matches!(
+                        rx.try_recv(),
+                        Err(mpsc::TryRecvError::Disconnected)
+                    )
+                };
+
+                let res = s.run(&stopped);
+                let failed = !matches!(res, Ok(Some(_)));
+                let n = st.fetch_add(1, Ordering::Relaxed) + 1;
+                if tx
+                    .send(Activity::Grown(
+                        job,
+                        Box::new(s.clone()),
+                        Box::new(res),
+                    ))
+                    .is_err()
+                    || failed
+                    || count.is_some_and(|count| n >= count)
+                {
+                    return;
+                }
+
+                match rx.recv_timeout(interval) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => (),
+                    _ => return,
+                }
+            },
+        )?;
+
+        Ok(Ramp {
+            job,
+            spec,
+            interval,
+            count,
+            since: Utc::now(),
+            steps,
+            _stop: stop,
+        })
+    }
+
+    /// The number of steps taken so far.
+    pub fn steps(&self) -> usize {
+        self.steps.load(Ordering::Relaxed)
+    }
+}
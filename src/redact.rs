@@ -0,0 +1,120 @@
+/*
+ * Redaction of identifying details from output, so that a session may be
+ * shared without revealing anything about the environment in which it runs.
+ */
+
+use crate::sys;
+
+/// Replace each occurrence of word in msg that stands on its own, rather than
+/// as part of a longer word, so that a short name does not mangle commands,
+/// column headings, and numbers that happen to contain it.
+fn replace_word(msg: &str, word: &str, with: &str) -> String {
+    let part = |c: Option<char>| {
+        c.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '-')
+    };
+
+    let mut out = String::with_capacity(msg.len());
+    let mut rest = msg;
+    while let Some(i) = rest.find(word) {
+        let (before, after) = (&rest[..i], &rest[i + word.len()..]);
+        let prev = before.chars().next_back().or(out.chars().next_back());
+        out.push_str(before);
+        if part(prev) || part(after.chars().next()) {
+            out.push_str(word);
+        } else {
+            out.push_str(with);
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+pub struct Redactor {
+    host: Option<String>,
+    zone: Option<String>,
+}
+
+impl Redactor {
+    pub fn new() -> Redactor {
+        Redactor {
+            host: sys::hostname().ok().filter(|h| !h.is_empty()),
+            /*
+             * The global zone has the same name everywhere, so there is
+             * nothing to hide.
+             */
+            zone: sys::zonename().ok().filter(|z| z != "global"),
+        }
+    }
+
+    /// Replace the host name, the zone name, and any absolute paths in the
+    /// message with placeholders.
+    pub fn apply(&self, msg: &str) -> String {
+        let mut msg = msg.to_string();
+        if let Some(host) = &self.host {
+            msg = replace_word(&msg, host, "<host>");
+        }
+        if let Some(zone) = &self.zone {
+            msg = replace_word(&msg, zone, "<zone>");
+        }
+
+        /*
+         * An absolute path begins with a slash at the start of a word, and
+         * runs until the next whitespace or closing delimiter.
+         */
+        let mut out = String::with_capacity(msg.len());
+        let mut in_path = false;
+        let mut prev = None;
+        for c in msg.chars() {
+            if in_path {
+                if c.is_whitespace() || "\"')],:".contains(c) {
+                    in_path = false;
+                } else {
+                    continue;
+                }
+            } else if c == '/'
+                && prev.is_none_or(|p: char| {
+                    p.is_whitespace() || "\"'([=".contains(p)
+                })
+            {
+                in_path = true;
+                out.push_str("<path>");
+                prev = Some(c);
+                continue;
+            }
+
+            out.push(c);
+            prev = Some(c);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn whole_words() {
+        assert_eq!(
+            replace_word("vm vmstat avm", "vm", "<host>"),
+            "<host> vmstat avm"
+        );
+        assert_eq!(replace_word("on vm: 12", "vm", "<host>"), "on <host>: 12");
+        assert_eq!(
+            replace_word("vm.example.com", "vm", "<host>"),
+            "<host>.example.com"
+        );
+        assert_eq!(
+            replace_word("vm-2 vm_2 2vm", "vm", "<host>"),
+            "vm-2 vm_2 2vm"
+        );
+        assert_eq!(replace_word("c1 c", "c", "<host>"), "c1 <host>");
+        assert_eq!(
+            replace_word("swap swapfs", "swap", "<zone>"),
+            "<zone> swapfs"
+        );
+        assert_eq!(replace_word("", "vm", "<host>"), "");
+    }
+}
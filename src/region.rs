@@ -0,0 +1,544 @@
+/*
+ * Memory mappings, used instead of the heap for allocations so that we can
+ * control exactly how the pages are mapped, locked, and released.  Most regions
+ * are anonymous memory, but a region may also be a shared mapping of a file or
+ * of a POSIX shared memory object.
+ */
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::sys;
+
+pub fn pagesize() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Describe a page size briefly, e.g., "4k" or "2m".
+pub fn size_name(sz: usize) -> String {
+    for (shift, unit) in [(30, "g"), (20, "m"), (10, "k")] {
+        if sz >= 1 << shift && sz.is_multiple_of(1 << shift) {
+            return format!("{}{unit}", sz >> shift);
+        }
+    }
+    format!("{sz}b")
+}
+
+/// The access permitted to the pages of a region.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protection {
+    None,
+    ReadOnly,
+    ReadWrite,
+}
+
+impl Protection {
+    fn prot(self) -> libc::c_int {
+        match self {
+            Protection::None => libc::PROT_NONE,
+            Protection::ReadOnly => libc::PROT_READ,
+            Protection::ReadWrite => libc::PROT_READ | libc::PROT_WRITE,
+        }
+    }
+}
+
+impl std::str::FromStr for Protection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Protection> {
+        Ok(match s {
+            "none" => Protection::None,
+            "ro" => Protection::ReadOnly,
+            "rw" => Protection::ReadWrite,
+            other => bail!("protection {other:?} must be none, ro, or rw"),
+        })
+    }
+}
+
+impl std::fmt::Display for Protection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Protection::None => write!(f, "none"),
+            Protection::ReadOnly => write!(f, "ro"),
+            Protection::ReadWrite => write!(f, "rw"),
+        }
+    }
+}
+
+/// Explain why a request to lock memory failed.
+fn lock_error(e: std::io::Error) -> anyhow::Error {
+    match e.raw_os_error() {
+        Some(libc::EPERM) => anyhow!(
+            "could not lock memory: the proc_lock_memory privilege is required"
+        ),
+        Some(libc::EAGAIN) => anyhow!(
+            "could not lock memory: the project.max-locked-memory resource \
+            control, or availrmem, would be exceeded"
+        ),
+        _ => anyhow!("could not lock memory: {e}"),
+    }
+}
+
+/// Lock every page of the process in memory, including those mapped later, as
+/// with mlockall(MCL_CURRENT | MCL_FUTURE); or, if on is false, unlock them all
+/// again.  Unlocking also unlocks any region locked on its own, and undoes
+/// lock_self().
+pub fn lock_all(on: bool) -> Result<()> {
+    if on {
+        let flags = libc::MCL_CURRENT | libc::MCL_FUTURE;
+        if unsafe { libc::mlockall(flags) } != 0 {
+            return Err(lock_error(std::io::Error::last_os_error()));
+        }
+    } else if unsafe { libc::munlockall() } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not unlock memory: {e}");
+    }
+
+    Ok(())
+}
+
+/// Lock every page that is mapped now, as with mlockall(MCL_CURRENT), but not
+/// those mapped later.  Done at startup, this wires down our own text, data,
+/// and thread stacks, so that we remain responsive while the system is paging
+/// without also locking the allocations we go on to make.
+pub fn lock_self() -> Result<()> {
+    if unsafe { libc::mlockall(libc::MCL_CURRENT) } != 0 {
+        return Err(lock_error(std::io::Error::last_os_error()));
+    }
+
+    Ok(())
+}
+
+pub struct Region {
+    base: *mut u8,
+    len: usize,
+    locked: bool,
+    /// The page size we asked for, if not the default.
+    pgsz: Option<usize>,
+    /// The file that backs the region, if it is not anonymous memory.
+    file: Option<(File, PathBuf)>,
+    /// Whether the file is a POSIX shared memory object, in which case the
+    /// path is its name.
+    shm: bool,
+    /// Whether swap space was left unreserved for the region.
+    noreserve: bool,
+    prot: Protection,
+}
+
+/*
+ * The mapping is owned exclusively by the Region, so it is safe to move it to
+ * another thread.
+ */
+unsafe impl Send for Region {}
+
+impl Region {
+    /// Map a new anonymous region of at least len bytes.  The pages are not
+    /// populated until they are touched.
+    pub fn new(len: usize) -> Result<Region> {
+        Region::map(len, pagesize(), None, 0)
+    }
+
+    /// Map a new anonymous region of at least len bytes, without reserving
+    /// swap space for it.  If swap is exhausted when the pages are first
+    /// touched, the process will receive a signal instead of the error from
+    /// mmap(2) that we would otherwise get now.
+    pub fn new_noreserve(len: usize) -> Result<Region> {
+        let mut r = Region::map(len, pagesize(), None, libc::MAP_NORESERVE)?;
+        r.noreserve = true;
+        Ok(r)
+    }
+
+    /// Create (or truncate) the file at path, extend it to at least len bytes,
+    /// and map it shared so that stores to the region dirty pages in the page
+    /// cache.  The file is removed again when the region is dropped.
+    pub fn file(len: usize, path: &Path) -> Result<Region> {
+        let len = len.next_multiple_of(pagesize());
+
+        let f = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("could not create {path:?}"))?;
+        let res = f
+            .set_len(len as u64)
+            .with_context(|| format!("could not extend {path:?}"))
+            .and_then(|()| Region::map(len, pagesize(), Some(&f), 0));
+
+        match res {
+            Ok(mut r) => {
+                r.file = Some((f, path.to_owned()));
+                Ok(r)
+            }
+            Err(e) => {
+                std::fs::remove_file(path).ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// Create a POSIX shared memory object with the given name, as with
+    /// shm_open(3C), size it to at least len bytes, and map it shared.  The
+    /// object lives in tmpfs, so its pages are charged against swap and
+    /// memory rather than written to disk.  It is unlinked again when the
+    /// region is dropped.
+    pub fn shm(len: usize, name: &str) -> Result<Region> {
+        let len = len.next_multiple_of(pagesize());
+
+        let cname = CString::new(name)?;
+        let fd = unsafe {
+            libc::shm_open(
+                cname.as_ptr(),
+                libc::O_RDWR | libc::O_CREAT | libc::O_EXCL,
+                0o600,
+            )
+        };
+        if fd < 0 {
+            let e = std::io::Error::last_os_error();
+            bail!("could not create shared memory object {name:?}: {e}");
+        }
+        let f = unsafe { File::from_raw_fd(fd) };
+
+        let res = f
+            .set_len(len as u64)
+            .with_context(|| format!("could not extend {name:?}"))
+            .and_then(|()| Region::map(len, pagesize(), Some(&f), 0));
+
+        match res {
+            Ok(mut r) => {
+                r.file = Some((f, PathBuf::from(name)));
+                r.shm = true;
+                Ok(r)
+            }
+            Err(e) => {
+                unsafe { libc::shm_unlink(cname.as_ptr()) };
+                Err(e)
+            }
+        }
+    }
+
+    /// Map a new anonymous region of at least len bytes, and ask the system to
+    /// back it with pages of size pgsz where it can.
+    pub fn with_pagesize(len: usize, pgsz: usize) -> Result<Region> {
+        let supported = sys::page_sizes()?;
+        if !supported.contains(&pgsz) {
+            bail!(
+                "page size {} not supported (supported sizes: {})",
+                size_name(pgsz),
+                supported
+                    .iter()
+                    .map(|&sz| size_name(sz))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+
+        let mut r = Region::map(len, pgsz, None, 0)?;
+        sys::hat_advise_pagesize(r.base, r.len, pgsz)?;
+        r.pgsz = Some(pgsz);
+        Ok(r)
+    }
+
+    /// Map a region of len bytes, rounded up to a multiple of align, at an
+    /// address that is also aligned to align.  The region is either anonymous
+    /// memory, or a shared mapping of the given file.  Any additional mmap(2)
+    /// flags are passed through.
+    fn map(
+        len: usize,
+        align: usize,
+        file: Option<&File>,
+        flags: libc::c_int,
+    ) -> Result<Region> {
+        let len = len.next_multiple_of(align);
+
+        /*
+         * The system only guarantees the alignment of a mapping to the base
+         * page size.  For a larger alignment, map enough extra that an aligned
+         * range must fall within it, and then unmap the excess at either end.
+         */
+        let extra = align.saturating_sub(pagesize());
+        let (flags, fd) = match file {
+            Some(f) => (flags | libc::MAP_SHARED, f.as_raw_fd()),
+            None => (flags | libc::MAP_PRIVATE | libc::MAP_ANON, -1),
+        };
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len + extra,
+                libc::PROT_READ | libc::PROT_WRITE,
+                flags,
+                fd,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            let e = std::io::Error::last_os_error();
+            bail!("could not map {len} bytes: {e}");
+        }
+
+        let base = base as *mut u8;
+        let head = (base as usize).next_multiple_of(align) - base as usize;
+        unsafe {
+            if head > 0 {
+                libc::munmap(base as *mut _, head);
+            }
+            if extra > head {
+                libc::munmap(base.add(head + len) as *mut _, extra - head);
+            }
+        }
+
+        Ok(Region {
+            base: unsafe { base.add(head) },
+            len,
+            locked: false,
+            pgsz: None,
+            file: None,
+            shm: false,
+            noreserve: false,
+            prot: Protection::ReadWrite,
+        })
+    }
+
+    /// Lock the pages of the region in memory.  Locked pages cannot be paged
+    /// out, and are removed from availrmem for as long as they remain locked.
+    pub fn lock(&mut self) -> Result<()> {
+        sys::lock_range(self.base, self.len, true).map_err(lock_error)?;
+
+        self.locked = true;
+        Ok(())
+    }
+
+    /// Unlock the pages of the region, so that they may be paged out again and
+    /// are returned to availrmem.
+    pub fn unlock(&mut self) -> Result<()> {
+        if let Err(e) = sys::lock_range(self.base, self.len, false) {
+            bail!("could not unlock memory: {e}");
+        }
+
+        self.locked = false;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The contents of the region.  The pages must be readable, as any access
+    /// to them would otherwise cause the process to receive a signal.
+    pub fn bytes(&self) -> Result<&[u8]> {
+        if self.prot == Protection::None {
+            bail!("region is not readable");
+        }
+
+        Ok(unsafe { std::slice::from_raw_parts(self.base, self.len) })
+    }
+
+    /// The contents of the region, for modification.  The pages must be
+    /// writable.
+    pub fn bytes_mut(&mut self) -> Result<&mut [u8]> {
+        if self.prot != Protection::ReadWrite {
+            bail!("region is not writable");
+        }
+
+        Ok(unsafe { std::slice::from_raw_parts_mut(self.base, self.len) })
+    }
+
+    /// The address of the start of the region.
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.base
+    }
+
+    /// Store to every stride'th byte of the len bytes at offset off, without
+    /// changing their values.  One store is enough to fault in a page and make
+    /// it dirty, while leaving the contents intact for later verification.
+    /// The pages must be writable.
+    pub fn touch(
+        &mut self,
+        off: usize,
+        len: usize,
+        stride: usize,
+    ) -> Result<()> {
+        assert!(
+            stride > 0 && off.checked_add(len).is_some_and(|e| e <= self.len)
+        );
+        if self.prot != Protection::ReadWrite {
+            bail!("region is not writable");
+        }
+
+        unsafe {
+            let mut p = self.base.add(off);
+            let end = p.add(len);
+            while p < end {
+                p.write_volatile(p.read_volatile());
+                p = p.wrapping_add(stride);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Report which pages of the region are resident, one entry per page.
+    pub fn resident(&self) -> Result<Vec<bool>> {
+        sys::resident(self.base, self.len, pagesize())
+    }
+
+    /// Pass advice about the expected use of the region to the system, as with
+    /// madvise(3C).
+    pub fn advise(&self, advice: libc::c_int) -> Result<()> {
+        if unsafe { libc::madvise(self.base as *mut _, self.len, advice) } != 0
+        {
+            let e = std::io::Error::last_os_error();
+            bail!("madvise: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Write any modified pages of a file-backed region back to the file, and
+    /// wait for that to finish, as with msync(MS_SYNC | MS_INVALIDATE).  The
+    /// cached pages are then invalidated, so that later accesses must read
+    /// them back from the file.
+    pub fn sync(&self) -> Result<()> {
+        let flags = libc::MS_SYNC | libc::MS_INVALIDATE;
+        if unsafe { libc::msync(self.base as *mut _, self.len, flags) } != 0 {
+            let e = std::io::Error::last_os_error();
+            bail!("msync: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Change the access permitted to the pages of the region, as with
+    /// mprotect(2).  Any access that is no longer permitted will cause the
+    /// process to receive a signal.
+    pub fn protect(&mut self, prot: Protection) -> Result<()> {
+        if unsafe { libc::mprotect(self.base as *mut _, self.len, prot.prot()) }
+            != 0
+        {
+            let e = std::io::Error::last_os_error();
+            bail!("mprotect: {e}");
+        }
+
+        self.prot = prot;
+        Ok(())
+    }
+
+    pub fn protection(&self) -> Protection {
+        self.prot
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Note that the region is no longer locked because everything has been
+    /// unlocked at once, with lock_all(false).
+    pub fn unlocked_all(&mut self) {
+        self.locked = false;
+    }
+
+    pub fn pgsz(&self) -> Option<usize> {
+        self.pgsz
+    }
+
+    pub fn noreserve(&self) -> bool {
+        self.noreserve
+    }
+
+    /// Whether the region is a shared mapping of a file or a shared memory
+    /// object, so that stores to it are seen by every process that maps it.
+    pub fn shared(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.file.as_ref().filter(|_| !self.shm).map(|(_, p)| p.as_path())
+    }
+
+    /// The name of the shared memory object that backs the region, if any.
+    pub fn shm_name(&self) -> Option<&Path> {
+        self.file.as_ref().filter(|_| self.shm).map(|(_, p)| p.as_path())
+    }
+
+    /// Sample the size of the physical pages that back the region, once every
+    /// stride bytes, and return the number of bytes apparently mapped with each
+    /// page size.  Pages that are not resident are not counted.
+    pub fn page_sizes(&self, stride: usize) -> Result<BTreeMap<u64, usize>> {
+        self.sample(stride, sys::MEMINFO_VPAGESIZE)
+    }
+
+    /// Sample the locality group from which the physical pages that back the
+    /// region were allocated, once every stride bytes, and return the number
+    /// of bytes apparently in each lgroup.
+    pub fn lgroups(&self, stride: usize) -> Result<BTreeMap<u64, usize>> {
+        self.sample(stride, sys::MEMINFO_VLGRP)
+    }
+
+    fn sample(
+        &self,
+        stride: usize,
+        what: libc::c_uint,
+    ) -> Result<BTreeMap<u64, usize>> {
+        let addrs = (0..self.len)
+            .step_by(stride)
+            .map(|off| self.base as u64 + off as u64)
+            .collect::<Vec<_>>();
+
+        let mut out = BTreeMap::new();
+        for v in sys::meminfo_one(&addrs, what)?.into_iter().flatten() {
+            *out.entry(v).or_default() += stride;
+        }
+        Ok(out)
+    }
+
+    /// Shorten the region to len bytes, rounded up to a whole page, returning
+    /// the pages beyond that point to the system.
+    pub fn truncate(&mut self, len: usize) {
+        let len = len.next_multiple_of(pagesize());
+        if len >= self.len {
+            return;
+        }
+
+        /*
+         * Unmapping pages also unlocks them, so there is no need to call
+         * munlock(3C) first.
+         */
+        let r = unsafe {
+            libc::munmap(self.base.add(len) as *mut _, self.len - len)
+        };
+        assert_eq!(r, 0, "munmap: {}", std::io::Error::last_os_error());
+        self.len = len;
+
+        /*
+         * Unmapping the pages of a file does not release them from the page
+         * cache, so shorten the file as well.
+         */
+        if let Some((f, _)) = &self.file {
+            f.set_len(len as u64).ok();
+        }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        self.truncate(0);
+        match &self.file {
+            Some((_, name)) if self.shm => {
+                if let Ok(name) = CString::new(name.as_os_str().as_bytes()) {
+                    unsafe { libc::shm_unlink(name.as_ptr()) };
+                }
+            }
+            Some((_, path)) => {
+                std::fs::remove_file(path).ok();
+            }
+            None => (),
+        }
+    }
+}
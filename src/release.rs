@@ -0,0 +1,74 @@
+/*
+ * Releasing memory.  Unmapping a very large allocation, especially one that
+ * has been paged out, can take a long time, so rather than drop each
+ * allocation in one go, a thread of its own trims them a chunk at a time, so
+ * that it can report progress and stop early.
+ */
+
+use std::time::{Duration, Instant};
+
+use crate::alloc::Alloc;
+
+/// Release at most this many bytes at a time between checks for interruption.
+const CHUNK: usize = 64 * 1024 * 1024;
+
+/// What became of a request to release memory.
+pub struct Released {
+    /// The number of bytes we were asked to release.
+    pub want: usize,
+    pub freed: usize,
+    /// The allocations, or what remains of them, that were not released, to
+    /// be tracked again by the caller.
+    pub kept: Vec<Alloc>,
+    pub interrupted: bool,
+    pub dur: Duration,
+}
+
+/// Release want bytes from the given allocations, which are taken from the
+/// last first, in the same way they were taken out of the registry.  The want
+/// size must be a whole number of pages.  The interrupted function is checked
+/// between chunks, and progress is called about once a second with the number
+/// of bytes released so far.
+pub fn run(
+    mut allocs: Vec<Alloc>,
+    want: usize,
+    interrupted: &dyn Fn() -> bool,
+    progress: &dyn Fn(usize),
+) -> Released {
+    let start = Instant::now();
+    let mut last_report = start;
+    let mut freed: usize = 0;
+    let mut stopped = false;
+    while freed < want {
+        if interrupted() {
+            stopped = true;
+            break;
+        }
+
+        let Some(a) = allocs.last_mut() else {
+            break;
+        };
+
+        let n = CHUNK.min(a.size()).min(want - freed);
+        if n == a.size() {
+            allocs.pop();
+        } else {
+            a.buf.truncate(a.size() - n);
+        }
+        freed += n;
+
+        let now = Instant::now();
+        if now.duration_since(last_report).as_secs() >= 1 {
+            last_report = now;
+            progress(freed);
+        }
+    }
+
+    Released {
+        want,
+        freed,
+        kept: allocs,
+        interrupted: stopped,
+        dur: start.elapsed(),
+    }
+}
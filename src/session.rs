@@ -0,0 +1,141 @@
+/*
+ * A session file records the existence of a running fillmem, so that a second
+ * instance on the same system does not unknowingly compete with it.  The file
+ * contains lines of the form "key=value":
+ *
+ *  pid         process ID of the running fillmem
+ *  start       time at which it started, in RFC 3339 format
+ *  control     path of the control socket, or "-" if there is none
+ */
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+pub const DEFAULT_PATH: &str = "/tmp/fillmem.session";
+
+struct Existing {
+    pid: i32,
+    start: String,
+}
+
+fn read(path: &Path) -> Result<Existing> {
+    let text = std::fs::read_to_string(path)?;
+
+    let mut pid = None;
+    let mut start = "unknown".to_string();
+    for l in text.lines() {
+        match l.split_once('=') {
+            Some(("pid", v)) => pid = v.parse::<i32>().ok(),
+            Some(("start", v)) => start = v.to_string(),
+            _ => (),
+        }
+    }
+
+    let Some(pid) = pid else {
+        bail!("session file {path:?} does not contain a process ID");
+    };
+
+    Ok(Existing { pid, start })
+}
+
+/// Determine whether a process still exists.
+fn alive(pid: i32) -> bool {
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+
+    /*
+     * If the process exists but belongs to somebody else, we are not permitted
+     * to signal it, but it is alive nonetheless.
+     */
+    std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// The session file for this instance, which is removed when dropped.
+pub struct Session {
+    path: PathBuf,
+}
+
+impl Session {
+    /// Create the session file.  If a session file already exists for a
+    /// process that is still running, we will fail unless forced to continue.
+    /// A session file left behind by a process that no longer exists is
+    /// cleaned up, with a message for each notable event returned to the
+    /// caller.
+    pub fn create(path: &Path, force: bool) -> Result<(Session, Vec<String>)> {
+        let mut notes = Vec::new();
+
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(mut f) => {
+                    write!(
+                        f,
+                        "pid={}\nstart={}\ncontrol=-\n",
+                        std::process::id(),
+                        chrono::Utc::now().to_rfc3339(),
+                    )?;
+                    f.flush()?;
+
+                    return Ok((Session { path: path.to_owned() }, notes));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => (),
+                Err(e) => bail!("could not create session file {path:?}: {e}"),
+            }
+
+            /*
+             * A file that is empty or has no process ID in it is what a crash
+             * between creating the file and writing to it leaves behind, so
+             * treat it as stale rather than let it block every later session.
+             */
+            match read(path) {
+                Ok(ex) if alive(ex.pid) => {
+                    if !force {
+                        bail!(
+                            "fillmem is already running as pid {} (since {}); \
+                            it has no control socket to attach to, so either \
+                            stop it, or use --force to run another session \
+                            anyway",
+                            ex.pid,
+                            ex.start,
+                        );
+                    }
+                    notes.push(format!(
+                        "ignoring running session in pid {} (since {})",
+                        ex.pid, ex.start,
+                    ));
+                }
+                Ok(ex) => notes.push(format!(
+                    "cleaned up after crashed session in pid {} (since {})",
+                    ex.pid, ex.start,
+                )),
+                Err(e) => notes.push(format!("cleaned up stale session: {e}")),
+            }
+
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    bail!("could not remove session file {path:?}: {e}");
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        /*
+         * Only remove the file if it still belongs to us; a forced session may
+         * have replaced it in the meantime.
+         */
+        if read(&self.path).is_ok_and(|ex| ex.pid == std::process::id() as i32)
+        {
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+
+/// How aggressively the threads that fill and touch memory should compete with
+/// the rest of the system for CPU time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// The nice value to apply to worker threads at this priority.
+    pub fn nice(&self) -> i32 {
+        match self {
+            Priority::Low => 19,
+            Priority::Normal => 0,
+            Priority::High => -20,
+        }
+    }
+}
+
+impl FromStr for Priority {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Priority> {
+        Ok(match s {
+            "low" => Priority::Low,
+            "normal" => Priority::Normal,
+            "high" => Priority::High,
+            other => bail!("priority {other:?} must be low, normal, or high"),
+        })
+    }
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        })
+    }
+}
+
+/// Tunable behaviour, adjusted at the prompt with the "set" command.
+pub struct Settings {
+    pub worker_priority: Priority,
+    pub vmem_cap: Option<u64>,
+    pub quiet: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            worker_priority: Priority::Normal,
+            vmem_cap: None,
+            quiet: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Produce one line per setting, suitable for display to the user.
+    pub fn describe(&self) -> Vec<String> {
+        vec![
+            format!("worker-priority {}", self.worker_priority),
+            format!(
+                "vmem-cap {}",
+                self.vmem_cap
+                    .map(|c| format!("{}m", c / 1024 / 1024))
+                    .unwrap_or_else(|| "none".into())
+            ),
+            format!("faults {}", crate::fault::armed()),
+            format!("quiet {}", if self.quiet { "on" } else { "off" }),
+        ]
+    }
+}
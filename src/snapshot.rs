@@ -0,0 +1,139 @@
+/*
+ * Periodic snapshots of the state of the system and of fillmem itself, for
+ * analysis after an experiment.  Each snapshot is a directory, named for the
+ * time at which it was taken, containing:
+ *
+ *  kstat.json          the entire kstat chain, as with "kstat dump"
+ *  allocations.json    the table of our current allocations
+ *  rusage.json         our own resource usage, from getrusage(3C)
+ *  identity.json       the host, OS, and hardware on which it was taken
+ */
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+
+use crate::{json, kstat, stats, term::Term, FillMem};
+
+pub struct Snapshots {
+    pub interval: Duration,
+    pub dir: PathBuf,
+    /*
+     * The thread exits once this channel is closed.
+     */
+    _stop: mpsc::Sender<()>,
+}
+
+impl Snapshots {
+    /// Take a snapshot immediately, and then again after each interval until
+    /// the returned object is dropped.
+    pub fn start(
+        ed: Arc<Term>,
+        fm: Arc<FillMem>,
+        interval: Duration,
+        dir: PathBuf,
+    ) -> Result<Snapshots> {
+        if interval.is_zero() {
+            bail!("snapshot interval must be non-zero");
+        }
+        std::fs::create_dir_all(&dir)?;
+
+        let (tx, rx) = mpsc::channel::<()>();
+
+        let d = dir.clone();
+        std::thread::Builder::new().name("snapshot".into()).spawn(
+            move || loop {
+                match take(&d, &fm) {
+                    Ok(p) => ed.log(&format!("snapshot: {}", p.display())),
+                    Err(e) => ed.log(&format!("snapshot failed: {e}")),
+                }
+                .ok();
+
+                match rx.recv_timeout(interval) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    _ => return,
+                }
+            },
+        )?;
+
+        Ok(Snapshots { interval, dir, _stop: tx })
+    }
+}
+
+/// Write one snapshot bundle into a new directory under the specified
+/// directory, returning the path of the bundle.
+pub fn take(dir: &Path, fm: &FillMem) -> Result<PathBuf> {
+    let now = Utc::now();
+    let path = dir.join(now.format("%Y%m%dT%H%M%S%.3fZ").to_string());
+    std::fs::create_dir(&path)?;
+
+    let mut w = BufWriter::new(File::create(path.join("kstat.json"))?);
+    kstat::dump(&mut w, None)?;
+
+    let mut w = BufWriter::new(File::create(path.join("allocations.json"))?);
+    let allocs = fm.allocs();
+    let time = |t: DateTime<Utc>| json::string(&t.to_rfc3339());
+    write!(w, "{{\"time\":{},\"allocations\":[", time(now))?;
+    for (i, a) in allocs.iter().enumerate() {
+        write!(
+            w,
+            "{}{{\"id\":{},\"size\":{},\"created\":{},\"touched\":{},\
+            \"locked\":{},\"pgsz\":{},\"file\":{}}}",
+            if i > 0 { "," } else { "" },
+            a.id,
+            a.size,
+            time(a.created),
+            a.touched.map(time).unwrap_or_else(|| "null".into()),
+            a.locked,
+            a.pgsz.map(|p| p.to_string()).unwrap_or_else(|| "null".into()),
+            a.file
+                .as_ref()
+                .map(|f| json::string(&f.to_string_lossy()))
+                .unwrap_or_else(|| "null".into()),
+        )?;
+    }
+    writeln!(
+        w,
+        "],\"total\":{}}}",
+        allocs.iter().map(|a| a.size).sum::<usize>()
+    )?;
+    w.flush()?;
+
+    let mut ru: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut ru) } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("getrusage: {e}");
+    }
+    let usec = |tv: libc::timeval| tv.tv_sec * 1_000_000 + tv.tv_usec;
+    let mut w = BufWriter::new(File::create(path.join("rusage.json"))?);
+    writeln!(
+        w,
+        "{{\"time\":{},\"utime_usec\":{},\"stime_usec\":{},\"maxrss\":{},\
+        \"minflt\":{},\"majflt\":{},\"nswap\":{},\"inblock\":{},\"oublock\":{},\
+        \"nvcsw\":{},\"nivcsw\":{}}}",
+        json::string(&now.to_rfc3339()),
+        usec(ru.ru_utime),
+        usec(ru.ru_stime),
+        ru.ru_maxrss,
+        ru.ru_minflt,
+        ru.ru_majflt,
+        ru.ru_nswap,
+        ru.ru_inblock,
+        ru.ru_oublock,
+        ru.ru_nvcsw,
+        ru.ru_nivcsw,
+    )?;
+    w.flush()?;
+
+    let id = stats::Source::open().and_then(|mut s| s.identity())?;
+    let mut w = BufWriter::new(File::create(path.join("identity.json"))?);
+    writeln!(w, "{}", id.json())?;
+    w.flush()?;
+
+    Ok(path)
+}
@@ -0,0 +1,99 @@
+/*
+ * Consuming stack.  A thread is created with a stack large enough for the
+ * requested size, and recurses down it touching every page on the way.  The
+ * system accounts for and reclaims stack segments a little differently from
+ * the heap and other anonymous mappings, so it is useful to be able to grow
+ * them on purpose.  The pages remain in use until the thread exits.
+ */
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use chrono::prelude::*;
+
+use crate::region;
+
+/// How much stack each level of recursion consumes.
+const FRAME: usize = 64 * 1024;
+
+/// Room for what each level of recursion needs beyond the frame we touch on
+/// purpose: the return address, saved registers, and in a debug build any
+/// temporaries the compiler did not optimise away.  Without this, a deep
+/// enough descent runs into the guard page and aborts the whole process.
+const LEVEL_SLACK: usize = 4096;
+
+/// Room for whatever else the thread needs on its stack, beyond the frames we
+/// touch on purpose.
+const SLACK: usize = 1024 * 1024;
+
+pub struct Stack {
+    /// The amount of stack touched, in bytes.
+    pub size: usize,
+    pub dur: Duration,
+    pub since: DateTime<Utc>,
+    /*
+     * The thread exits, releasing its stack, once this channel is closed.
+     */
+    _stop: mpsc::Sender<()>,
+}
+
+/// Recurse until at least left bytes of stack have been used, writing to each
+/// page of every frame.
+#[inline(never)]
+fn descend(left: usize, pagesize: usize) {
+    let mut frame = [0u8; FRAME];
+    for off in (0..FRAME).step_by(pagesize) {
+        unsafe { std::ptr::write_volatile(&mut frame[off], b'S') };
+    }
+    std::hint::black_box(&mut frame);
+
+    if left > FRAME {
+        descend(left - FRAME, pagesize);
+    }
+
+    /*
+     * Use the frame again after the call returns so that it cannot be made
+     * into a tail call that would reuse our frame.
+     */
+    std::hint::black_box(&frame);
+}
+
+impl Stack {
+    /// Start a thread that touches size bytes of its own stack and then waits
+    /// until the returned object is dropped.
+    pub fn start(size: usize) -> Result<Stack> {
+        if size == 0 {
+            bail!("stack size must be non-zero");
+        }
+
+        let (stop, rx) = mpsc::channel::<()>();
+        let (donetx, done) = mpsc::channel::<Duration>();
+
+        let levels = size.div_ceil(FRAME);
+        let Some(stack) = levels
+            .checked_mul(FRAME + LEVEL_SLACK)
+            .and_then(|sz| sz.checked_add(SLACK))
+        else {
+            bail!("stack size {size} is too large");
+        };
+
+        let pagesize = region::pagesize();
+        std::thread::Builder::new()
+            .name("stack".into())
+            .stack_size(stack)
+            .spawn(move || {
+                let start = Instant::now();
+                descend(size, pagesize);
+                if donetx.send(start.elapsed()).is_ok() {
+                    rx.recv().ok();
+                }
+            })?;
+
+        let Ok(dur) = done.recv() else {
+            bail!("stack thread exited before touching its stack");
+        };
+
+        Ok(Stack { size, dur, since: Utc::now(), _stop: stop })
+    }
+}
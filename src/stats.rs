@@ -0,0 +1,807 @@
+use std::time::Instant;
+
+use chrono::Utc;
+
+use anyhow::{bail, Result};
+
+use crate::disk::{self, Disk};
+use crate::fault::{self, Fault};
+use crate::kstat::{self, consts::*, CpuStats, Delta, FromKstat, KstatRates};
+use crate::kstat::{KstatVminfo, KstatWrapper, Named};
+use crate::kvm::Kvm;
+use crate::lgrp::{self, Lgroup};
+use crate::{json, region, swap, sys};
+
+/// A single sample of the statistics reported by the timer thread.  ARC sizes
+/// are in bytes, while memory figures from the VM system are in pages.
+#[derive(Default)]
+pub struct Sample {
+    pub arc_c: u64,
+    pub arc_c_min: u64,
+    pub arc_c_max: u64,
+    pub arc_size: u64,
+    /// Whether the ARC has been told not to grow because memory is short, if
+    /// this kernel reports it.
+    pub arc_no_grow: Option<bool>,
+    pub freemem: u64,
+    pub availrmem: u64,
+    pub physmem: u64,
+}
+
+/// The target and bounds of the ARC, from the "zfs:0:arcstats" kstat.
+struct ArcSizes {
+    c: u64,
+    c_min: u64,
+    c_max: u64,
+    size: u64,
+    no_grow: Option<bool>,
+}
+
+impl FromKstat for ArcSizes {
+    fn from_kstat(n: &Named) -> kstat::Result<ArcSizes> {
+        Ok(ArcSizes {
+            c: n.u64(STAT_C).unwrap_or(0),
+            c_min: n.u64(STAT_C_MIN).unwrap_or(0),
+            c_max: n.u64(STAT_C_MAX).unwrap_or(0),
+            size: n.u64(STAT_SIZE).unwrap_or(0),
+            no_grow: n.counter(STAT_ARC_NO_GROW).map(|v| v != 0),
+        })
+    }
+}
+
+/// Memory figures from the "unix:0:system_pages" kstat.
+struct SystemPages {
+    availrmem: u64,
+    freemem: u64,
+    physmem: u64,
+}
+
+impl FromKstat for SystemPages {
+    fn from_kstat(n: &Named) -> kstat::Result<SystemPages> {
+        Ok(SystemPages {
+            availrmem: n.u64(STAT_AVAILRMEM).unwrap_or(0),
+            freemem: n.u64(STAT_FREEMEM).unwrap_or(0),
+            physmem: n.u64(STAT_PHYSMEM).unwrap_or(0),
+        })
+    }
+}
+
+/// Swap accounting from the "unix:0:vminfo" kstat, in pages.
+#[derive(Clone, Copy, Default)]
+pub struct Swap {
+    /// Swap reserved by mappings, whether or not it has been allocated.
+    pub resv: u64,
+    /// Swap allocated to pages that have been touched.
+    pub alloc: u64,
+    /// Swap still available to be reserved.
+    pub avail: u64,
+    /// Swap not yet allocated, whether or not it is reserved.
+    pub free: u64,
+}
+
+impl Swap {
+    /// The kernel adds the current figures to the vminfo counters once per
+    /// second.  Work out the average over the updates between two readings,
+    /// or None if there have been no updates in between.
+    pub fn average(later: &KstatVminfo, earlier: &KstatVminfo) -> Option<Swap> {
+        let n = later.updates.checked_sub(earlier.updates)?;
+        if n == 0 {
+            return None;
+        }
+
+        let avg = |l: u64, e: u64| l.saturating_sub(e) / n;
+        Some(Swap {
+            resv: avg(later.swap_resv, earlier.swap_resv),
+            alloc: avg(later.swap_alloc, earlier.swap_alloc),
+            avail: avg(later.swap_avail, earlier.swap_avail),
+            free: avg(later.swap_free, earlier.swap_free),
+        })
+    }
+}
+
+/// The memory use and caps of a zone, from the "memory_cap" kstat, in bytes.
+pub struct Zone {
+    pub id: i32,
+    pub name: String,
+    /// The resident set size of all processes in the zone.
+    pub rss: u64,
+    pub physcap: Option<u64>,
+    /// Swap reserved by processes in the zone.
+    pub swap: u64,
+    pub swapcap: Option<u64>,
+    /// The number of times the zone has gone over its physical memory cap.
+    pub nover: u64,
+    /// Bytes paged out to bring the zone back under its cap.
+    pub pagedout: u64,
+}
+
+/// Interpret a zone cap from the "memory_cap" kstat.  A zone without a cap
+/// reports either zero or, for an explicit cap of "none", the largest value.
+fn cap(v: u64) -> Option<u64> {
+    match v {
+        0 | u64::MAX => None,
+        v => Some(v),
+    }
+}
+
+/// The machine on which statistics are collected, so that logs gathered from
+/// several machines can later be told apart.
+pub struct Identity {
+    pub host: String,
+    pub zone: String,
+    /// The operating system name, release, and version, as from uname(2).
+    pub os: String,
+    /// Physical memory, in bytes.
+    pub physmem: u64,
+    pub pagesize: u64,
+    pub ncpus: u64,
+    /// The clock speed of the first CPU, if known.
+    pub mhz: Option<u64>,
+}
+
+impl Identity {
+    /// Describe the machine as a JSON object.
+    pub fn json(&self) -> String {
+        format!(
+            "{{\"host\":{},\"zone\":{},\"os\":{},\"physmem\":{},\
+            \"pagesize\":{},\"ncpus\":{},\"mhz\":{}}}",
+            json::string(&self.host),
+            json::string(&self.zone),
+            json::string(&self.os),
+            self.physmem,
+            self.pagesize,
+            self.ncpus,
+            self.mhz.map(|m| m.to_string()).unwrap_or_else(|| "null".into()),
+        )
+    }
+}
+
+impl std::fmt::Display for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "host {} zone {}, {}, {} CPUs",
+            self.host, self.zone, self.os, self.ncpus
+        )?;
+        if let Some(mhz) = self.mhz {
+            write!(f, " at {mhz} MHz")?;
+        }
+        write!(
+            f,
+            ", {:.1} MB physmem, {} byte pages",
+            self.physmem as f64 / 1024.0 / 1024.0,
+            self.pagesize
+        )
+    }
+}
+
+/// A summary of the system as a whole, as shown by uptime(1).
+pub struct System {
+    /// Seconds since boot.
+    pub uptime: u64,
+    pub nproc: u64,
+    /// The 1, 5, and 15 minute load averages.
+    pub load: [f64; 3],
+}
+
+/// Network activity that holds kernel memory.  Data queued on sockets is kept
+/// in STREAMS message blocks, allocated from the "streams_mblk" and
+/// "streams_dblk_*" kmem caches.
+pub struct Net {
+    /// TCP connections in the ESTABLISHED or CLOSE-WAIT state.
+    pub tcp_estab: u64,
+    /// UDP datagrams dropped because the socket receive buffer was full.
+    pub udp_overflows: u64,
+    /// Bytes of memory in message blocks that are allocated.
+    pub streams: u64,
+}
+
+/// The state of the page allocator, in pages.  Once free memory falls below
+/// throttlefree, page_create() makes threads other than the pageout daemon
+/// wait for the page scanner to free memory before they can allocate; needfree
+/// counts the pages being waited for, and deficit the pages the scanner has
+/// recently been asked to find in a hurry.
+#[derive(Clone, Copy)]
+pub struct Throttle {
+    pub freemem: u64,
+    pub throttlefree: u64,
+    pub needfree: u64,
+    pub deficit: u64,
+}
+
+impl Throttle {
+    /// Whether allocating threads are being made to wait for memory.
+    pub fn throttled(&self) -> bool {
+        self.freemem < self.throttlefree || self.needfree > 0
+    }
+}
+
+/// The levels of free memory, in pages, at which the kernel takes action to
+/// reclaim memory.  Below lotsfree the page scanner starts; below desfree it
+/// runs at full speed, and the swapper may begin swapping out whole processes;
+/// below minfree only the most critical allocations succeed without waiting.
+#[derive(Clone, Copy)]
+pub struct Thresholds {
+    pub lotsfree: u64,
+    pub desfree: u64,
+    pub minfree: u64,
+    /// Below this, page_create() waits for memory; see Throttle.
+    pub throttlefree: u64,
+}
+
+impl Thresholds {
+    /// The name of the lowest threshold that free memory is below, and its
+    /// value, if any.
+    pub fn below(&self, freemem: u64) -> Option<(&'static str, u64)> {
+        [
+            ("throttlefree", self.throttlefree),
+            ("minfree", self.minfree),
+            ("desfree", self.desfree),
+            ("lotsfree", self.lotsfree),
+        ]
+        .into_iter()
+        .find(|(_, v)| freemem < *v)
+    }
+}
+
+/// Kernel variables describing page allocator throttling, which are not
+/// exported as kstats and must be read from the kernel with libkvm(3LIB).
+pub struct PageVars {
+    kvm: Kvm,
+    throttlefree: usize,
+    needfree: usize,
+    deficit: usize,
+}
+
+impl PageVars {
+    pub fn open() -> Result<PageVars> {
+        let kvm = Kvm::new_readonly()?;
+        Ok(PageVars {
+            throttlefree: kvm.locate("throttlefree")?,
+            needfree: kvm.locate("needfree")?,
+            deficit: kvm.locate("deficit")?,
+            kvm,
+        })
+    }
+}
+
+/// A column that may be included in the periodic statistics line.
+pub struct Column {
+    pub name: &'static str,
+    pub desc: &'static str,
+    /// Whether the column is shown until the user says otherwise.
+    pub default: bool,
+}
+
+/// Every column the timer thread knows how to produce, in the order in which
+/// they are shown by default.  Some columns only appear when they make sense,
+/// e.g., the swap averages once there have been two updates, and the zone
+/// figures only in a non-global zone.  The lowest, mean, and highest figures
+/// cover the readings taken several times a second during each interval.
+pub const COLUMNS: &[Column] = &[
+    col("c", true, "ARC target size (MB)"),
+    col("min", true, "ARC minimum size (MB)"),
+    col("max", true, "ARC maximum size (MB)"),
+    col("free", true, "free memory (MB)"),
+    col("avrm", true, "availrmem (MB)"),
+    col("mine", true, "availrmem held by fillmem (MB)"),
+    col("resv", true, "swap reserved (MB)"),
+    col("alloc", true, "swap allocated (MB)"),
+    col("avail", true, "swap available to reserve (MB)"),
+    col("sfree", true, "swap not yet allocated (MB)"),
+    col("sdev", true, "free space on swap devices (MB)"),
+    col("scan", true, "pages scanned per second"),
+    col("apgi", true, "anonymous pages in per second"),
+    col("apgo", true, "anonymous pages out per second"),
+    col("swpi", true, "pages swapped in per second"),
+    col("swpo", false, "pages swapped out per second"),
+    col("pgin", false, "pages paged in per second"),
+    col("pgout", false, "pages paged out per second"),
+    col("fpgi", false, "file system pages in per second"),
+    col("fpgo", false, "file system pages out per second"),
+    col("hit", true, "ARC hit ratio (%)"),
+    col("zrss", true, "zone resident set size (MB)"),
+    col("zcap", true, "zone physical memory cap (MB)"),
+    col("zswap", true, "zone swap reserved (MB)"),
+    col("zscap", true, "zone swap cap (MB)"),
+    col("zfree", true, "zone memory left under the physical cap (MB)"),
+    col("zsfree", true, "zone swap left under the swap cap (MB)"),
+    col("tcpe", false, "established TCP connections"),
+    col("udpo", false, "UDP receive buffer overflows per second"),
+    col("strm", false, "STREAMS message blocks in use (MB)"),
+    col("pfh", false, "ZFS prefetch hit ratio (%)"),
+    col("prd", false, "ZFS pool reads (MB/s)"),
+    col("pwr", false, "ZFS pool writes (MB/s)"),
+    col("below", false, "lowest reclaim threshold free memory is below"),
+    col("drop", false, "kstat readings lost since startup"),
+    col("hrt", false, "time of the sample (nanoseconds of gethrtime)"),
+    col("fmin", false, "lowest free memory (MB)"),
+    col("favg", false, "mean free memory (MB)"),
+    col("fmax", false, "highest free memory (MB)"),
+    col("amin", false, "lowest availrmem (MB)"),
+    col("aavg", false, "mean availrmem (MB)"),
+    col("amax", false, "highest availrmem (MB)"),
+];
+
+/// Measure availrmem now, in bytes, or return None if it cannot be read.
+pub fn availrmem() -> Option<u64> {
+    let (_, avrm) = Source::open().and_then(|mut s| s.pages()).ok()?;
+    Some(avrm * region::pagesize() as u64)
+}
+
+/// The columns shown until the user says otherwise.  In a non-global zone the
+/// zone's own figures come first, as its caps are what will stop us long
+/// before the system as a whole runs short.
+pub fn default_columns(zoned: bool) -> Vec<&'static str> {
+    let mut cols: Vec<_> =
+        COLUMNS.iter().filter(|c| c.default).map(|c| c.name).collect();
+    if zoned {
+        cols.sort_by_key(|c| !c.starts_with('z'));
+    }
+    cols
+}
+
+const fn col(name: &'static str, default: bool, desc: &'static str) -> Column {
+    Column { name, desc, default }
+}
+
+/// The lowest, mean, and highest of a series of readings.
+#[derive(Clone, Copy, Default)]
+pub struct Range {
+    pub min: u64,
+    pub max: u64,
+    sum: u64,
+    count: u64,
+}
+
+impl Range {
+    pub fn add(&mut self, v: u64) {
+        if self.count == 0 || v < self.min {
+            self.min = v;
+        }
+        self.max = self.max.max(v);
+        self.sum += v;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> u64 {
+        self.sum.checked_div(self.count).unwrap_or(0)
+    }
+}
+
+/// Find a column by name.
+pub fn column(name: &str) -> Option<&'static Column> {
+    COLUMNS.iter().find(|c| c.name == name)
+}
+
+/// Where the timer thread gets its statistics from.
+pub enum Source {
+    Kstat(KstatWrapper),
+    /// Fixed figures for use in FILLMEM_TEST mode, where the test harness
+    /// needs predictable output regardless of the state of the system.  The
+    /// vminfo counters advance by one update on every reading.
+    Simulated {
+        updates: u64,
+        start: Instant,
+    },
+}
+
+impl Source {
+    /// Use simulated statistics in FILLMEM_TEST mode, or the kernel's
+    /// statistics otherwise.
+    pub fn open() -> Result<Source> {
+        Ok(if std::env::var_os("FILLMEM_TEST").is_some() {
+            Source::Simulated { updates: 0, start: Instant::now() }
+        } else {
+            Source::Kstat(KstatWrapper::open()?)
+        })
+    }
+
+    pub fn identity(&mut self) -> Result<Identity> {
+        if let Source::Simulated { .. } = self {
+            return Ok(Identity {
+                host: "simulated".into(),
+                zone: "global".into(),
+                os: "SunOS 5.11 simulated".into(),
+                physmem: 1048576 * 4096,
+                pagesize: 4096,
+                ncpus: 4,
+                mhz: Some(2400),
+            });
+        }
+
+        let pagesize = region::pagesize() as u64;
+        Ok(Identity {
+            host: sys::hostname()?,
+            zone: sys::zonename()?,
+            os: sys::os_version()?,
+            physmem: self.sample()?.physmem * pagesize,
+            pagesize,
+            ncpus: sys::ncpus(),
+            mhz: kstat::cpu_mhz().ok(),
+        })
+    }
+
+    pub fn system(&mut self) -> Result<System> {
+        if let Source::Simulated { .. } = self {
+            return Ok(System {
+                uptime: 93784,
+                nproc: 42,
+                load: [0.5, 0.25, 0.1],
+            });
+        }
+
+        let now = Utc::now().timestamp().max(0) as u64;
+        Ok(System {
+            uptime: now.saturating_sub(kstat::boot_time()?),
+            nproc: kstat::nproc()?,
+            load: kstat::load_average()?,
+        })
+    }
+
+    pub fn sample(&mut self) -> Result<Sample> {
+        let ks = match self {
+            Source::Kstat(ks) => ks,
+            Source::Simulated { .. } => {
+                /*
+                 * There is no kstat chain to fail, so honour any injected
+                 * faults here instead.
+                 */
+                if fault::injected(Fault::ChainUpdate)
+                    || fault::injected(Fault::KstatRead)
+                {
+                    bail!("simulated statistics failure: injected fault");
+                }
+
+                return Ok(Sample {
+                    arc_c: 1024 * 1024 * 1024,
+                    arc_c_min: 64 * 1024 * 1024,
+                    arc_c_max: 4 * 1024 * 1024 * 1024,
+                    arc_size: 768 * 1024 * 1024,
+                    arc_no_grow: Some(false),
+                    freemem: 262144,
+                    availrmem: 524288,
+                    physmem: 1048576,
+                });
+            }
+        };
+
+        let mut s = Sample::default();
+
+        ks.chain_update()?;
+
+        /*
+         * There are no arcstats on a system without ZFS, but a genuine
+         * failure to read them is counted by the wrapper as a dropped sample.
+         */
+        if let Ok(arc) =
+            ks.read_with(Some(MODULE_ZFS), 0, Some(NAME_ARCSTATS), |k| {
+                k.read_into::<ArcSizes>()
+            })
+        {
+            s.arc_c = arc.c;
+            s.arc_c_min = arc.c_min;
+            s.arc_c_max = arc.c_max;
+            s.arc_size = arc.size;
+            s.arc_no_grow = arc.no_grow;
+        }
+
+        let sp =
+            ks.read_with(Some(MODULE_UNIX), 0, Some(NAME_SYSTEM_PAGES), |k| {
+                k.read_into::<SystemPages>()
+            })?;
+        s.availrmem = sp.availrmem;
+        s.freemem = sp.freemem;
+        s.physmem = sp.physmem;
+
+        Ok(s)
+    }
+
+    /// Read just free memory and availrmem, in pages.  This is cheap enough
+    /// to do many times per interval.
+    pub fn pages(&mut self) -> Result<(u64, u64)> {
+        let ks = match self {
+            Source::Kstat(ks) => ks,
+            Source::Simulated { .. } => return Ok((262144, 524288)),
+        };
+
+        let sp =
+            ks.read_with(Some(MODULE_UNIX), 0, Some(NAME_SYSTEM_PAGES), |k| {
+                k.read_into::<SystemPages>()
+            })?;
+        Ok((sp.freemem, sp.availrmem))
+    }
+
+    /// The number of readings lost because a kstat could not be read, even
+    /// after retrying.
+    pub fn dropped(&self) -> u64 {
+        match self {
+            Source::Kstat(ks) => ks.dropped(),
+            Source::Simulated { .. } => 0,
+        }
+    }
+
+    /// Track a set of kstat counters with KstatRates.  Simulated counters
+    /// never change.
+    pub fn rates(
+        &mut self,
+        rates: &mut KstatRates,
+    ) -> Result<Option<Vec<Option<Delta>>>> {
+        match self {
+            Source::Kstat(ks) => Ok(rates.update(ks)?),
+            Source::Simulated { .. } => {
+                Ok(Some(vec![Some(Delta::default()); rates.count()]))
+            }
+        }
+    }
+
+    /// Read the paging and system counters for each CPU.
+    pub fn vm(&mut self) -> Result<CpuStats> {
+        let ks = match self {
+            Source::Kstat(ks) => ks,
+            Source::Simulated { start, .. } => {
+                let mut cs = CpuStats::default();
+                cs.snaptime = start.elapsed().as_nanos() as u64;
+                return Ok(cs);
+            }
+        };
+
+        ks.chain_update()?;
+        Ok(CpuStats::read(ks)?)
+    }
+
+    /// Read the cumulative swap counters from the "unix:0:vminfo" kstat, for
+    /// use with Swap::average().
+    pub fn vminfo(&mut self) -> Result<KstatVminfo> {
+        let ks = match self {
+            Source::Kstat(ks) => ks,
+            Source::Simulated { updates, .. } => {
+                *updates += 1;
+                let n = *updates;
+                return Ok(KstatVminfo {
+                    freemem: 262144 * n,
+                    swap_resv: 393216 * n,
+                    swap_alloc: 262144 * n,
+                    swap_avail: 655360 * n,
+                    swap_free: 786432 * n,
+                    updates: n,
+                });
+            }
+        };
+
+        ks.chain_update()?;
+
+        let vi =
+            ks.read_with(Some(MODULE_UNIX), 0, Some(NAME_VMINFO), |k| {
+                k.read().map(|_| k.vminfo())
+            })?;
+        match vi {
+            Some(vi) => Ok(vi),
+            None => bail!("vminfo kstat not found"),
+        }
+    }
+
+    /// Read the swap accounting and list the swap devices.
+    pub fn swap(&mut self) -> Result<swap::Summary> {
+        match self {
+            Source::Kstat(_) => swap::summary(),
+            Source::Simulated { .. } => Ok(swap::Summary {
+                max: 1048576,
+                free: 786432,
+                resv: 393216,
+                devices: vec![swap::Device {
+                    path: "/dev/zvol/dsk/rpool/swap".into(),
+                    pages: 1048576,
+                    free: 524288,
+                    deleting: false,
+                }],
+            }),
+        }
+    }
+
+    /// Read the page allocator throttling state.  Without access to the
+    /// kernel variables we can still tell when free memory is below minfree,
+    /// which is where throttlefree is set by default.
+    pub fn throttle(&mut self, vars: Option<&PageVars>) -> Result<Throttle> {
+        let ks = match self {
+            Source::Kstat(ks) => ks,
+            Source::Simulated { .. } => {
+                return Ok(Throttle {
+                    freemem: 262144,
+                    throttlefree: 2048,
+                    needfree: 0,
+                    deficit: 0,
+                });
+            }
+        };
+
+        ks.chain_update()?;
+
+        let Some(k) = ks.lookup(Some(MODULE_UNIX), Some(NAME_SYSTEM_PAGES))
+        else {
+            bail!("system pages kstat not available");
+        };
+        let n = k.snapshot()?;
+        let (Some(freemem), Some(minfree)) =
+            (n.ulong(STAT_FREEMEM), n.ulong(STAT_MINFREE))
+        else {
+            bail!("system pages kstat incomplete");
+        };
+
+        let mut t = Throttle {
+            freemem,
+            throttlefree: minfree,
+            needfree: 0,
+            deficit: 0,
+        };
+        if let Some(v) = vars {
+            t.throttlefree = v.kvm.read_usize(v.throttlefree)? as u64;
+            t.needfree = v.kvm.read_usize(v.needfree)? as u64;
+            t.deficit = v.kvm.read_usize(v.deficit)? as u64;
+        }
+        Ok(t)
+    }
+
+    /// Read the reclaim thresholds.  These change only if an administrator
+    /// tunes them, so they need only be read once.
+    pub fn thresholds(
+        &mut self,
+        vars: Option<&PageVars>,
+    ) -> Result<Thresholds> {
+        let ks = match self {
+            Source::Kstat(ks) => ks,
+            Source::Simulated { .. } => {
+                return Ok(Thresholds {
+                    lotsfree: 16384,
+                    desfree: 8192,
+                    minfree: 4096,
+                    throttlefree: 4096,
+                });
+            }
+        };
+
+        ks.chain_update()?;
+
+        let n =
+            ks.read_with(Some(MODULE_UNIX), 0, Some(NAME_SYSTEM_PAGES), |k| {
+                let n = k.snapshot()?;
+                Ok((
+                    n.ulong(STAT_LOTSFREE),
+                    n.ulong(STAT_DESFREE),
+                    n.ulong(STAT_MINFREE),
+                ))
+            })?;
+        let (Some(lotsfree), Some(desfree), Some(minfree)) = n else {
+            bail!("system pages kstat incomplete");
+        };
+
+        /*
+         * throttlefree is set to minfree unless it has been tuned, and is
+         * only visible through the kernel.
+         */
+        let throttlefree = match vars {
+            Some(v) => v.kvm.read_usize(v.throttlefree)? as u64,
+            None => minfree,
+        };
+
+        Ok(Thresholds { lotsfree, desfree, minfree, throttlefree })
+    }
+
+    /// Read the TCP and UDP counters from the "tcp" and "udp" modules, and
+    /// the memory used by STREAMS message blocks.  Walking every kmem cache is
+    /// comparatively expensive, so this is only done when asked for.
+    pub fn net(&mut self) -> Result<Net> {
+        let ks = match self {
+            Source::Kstat(ks) => ks,
+            Source::Simulated { .. } => {
+                return Ok(Net { tcp_estab: 0, udp_overflows: 0, streams: 0 });
+            }
+        };
+
+        ks.chain_update()?;
+
+        let Some(tcp) = ks.lookup(Some(MODULE_TCP), Some(NAME_TCP)) else {
+            bail!("tcp kstat not found");
+        };
+        let udp = ks.lookup(Some(MODULE_UDP), Some(NAME_UDP));
+
+        let mut streams = 0;
+        for k in ks.walk_class(CLASS_KMEM_CACHE) {
+            let n = k.name().to_bytes();
+            if n == b"streams_mblk" || n.starts_with(b"streams_dblk_") {
+                streams += k.data_u64(STAT_BUF_INUSE).unwrap_or(0)
+                    * k.data_u64(STAT_BUF_SIZE).unwrap_or(0);
+            }
+        }
+
+        Ok(Net {
+            tcp_estab: tcp.data_u64(STAT_TCP_CURR_ESTAB).unwrap_or(0),
+            udp_overflows: udp
+                .and_then(|k| k.data_u64(STAT_UDP_IN_OVERFLOWS))
+                .unwrap_or(0),
+            streams,
+        })
+    }
+
+    /// Read the I/O counters of every imported ZFS pool.
+    pub fn pools(&mut self) -> Result<Vec<Disk>> {
+        match self {
+            Source::Kstat(ks) => {
+                ks.chain_update()?;
+                Ok(disk::pools(ks))
+            }
+            Source::Simulated { .. } => Ok(Vec::new()),
+        }
+    }
+
+    /// List the lgroups that directly contain memory.  There is no kstat for
+    /// this, but the simulation needs to stand in for liblgrp as well.
+    pub fn lgroups(&mut self) -> Result<Vec<Lgroup>> {
+        match self {
+            Source::Kstat(_) => lgrp::list(),
+            Source::Simulated { .. } => Ok(vec![
+                Lgroup {
+                    id: 1,
+                    installed: 2048 * 1024 * 1024,
+                    free: 512 * 1024 * 1024,
+                },
+                Lgroup {
+                    id: 2,
+                    installed: 2048 * 1024 * 1024,
+                    free: 768 * 1024 * 1024,
+                },
+            ]),
+        }
+    }
+
+    /// Read the "memory_cap" kstat for the zone in which we are running.
+    pub fn zone(&mut self) -> Result<Zone> {
+        let ks = match self {
+            Source::Kstat(ks) => ks,
+            Source::Simulated { .. } => {
+                return Ok(Zone {
+                    id: 0,
+                    name: "global".into(),
+                    rss: 512 * 1024 * 1024,
+                    physcap: None,
+                    swap: 256 * 1024 * 1024,
+                    swapcap: None,
+                    nover: 0,
+                    pagedout: 0,
+                });
+            }
+        };
+
+        let id = sys::zoneid();
+
+        ks.chain_update()?;
+
+        if let Some(k) = ks.lookup_instance(Some(MODULE_MEMORY_CAP), id, None) {
+            return Ok(Zone {
+                id,
+                name: k.name().to_string_lossy().into_owned(),
+                rss: k.data_u64(STAT_RSS).unwrap_or(0),
+                physcap: k.data_u64(STAT_PHYSCAP).and_then(cap),
+                swap: k.data_u64(STAT_SWAP).unwrap_or(0),
+                swapcap: k.data_u64(STAT_SWAPCAP).and_then(cap),
+                nover: k.data_u64(STAT_NOVER).unwrap_or(0),
+                pagedout: k.data_u64(STAT_PAGEDOUT).unwrap_or(0),
+            });
+        }
+
+        bail!("memory_cap kstat for zone {id} not found");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn zone_cap() {
+        assert_eq!(cap(0), None);
+        assert_eq!(cap(u64::MAX), None);
+        assert_eq!(cap(1 << 30), Some(1 << 30));
+        assert_eq!(cap(u64::MAX - 1), Some(u64::MAX - 1));
+    }
+}
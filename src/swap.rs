@@ -0,0 +1,161 @@
+/*
+ * Swap space, from swapctl(2).  Anonymous memory must be reserved against swap
+ * when it is mapped, and once the swap devices and whatever physical memory
+ * the system is willing to count as swap are exhausted, further allocations
+ * fail regardless of how much memory is free.
+ */
+
+use std::ffi::CStr;
+
+use anyhow::{bail, Result};
+use libc::{c_char, c_int, c_long, c_ulong, c_void, off_t};
+
+/*
+ * From <sys/swap.h>:
+ */
+const SC_LIST: c_int = 2;
+const SC_GETNSWP: c_int = 4;
+const SC_AINFO: c_int = 5;
+const ST_INDEL: c_int = 0x01;
+
+#[repr(C)]
+#[derive(Default)]
+struct anoninfo {
+    ani_max: c_ulong,
+    ani_free: c_ulong,
+    ani_resv: c_ulong,
+}
+
+#[repr(C)]
+struct swapent {
+    ste_path: *mut c_char,
+    ste_start: off_t,
+    ste_length: off_t,
+    ste_pages: c_long,
+    ste_free: c_long,
+    ste_flags: c_int,
+}
+
+extern "C" {
+    fn swapctl(cmd: c_int, arg: *mut c_void) -> c_int;
+}
+
+/// A configured swap device or file.
+pub struct Device {
+    pub path: String,
+    /// The size of the device, in pages.
+    pub pages: u64,
+    /// Pages not yet allocated to anonymous memory.
+    pub free: u64,
+    /// Whether the device is being removed.
+    pub deleting: bool,
+}
+
+/// The system-wide swap accounting, in pages, as reported by swap(8) -s; and
+/// the devices that contribute to it.
+pub struct Summary {
+    /// All swap, including physical memory that may be used as swap.
+    pub max: u64,
+    /// Swap that has not been allocated.
+    pub free: u64,
+    /// Swap that has been reserved, whether or not it has been allocated.
+    pub resv: u64,
+    pub devices: Vec<Device>,
+}
+
+impl Summary {
+    /// Swap allocated to anonymous pages that have been touched.
+    pub fn allocated(&self) -> u64 {
+        self.max.saturating_sub(self.free)
+    }
+
+    /// Swap reserved but not yet allocated.
+    pub fn reserved(&self) -> u64 {
+        self.resv.saturating_sub(self.allocated())
+    }
+
+    /// Swap available to be reserved by new mappings.
+    pub fn available(&self) -> u64 {
+        self.max.saturating_sub(self.resv)
+    }
+
+    /// Pages free on the swap devices, as distinct from physical memory.
+    pub fn device_free(&self) -> u64 {
+        self.devices.iter().map(|d| d.free).sum()
+    }
+}
+
+fn devices() -> Result<Vec<Device>> {
+    /*
+     * The number of devices may change between calls, so we ask for room for
+     * one more than there were a moment ago and use only as many entries as
+     * were filled in.
+     */
+    let n = unsafe { swapctl(SC_GETNSWP, std::ptr::null_mut()) };
+    if n < 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not count swap devices: {e}");
+    }
+    let n = n as usize + 1;
+
+    /*
+     * The table is a count followed by an array of entries, each of which
+     * must point to a buffer for the path of the device.
+     */
+    let mut paths = vec![[0 as c_char; libc::PATH_MAX as usize]; n];
+    let off = std::mem::align_of::<swapent>().max(std::mem::size_of::<c_int>());
+    let len = off + n * std::mem::size_of::<swapent>();
+    let mut tbl = vec![0u64; len.div_ceil(8)];
+    let base = tbl.as_mut_ptr() as *mut u8;
+    let ents = unsafe { base.add(off) } as *mut swapent;
+    unsafe {
+        *(base as *mut c_int) = n as c_int;
+        for (i, p) in paths.iter_mut().enumerate() {
+            ents.add(i).write(swapent {
+                ste_path: p.as_mut_ptr(),
+                ste_start: 0,
+                ste_length: 0,
+                ste_pages: 0,
+                ste_free: 0,
+                ste_flags: 0,
+            });
+        }
+    }
+
+    let r = unsafe { swapctl(SC_LIST, base as *mut c_void) };
+    if r < 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not list swap devices: {e}");
+    }
+
+    Ok((0..(r as usize).min(n))
+        .map(|i| {
+            let e = unsafe { &*ents.add(i) };
+            Device {
+                path: unsafe { CStr::from_ptr(e.ste_path) }
+                    .to_string_lossy()
+                    .into_owned(),
+                pages: e.ste_pages.max(0) as u64,
+                free: e.ste_free.max(0) as u64,
+                deleting: e.ste_flags & ST_INDEL != 0,
+            }
+        })
+        .collect())
+}
+
+/// Read the current swap accounting.
+pub fn summary() -> Result<Summary> {
+    let mut ai = anoninfo::default();
+    if unsafe { swapctl(SC_AINFO, &mut ai as *mut anoninfo as *mut c_void) } < 0
+    {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get swap accounting: {e}");
+    }
+
+    Ok(Summary {
+        max: ai.ani_max,
+        free: ai.ani_free,
+        resv: ai.ani_resv,
+        devices: devices()?,
+    })
+}
@@ -0,0 +1,382 @@
+/*
+ * Assorted illumos system interfaces that are not otherwise exposed by the
+ * libc crate.
+ */
+
+use std::ffi::CStr;
+
+use anyhow::{bail, Result};
+use libc::{c_char, c_int, c_long, c_uint, c_void, size_t, ssize_t};
+
+/*
+ * From <sys/procset.h>:
+ */
+pub const P_LWPID: c_int = 8;
+pub const P_MYID: c_int = -1;
+
+/*
+ * From <sys/priocntl.h>.  The priocntl(2) entry point in libc is a macro
+ * around __priocntl(), which takes the interface version as an extra leading
+ * argument.
+ */
+const PC_VERSION: c_int = 1;
+const PC_DONICE: c_int = 6;
+const PC_SETNICE: c_int = 1;
+
+#[repr(C)]
+struct pcnice {
+    pc_val: c_int,
+    pc_op: c_int,
+}
+
+extern "C" {
+    fn __priocntl(
+        pc_version: c_int,
+        idtype: c_int,
+        id: c_int,
+        cmd: c_int,
+        arg: *mut c_void,
+    ) -> c_long;
+}
+
+/// Set the nice value of the calling LWP, leaving any other threads in the
+/// process alone.
+pub fn set_lwp_nice(nice: i32) -> Result<()> {
+    let mut pcn = pcnice { pc_val: nice, pc_op: PC_SETNICE };
+
+    let r = unsafe {
+        __priocntl(
+            PC_VERSION,
+            P_LWPID,
+            P_MYID,
+            PC_DONICE,
+            &mut pcn as *mut pcnice as *mut c_void,
+        )
+    };
+    if r == -1 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not set nice value to {nice}: {e}");
+    }
+
+    Ok(())
+}
+
+/*
+ * From <sys/processor.h>:
+ */
+const PBIND_NONE: c_int = -1;
+const PBIND_QUERY: c_int = -2;
+
+extern "C" {
+    fn processor_bind(
+        idtype: c_int,
+        id: c_int,
+        processorid: c_int,
+        obind: *mut c_int,
+    ) -> c_int;
+}
+
+/// Bind the calling LWP to the given CPU, or remove any binding if None.
+/// Returns the previous binding so that it may be restored.
+pub fn bind_lwp(cpu: Option<i32>) -> Result<Option<i32>> {
+    let mut obind = PBIND_QUERY;
+    let r = unsafe {
+        processor_bind(P_LWPID, P_MYID, cpu.unwrap_or(PBIND_NONE), &mut obind)
+    };
+    if r != 0 {
+        let e = std::io::Error::last_os_error();
+        match cpu {
+            Some(cpu) => bail!("could not bind to CPU {cpu}: {e}"),
+            None => bail!("could not unbind from CPU: {e}"),
+        }
+    }
+
+    Ok((obind != PBIND_NONE).then_some(obind))
+}
+
+/*
+ * From <sys/fcntl.h>:
+ */
+const DIRECTIO_ON: c_int = 1;
+
+extern "C" {
+    fn directio(fildes: c_int, advice: c_int) -> c_int;
+}
+
+/// Ask that reads and writes of the open file bypass the page cache, as with
+/// directio(3C).  Not every file system supports this.
+pub fn directio_on(fd: c_int) -> Result<()> {
+    if unsafe { directio(fd, DIRECTIO_ON) } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not enable direct I/O: {e}");
+    }
+
+    Ok(())
+}
+
+/// Limit the total virtual size of the process, as with RLIMIT_VMEM.  Only the
+/// soft limit is adjusted so that the cap may later be raised or removed.
+/// Passing None removes the cap, up to whatever hard limit is in force.
+pub fn set_vmem_cap(cap: Option<u64>) -> Result<()> {
+    let mut rl = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_AS, &mut rl) } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get RLIMIT_VMEM: {e}");
+    }
+
+    rl.rlim_cur = match cap {
+        Some(cap)
+            if rl.rlim_max != libc::RLIM_INFINITY && cap > rl.rlim_max =>
+        {
+            bail!("cap exceeds the hard limit of {} bytes", rl.rlim_max);
+        }
+        Some(cap) => cap,
+        None => rl.rlim_max,
+    };
+
+    if unsafe { libc::setrlimit(libc::RLIMIT_AS, &rl) } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not set RLIMIT_VMEM: {e}");
+    }
+
+    Ok(())
+}
+
+extern "C" {
+    fn getzoneid() -> c_int;
+    fn getzonenamebyid(id: c_int, buf: *mut c_char, buflen: size_t) -> ssize_t;
+}
+
+/// From <sys/zone.h>:
+const ZONENAME_MAX: usize = 64;
+
+/// Return the ID of the zone in which we are running.  The global zone is
+/// always zone 0.
+pub fn zoneid() -> i32 {
+    unsafe { getzoneid() }
+}
+
+/// Return the name of the zone in which we are running.
+pub fn zonename() -> Result<String> {
+    let mut buf = [0 as c_char; ZONENAME_MAX];
+
+    let r =
+        unsafe { getzonenamebyid(getzoneid(), buf.as_mut_ptr(), buf.len()) };
+    if r < 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get zone name: {e}");
+    }
+
+    Ok(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned())
+}
+
+extern "C" {
+    fn gethrtime() -> i64;
+}
+
+/// Return the high-resolution time, in nanoseconds since an arbitrary point in
+/// the past, as with gethrtime(3C).  This is the clock against which kstat
+/// snapshot times are measured.
+pub fn hrtime() -> u64 {
+    unsafe { gethrtime() as u64 }
+}
+
+/// Return the name of this host.
+pub fn hostname() -> Result<String> {
+    let mut buf = [0 as c_char; 256];
+
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get host name: {e}");
+    }
+
+    Ok(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_string_lossy().into_owned())
+}
+
+/// Return the name, release, and version of the operating system, as with
+/// "uname -srv".
+pub fn os_version() -> Result<String> {
+    let mut u: libc::utsname = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::uname(&mut u) } < 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get system name: {e}");
+    }
+
+    let s = |f: &[c_char]| {
+        unsafe { CStr::from_ptr(f.as_ptr()) }.to_string_lossy().into_owned()
+    };
+    Ok(format!("{} {} {}", s(&u.sysname), s(&u.release), s(&u.version)))
+}
+
+/// Return the number of CPUs that are online.
+pub fn ncpus() -> u64 {
+    unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) }.max(1) as u64
+}
+
+/*
+ * From <sys/mman.h>:
+ */
+const MC_LOCK: c_int = 2;
+const MC_UNLOCK: c_int = 3;
+const MC_HAT_ADVISE: c_int = 7;
+const MHA_MAPSIZE_VA: c_uint = 0x1;
+pub const MEMINFO_VLGRP: c_uint = 0x2;
+pub const MEMINFO_VPAGESIZE: c_uint = 0x3;
+const MAX_MEMINFO_CNT: usize = 256;
+
+#[repr(C)]
+struct memcntl_mha {
+    mha_cmd: c_uint,
+    mha_flags: c_uint,
+    mha_pagesize: size_t,
+}
+
+extern "C" {
+    fn memcntl(
+        addr: *mut c_void,
+        len: size_t,
+        cmd: c_int,
+        arg: *mut c_void,
+        attr: c_int,
+        mask: c_int,
+    ) -> c_int;
+    fn getpagesizes(pagesize: *mut size_t, nelem: c_int) -> c_int;
+    fn mincore(addr: *mut c_char, len: size_t, vec: *mut c_char) -> c_int;
+    fn meminfo(
+        inaddr: *const u64,
+        addr_count: c_int,
+        info_req: *const c_uint,
+        info_count: c_int,
+        outdata: *mut u64,
+        validity: *mut c_uint,
+    ) -> c_int;
+}
+
+/// Return the page sizes supported by the system, smallest first.
+pub fn page_sizes() -> Result<Vec<usize>> {
+    let n = unsafe { getpagesizes(std::ptr::null_mut(), 0) };
+    if n < 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get page sizes: {e}");
+    }
+
+    let mut sizes = vec![0; n as usize];
+    let n = unsafe { getpagesizes(sizes.as_mut_ptr(), n) };
+    if n < 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get page sizes: {e}");
+    }
+    sizes.truncate(n as usize);
+
+    Ok(sizes)
+}
+
+/// Ask the system to map the given range of our address space with pages of
+/// the given size, as with memcntl(MC_HAT_ADVISE).  This is only advice, and
+/// the system may still use smaller pages.
+pub fn hat_advise_pagesize(
+    addr: *mut u8,
+    len: usize,
+    pgsz: usize,
+) -> Result<()> {
+    let mut mha = memcntl_mha {
+        mha_cmd: MHA_MAPSIZE_VA,
+        mha_flags: 0,
+        mha_pagesize: pgsz,
+    };
+
+    let r = unsafe {
+        memcntl(
+            addr as *mut c_void,
+            len,
+            MC_HAT_ADVISE,
+            &mut mha as *mut memcntl_mha as *mut c_void,
+            0,
+            0,
+        )
+    };
+    if r != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not advise page size of {pgsz} bytes: {e}");
+    }
+
+    Ok(())
+}
+
+/// Lock or unlock the pages in the given range of our address space, as with
+/// memcntl(MC_LOCK) or memcntl(MC_UNLOCK).  The error is returned as is so
+/// that the caller can explain it.
+pub fn lock_range(
+    addr: *mut u8,
+    len: usize,
+    lock: bool,
+) -> std::io::Result<()> {
+    let cmd = if lock { MC_LOCK } else { MC_UNLOCK };
+
+    let r = unsafe {
+        memcntl(addr as *mut c_void, len, cmd, std::ptr::null_mut(), 0, 0)
+    };
+    if r != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Make a single meminfo(2) request, e.g., MEMINFO_VPAGESIZE for the size of
+/// the physical page, about each of the given addresses.  Addresses that are
+/// not currently backed by a physical page are reported as None.
+pub fn meminfo_one(addrs: &[u64], what: c_uint) -> Result<Vec<Option<u64>>> {
+    let req = [what];
+    let mut out = Vec::with_capacity(addrs.len());
+
+    for chunk in addrs.chunks(MAX_MEMINFO_CNT) {
+        let mut data = vec![0u64; chunk.len()];
+        let mut validity = vec![0 as c_uint; chunk.len()];
+
+        let r = unsafe {
+            meminfo(
+                chunk.as_ptr(),
+                chunk.len() as c_int,
+                req.as_ptr(),
+                req.len() as c_int,
+                data.as_mut_ptr(),
+                validity.as_mut_ptr(),
+            )
+        };
+        if r != 0 {
+            let e = std::io::Error::last_os_error();
+            bail!("meminfo: {e}");
+        }
+
+        /*
+         * Bit 0 of the validity word tells us if the address was valid at
+         * all, and bit 1 if our request could be answered.
+         */
+        out.extend(
+            data.iter()
+                .zip(validity.iter())
+                .map(|(&d, &v)| (v & 0x3 == 0x3).then_some(d)),
+        );
+    }
+
+    Ok(out)
+}
+
+/// Determine which pages of the given range of our address space are resident
+/// in memory, as with mincore(2).  There is one entry for each page.
+pub fn resident(
+    addr: *mut u8,
+    len: usize,
+    pagesize: usize,
+) -> Result<Vec<bool>> {
+    let mut vec = vec![0 as c_char; len.div_ceil(pagesize)];
+
+    if unsafe { mincore(addr as *mut c_char, len, vec.as_mut_ptr()) } != 0 {
+        let e = std::io::Error::last_os_error();
+        bail!("could not get residency with mincore: {e}");
+    }
+
+    Ok(vec.into_iter().map(|v| v & 1 != 0).collect())
+}
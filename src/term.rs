@@ -11,12 +11,16 @@ use anyhow::{bail, Result};
 use libc::{TCIOFLUSH, TCSADRAIN, TCSANOW};
 use termios::Termios;
 
+use crate::fault::{self, Fault};
+use crate::redact::Redactor;
+
 pub struct Term {
     orig_termios: Termios,
     cleaned_up: bool,
 
     output: Mutex<Box<dyn ForOut>>,
     sigterm: Arc<AtomicBool>,
+    redactor: Mutex<Option<Redactor>>,
 
     inner: Arc<(Mutex<Inner>, Condvar)>,
 }
@@ -175,11 +179,25 @@ impl Term {
             cleaned_up: false,
             output: Mutex::new(output),
             sigterm,
+            redactor: Mutex::new(None),
             inner: inner0,
         })
     }
 
+    /// In quiet mode, identifying details are removed from everything we
+    /// log.
+    pub fn set_quiet(&self, quiet: bool) {
+        *self.redactor.lock().unwrap() =
+            if quiet { Some(Redactor::new()) } else { None };
+    }
+
     pub fn log(&self, msg: &str) -> Result<()> {
+        let msg = match self.redactor.lock().unwrap().as_ref() {
+            Some(r) => r.apply(msg),
+            None => msg.to_string(),
+        };
+        let msg = msg.as_str();
+
         let mut i = self.inner.0.lock().unwrap();
 
         loop {
@@ -207,7 +225,17 @@ impl Term {
         }
     }
 
+    /// Log a message that must stand out from the routine output, in reverse
+    /// video.
+    pub fn alert(&self, msg: &str) -> Result<()> {
+        self.log(&format!("\x1b[7m{msg}\x1b[0m"))
+    }
+
     fn emit(&self, msg: &str) -> Result<()> {
+        if fault::injected(Fault::TermWrite) {
+            bail!("terminal write failure: injected fault");
+        }
+
         let mut out = self.output.lock().unwrap();
 
         out.write_all(msg.as_bytes())?;
@@ -0,0 +1,125 @@
+/*
+ * Worker processes.  Some behaviour of the VM system, such as per-process
+ * resource controls and the choice of which process to take pages from, can
+ * only be seen with more than one address space.  A worker is another copy of
+ * fillmem, started with --worker, that allocates memory on our behalf.
+ *
+ * We drive each worker over a simple line protocol on its standard input and
+ * output.  Each request is a single line:
+ *
+ *  grow SIZE       allocate and fill SIZE bytes
+ *
+ * and each is answered with a single line, either "ok MESSAGE" or "error
+ * MESSAGE".  The worker exits when its standard input is closed.
+ */
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::grow;
+use crate::parse;
+use crate::region::Region;
+
+/// The main loop of a worker process.
+pub fn run() -> Result<()> {
+    let mut regions: Vec<Region> = Vec::new();
+    let mut out = std::io::stdout().lock();
+
+    for l in std::io::stdin().lock().lines() {
+        let l = l?;
+        let t = l.split_whitespace().collect::<Vec<_>>();
+
+        let res = match t.as_slice() {
+            ["grow", size] => size
+                .parse::<usize>()
+                .map_err(anyhow::Error::from)
+                .and_then(|size| grow::Spec::new(size).run(&|| false))
+                .and_then(|g| g.ok_or_else(|| anyhow!("interrupted")))
+                .map(|g| {
+                    let msg = format!(
+                        "grew by {}",
+                        parse::megabytes(g.region.len() as u64)
+                    );
+                    regions.push(g.region);
+                    msg
+                }),
+            _ => Err(anyhow!("{l:?} not understood")),
+        };
+
+        match res {
+            Ok(msg) => writeln!(out, "ok {msg}")?,
+            Err(e) => writeln!(out, "error {e}")?,
+        }
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Our end of a worker process.
+pub struct Worker {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    /// The bytes the worker has been asked to grow by so far.
+    pub size: usize,
+}
+
+impl Worker {
+    pub fn spawn() -> Result<Worker> {
+        let mut child = Command::new(std::env::current_exe()?)
+            .arg("--worker")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Worker { child, stdin, stdout, size: 0 })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Send a request without waiting for the reply, so that several workers
+    /// may act at once.
+    pub fn send(&mut self, req: &str) -> Result<()> {
+        let Some(stdin) = self.stdin.as_mut() else {
+            bail!("worker {} is closed", self.pid());
+        };
+        writeln!(stdin, "{req}")?;
+        stdin.flush()?;
+        Ok(())
+    }
+
+    /// Wait for the reply to a request.
+    pub fn reply(&mut self) -> Result<String> {
+        let mut l = String::new();
+        if self.stdout.read_line(&mut l)? == 0 {
+            bail!("worker {} exited", self.pid());
+        }
+
+        let l = l.trim_end();
+        if let Some(msg) = l.strip_prefix("ok ") {
+            Ok(msg.into())
+        } else if let Some(msg) = l.strip_prefix("error ") {
+            bail!("worker {}: {msg}", self.pid());
+        } else {
+            bail!("worker {}: unexpected reply {l:?}", self.pid());
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        /*
+         * Closing the worker's standard input asks it to exit.
+         */
+        self.stdin.take();
+        self.child.wait().ok();
+    }
+}
@@ -0,0 +1,1187 @@
+/*
+ * End-to-end tests that drive the fillmem binary through a pseudo-terminal,
+ * just as an interactive user would.  The binary is run in FILLMEM_TEST mode so
+ * that the periodic statistics are simulated and predictable.
+ */
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Session {
+    master: File,
+    child: Child,
+    output: String,
+}
+
+/// Each test uses its own session file, so that tests may run concurrently.
+fn session_file() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    std::env::temp_dir().join(format!(
+        "fillmem-test.{}.{}.session",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed),
+    ))
+}
+
+impl Session {
+    fn start() -> Session {
+        Session::start_with(&session_file())
+    }
+
+    fn start_with(session: &PathBuf) -> Session {
+        Session::spawn(session, &[])
+    }
+
+    fn start_args(args: &[&str]) -> Session {
+        Session::spawn(&session_file(), args)
+    }
+
+    fn spawn(session: &PathBuf, args: &[&str]) -> Session {
+        let mut master = -1;
+        let mut slave = -1;
+        let ws = libc::winsize {
+            ws_row: 24,
+            ws_col: 80,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+
+        let r = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &ws,
+            )
+        };
+        assert_eq!(r, 0, "openpty: {}", std::io::Error::last_os_error());
+
+        let master = unsafe { File::from_raw_fd(master) };
+        let slave = unsafe { File::from_raw_fd(slave) };
+
+        let child = Command::new(env!("CARGO_BIN_EXE_fillmem"))
+            .env("FILLMEM_TEST", "1")
+            .arg("--session-file")
+            .arg(session)
+            .args(args)
+            .stdin(Stdio::from(slave.try_clone().unwrap()))
+            .stdout(Stdio::from(slave.try_clone().unwrap()))
+            .stderr(Stdio::from(slave))
+            .spawn()
+            .unwrap();
+
+        Session { master, child, output: String::new() }
+    }
+
+    fn send(&mut self, keys: &str) {
+        self.master.write_all(keys.as_bytes()).unwrap();
+        self.master.flush().unwrap();
+    }
+
+    /// Wait for the given text to appear in the output, and discard
+    /// everything up to and including it.
+    fn expect(&mut self, needle: &str) {
+        let deadline = Instant::now() + TIMEOUT;
+
+        loop {
+            if let Some(pos) = self.output.find(needle) {
+                self.output.drain(..pos + needle.len());
+                return;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                panic!("timed out waiting for {needle:?} in {:?}", self.output);
+            }
+
+            let mut pfd = libc::pollfd {
+                fd: self.master.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ms = (deadline - now).as_millis() as libc::c_int;
+            if unsafe { libc::poll(&mut pfd, 1, ms) } <= 0 {
+                continue;
+            }
+
+            let mut buf = [0u8; 4096];
+            match self.master.read(&mut buf) {
+                Ok(0) | Err(_) => {
+                    panic!("output ended waiting for {needle:?}");
+                }
+                Ok(n) => {
+                    self.output.push_str(&String::from_utf8_lossy(&buf[..n]))
+                }
+            }
+        }
+    }
+
+    fn command(&mut self, cmd: &str) {
+        self.expect("fillmem> ");
+        self.send(cmd);
+        self.send("\r");
+    }
+
+    fn wait(&mut self) -> std::process::ExitStatus {
+        let deadline = Instant::now() + TIMEOUT;
+
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return status;
+            }
+            if Instant::now() >= deadline {
+                panic!("timed out waiting for exit");
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+#[test]
+fn unknown_command() {
+    let mut s = Session::start();
+
+    s.command("bogus");
+    s.expect("\"bogus\" not understood");
+}
+
+#[test]
+fn grow_touch_free() {
+    let mut s = Session::start();
+
+    s.command("grow 2");
+    s.expect("grew by 2 megabytes");
+    s.expect(" MB/s (allocation 1)");
+    s.expect("page sizes: ");
+    s.command("touch");
+    s.expect("touched 2 megabytes");
+    s.expect("page-ins: 0 anon from swap, 0 file, 0 reclaimed from free list");
+    s.command("touch stride 1");
+    s.expect("touched 2 megabytes in ");
+    s.expect(" msec (stride 1)");
+    s.command("touch stride 0");
+    s.expect("stride must be at least 1 byte");
+    s.command("grow 1");
+    s.expect("(allocation 2)");
+    s.command("touch 2");
+    s.expect("touched 1 megabytes");
+    s.command("touch 1 stride 2");
+    s.expect("touched 2 megabytes");
+    s.command("touch 3");
+    s.expect("no allocation 3");
+    s.command("free 1");
+    s.expect("freed 1 megabytes");
+    s.command("free");
+    s.expect("freed 2 megabytes");
+}
+
+#[test]
+fn free_background() {
+    let mut s = Session::start();
+
+    s.command("grow 2");
+    s.expect("(allocation 1)");
+    s.command("grow 1");
+    s.expect("(allocation 2)");
+    s.command("free 1536k &");
+    s.expect("started job 1: free 1.5 megabytes");
+    s.expect("job 1: freed 1.5 megabytes in ");
+    s.command("list");
+    s.expect("   1  ");
+    s.command("status");
+    s.expect("1 allocations, 1.5 megabytes");
+}
+
+#[test]
+fn missing_argument() {
+    let mut s = Session::start();
+
+    s.command("grow");
+    s.expect("grow by how much?");
+    s.command("grow lots");
+    s.expect("invalid size \"lots\": expected a number");
+}
+
+#[test]
+fn line_editing() {
+    let mut s = Session::start();
+
+    /*
+     * Correct a typo with backspace:
+     */
+    s.command("grwo\x7f\x7fow 1");
+    s.expect("grew by 1 megabytes");
+
+    /*
+     * Discard a whole line with ^U:
+     */
+    s.command("nonsense\x15set");
+    s.expect("worker-priority normal");
+}
+
+#[test]
+fn simulated_stats() {
+    let mut s = Session::start();
+
+    s.expect(
+        " c  1024.0 min    64.0 max  4096.0 free  1024.0 avrm  2048.0 \
+        mine     0.0 resv  1536.0 alloc  1024.0 avail  2560.0 \
+        sfree  3072.0 sdev  2048.0 scan       0 apgi       0 apgo       0 \
+        swpi       0 hit       -\r\n",
+    );
+}
+
+#[test]
+fn identity() {
+    let mut s = Session::start();
+
+    s.expect(
+        "host simulated zone global, SunOS 5.11 simulated, 4 CPUs at 2400 MHz, \
+        4096.0 MB physmem, 4096 byte pages\r\n",
+    );
+}
+
+#[test]
+fn thresholds() {
+    let mut s = Session::start();
+
+    s.expect(
+        "lotsfree 64.0 MB, desfree 32.0 MB, minfree 16.0 MB, \
+        throttlefree 16.0 MB\r\n",
+    );
+    s.command("stats add below");
+    s.expect(" below       -\r\n");
+}
+
+#[test]
+fn end_of_input() {
+    let mut s = Session::start();
+
+    s.expect("fillmem> ");
+    s.send("\x04");
+    s.expect(" * end!");
+    assert!(s.wait().success());
+}
+
+#[test]
+fn stats_fault() {
+    let mut s = Session::start();
+
+    s.command("set faults chain-update");
+    s.expect("statistics unavailable: ");
+    s.command("set faults none");
+    s.expect("statistics available again");
+}
+
+#[test]
+fn unknown_fault() {
+    let mut s = Session::start();
+
+    s.command("set faults everything");
+    s.expect("unknown fault \"everything\"");
+}
+
+#[test]
+fn terminal_write_fault() {
+    let mut s = Session::start();
+
+    /*
+     * Once terminal writes begin to fail, the session cannot continue.  We
+     * should exit with an error, having restored the terminal.
+     */
+    s.command("set faults term-write");
+    s.expect("Error: terminal write failure: injected fault");
+    assert!(!s.wait().success());
+}
+
+#[test]
+fn status() {
+    let mut s = Session::start();
+
+    s.command("grow 3");
+    s.expect("grew by 3 megabytes");
+    s.command("status");
+    s.expect("1 allocations, 3 megabytes");
+    s.expect("fillmem holds 0.0 MB of availrmem (0.0 MB in locked allocations, 0.0 MB of its own memory)");
+    s.expect("availrmem 2048.0 MB; rest of system holds 2048.0 MB");
+    s.expect("physmem 4096.0 MB, freemem 1024.0 MB");
+    s.expect("up 1 day, 2:03, 42 processes, load average 0.50, 0.25, 0.10");
+}
+
+#[test]
+fn grow_locked() {
+    let mut s = Session::start();
+
+    s.command("grow 1 locked");
+    s.expect("grew by 1 megabytes (locked)");
+    s.command("status");
+    s.expect("fillmem holds 1.0 MB of availrmem (1.0 MB in locked allocations, 0.0 MB of its own memory)");
+    s.command("list");
+    s.expect(" locked\r\n");
+    s.command("free");
+    s.expect("freed 1 megabytes");
+    s.command("status");
+    s.expect("fillmem holds 0.0 MB of availrmem (0.0 MB in locked allocations, 0.0 MB of its own memory)");
+
+    s.command("grow 1 sideways");
+    s.expect("grow option \"sideways\" unknown");
+    s.command("grow 1 pgsz");
+    s.expect("grow option \"pgsz\" requires a page size");
+    s.command("grow 1 pgsz 3k");
+    s.expect("could not grow: page size 3k not supported (supported sizes: ");
+}
+
+#[test]
+fn sizes() {
+    let mut s = Session::start();
+
+    s.command("grow 1536k");
+    s.expect("grew by 1.5 megabytes in ");
+    s.command("grow 1m+512k");
+    s.expect("grew by 1.5 megabytes in ");
+    s.command("free 512k");
+    s.expect("freed 0.5 megabytes in ");
+    s.command("status");
+    s.expect("2 allocations, 2.5 megabytes");
+    s.command("grow 2x");
+    s.expect("invalid size \"2x\": unknown unit \"x\" in \"2x\"");
+}
+
+#[test]
+fn undo() {
+    let mut s = Session::start();
+
+    s.command("undo");
+    s.expect("nothing to undo");
+    s.command("grow 1");
+    s.expect("(allocation 1)");
+    s.command("grow 2");
+    s.expect("(allocation 2)");
+    s.command("reserve 4");
+    s.expect("(allocation 3)");
+    s.command("undo");
+    s.expect("released allocation 2 (2 megabytes)");
+    s.command("status");
+    s.expect("2 allocations, 5 megabytes");
+    s.command("undo");
+    s.expect("nothing to undo");
+
+    s.command("grow 1");
+    s.expect("(allocation 4)");
+    s.command("free");
+    s.command("undo");
+    s.expect("allocation 4 has already been freed");
+}
+
+#[test]
+fn list() {
+    let mut s = Session::start();
+
+    s.command("list");
+    s.expect("no allocations");
+    s.command("grow 2");
+    s.expect("(allocation 1)");
+    s.command("touch");
+    s.expect("touched 2 megabytes");
+    s.command("grow 1");
+    s.expect("(allocation 2)");
+    s.command("list");
+    s.expect("  ID         SIZE CREATED       TOUCHED");
+    s.expect("   1       2.0 MB ");
+    s.expect("   2       1.0 MB ");
+    s.expect(" -");
+
+    /*
+     * Identifiers are not reused once an allocation is freed.
+     */
+    s.command("free 1");
+    s.command("grow 1");
+    s.expect("(allocation 3)");
+}
+
+#[test]
+fn grow_patterns() {
+    let mut s = Session::start();
+
+    s.command("grow 1 pattern zero");
+    s.expect("grew by 1 megabytes (zero) in ");
+    s.command("grow 1 pattern random -j 2");
+    s.expect("grew by 1 megabytes (random seed 1) in ");
+    s.command("grow 1 seed 42 locked");
+    s.expect("grew by 1 megabytes (locked, random seed 42) in ");
+    s.command("grow 1 pattern incompressible &");
+    s.expect("grew by 1 megabytes (incompressible) in ");
+    s.command("grow 1 pattern stripes");
+    s.expect("pattern \"stripes\" must be a, zero, text, random, or");
+}
+
+#[test]
+fn madvise() {
+    let mut s = Session::start();
+
+    s.command("grow 1");
+    s.expect("(allocation 1)");
+    s.command("madvise 1 willneed");
+    s.expect("advised allocation 1: willneed");
+    s.command("madvise 1 dontneed");
+    s.expect("advised allocation 1: dontneed");
+    s.command("madvise 2 free");
+    s.expect("no allocation 2");
+    s.command("madvise 1 forget");
+    s.expect("advice \"forget\" must be dontneed, willneed, or free");
+}
+
+#[test]
+fn hold() {
+    let mut s = Session::start();
+
+    s.command("hold");
+    s.expect("not holding");
+    s.command("hold 2 every 100ms");
+    s.expect("holding 2 megabytes");
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    s.command("hold");
+    s.expect(" MB resident after ");
+    s.command("hold stop");
+    s.expect("released hold of 2 megabytes");
+    s.command("hold 2 every");
+    s.expect("usage: hold SIZE [every PERIOD]");
+}
+
+#[test]
+fn stack() {
+    let mut s = Session::start();
+
+    s.command("stack");
+    s.expect("no stack");
+    s.command("stack 8");
+    s.expect("touched 8 megabytes of stack in ");
+    s.command("stack");
+    s.expect("holding 8 megabytes of stack since ");
+    s.command("stack stop");
+    s.expect("released 8 megabytes of stack");
+    s.command("stack 1 2");
+    s.expect("usage: stack [SIZE | stop]");
+}
+
+#[test]
+fn protect_self() {
+    let mut s = Session::start_args(&["--protect-self"]);
+
+    s.expect("locked fillmem's own memory in ");
+    s.command("status");
+    s.expect("0 allocations");
+}
+
+#[test]
+fn interval() {
+    let mut s = Session::start_args(&["--interval", "2s"]);
+
+    s.command("interval");
+    s.expect("statistics every 2000 msec");
+    s.command("interval 100ms");
+    s.expect("statistics now every 100 msec");
+    s.command("interval 0");
+    s.expect("interval must be non-zero");
+    s.command("interval 5 10");
+    s.expect("usage: interval [PERIOD]");
+}
+
+#[test]
+fn stats_columns() {
+    let mut s = Session::start();
+
+    s.command("stats del scan");
+    s.expect("removed column \"scan\"");
+    s.expect(" sdev  2048.0 apgi       0 ");
+    s.command("stats add scan");
+    s.expect("added column \"scan\"");
+    s.expect(" hit       - scan       0\r\n");
+    s.command("stats add scan");
+    s.expect("column \"scan\" already shown");
+    s.command("stats add bogus");
+    s.expect("no column \"bogus\"");
+    s.command("stats del bogus");
+    s.expect("column \"bogus\" not shown");
+    s.command("stats add fmin");
+    s.expect(" scan       0 fmin  1024.0\r\n");
+    s.command("stats add tcpe");
+    s.expect(" fmin  1024.0 tcpe       0\r\n");
+    s.command("stats add pfh");
+    s.expect(" tcpe       0 pfh       -\r\n");
+    s.command("stats add hrt");
+    s.expect(" pfh       - hrt ");
+    s.command("stats add drop");
+    s.expect(" drop       0\r\n");
+    s.command("stats add pgout");
+    s.expect(" drop       0 pgout       0\r\n");
+}
+
+#[test]
+fn pause_resume() {
+    let mut s = Session::start();
+
+    s.command("pause");
+    s.expect("statistics paused");
+    s.command("pause");
+    s.expect("statistics already paused");
+    s.command("resume");
+    s.expect("statistics resumed");
+    s.expect(" c  1024.0 ");
+    s.command("resume");
+    s.expect("statistics not paused");
+}
+
+#[test]
+fn lock() {
+    let mut s = Session::start();
+
+    s.command("grow 2");
+    s.expect("(allocation 1)");
+    s.command("unlock 1");
+    s.expect("allocation 1 is already unlocked");
+    s.command("lock 2");
+    s.expect("no allocation 2");
+    s.command("lock");
+    s.expect("usage: lock ID");
+    s.command("grow 2 locked");
+    s.expect("(allocation 2)");
+    s.command("unlock 2");
+    s.expect("unlocked allocation 2 (2 megabytes) in ");
+    s.command("status");
+    s.expect("fillmem holds 0.0 MB of availrmem (0.0 MB in locked allocations, 0.0 MB of its own memory)");
+    s.command("lock 1");
+    s.expect("locked allocation 1 (2 megabytes) in ");
+    s.command("status");
+    s.expect("fillmem holds 2.0 MB of availrmem (2.0 MB in locked allocations, 0.0 MB of its own memory)");
+}
+
+#[test]
+fn zone() {
+    let mut s = Session::start();
+
+    s.command("zone");
+    s.expect("zone global (id 0)");
+    s.expect("rss 512.0 MB, no cap");
+    s.expect("swap 256.0 MB, no cap");
+    s.expect("over cap 0 times, 0.0 MB paged out");
+}
+
+#[test]
+fn kmem() {
+    let mut s = Session::start();
+
+    s.command("kmem 1 2");
+    s.expect("usage: kmem [COUNT]");
+    s.command("kmem many");
+    s.expect("invalid digit found in string");
+}
+
+#[test]
+fn disks() {
+    let mut s = Session::start();
+
+    s.command("disks 1 2");
+    s.expect("usage: disks [PERIOD]");
+    s.command("disks 0");
+    s.expect("period must be non-zero");
+}
+
+#[test]
+fn kstat_query() {
+    let mut s = Session::start();
+
+    s.command("kstat");
+    s.expect("usage: kstat MODULE:INSTANCE:NAME[:STATISTIC]");
+    s.command("kstat a:b:c:d:e");
+    s.expect(
+        "kstat: pattern should be of the form module:instance:name:statistic",
+    );
+}
+
+#[test]
+fn swap() {
+    let mut s = Session::start();
+
+    s.command("swap");
+    s.expect(
+        "1024.0 MB allocated + 512.0 MB reserved = 1536.0 MB used, \
+        2560.0 MB available",
+    );
+    s.expect("/dev/zvol/dsk/rpool/swap: 4096.0 MB, 2048.0 MB free");
+}
+
+#[test]
+fn lockall() {
+    let mut s = Session::start();
+
+    s.command("lockall");
+    s.expect("memory not locked");
+    s.command("lockall maybe");
+    s.expect("usage: lockall [on | off]");
+    s.command("lockall off");
+    s.expect("unlocked all memory in ");
+
+    /*
+     * Every allocation is locked while lockall is on, including those made
+     * before it was turned on.
+     */
+    s.command("grow 1");
+    s.expect("(allocation 1)");
+    s.command("lockall on");
+    s.expect("locked all memory in ");
+    s.command("status");
+    s.expect("fillmem holds 1.0 MB of availrmem (1.0 MB in locked allocations");
+    s.command("lockall off");
+    s.expect("unlocked all memory in ");
+    s.command("status");
+    s.expect("fillmem holds 0.0 MB of availrmem (0.0 MB in locked allocations");
+
+    /*
+     * Turning lockall off also unlocks an allocation locked on its own.
+     */
+    s.command("grow 1 locked");
+    s.expect("(allocation 2)");
+    s.command("lockall on");
+    s.expect("locked all memory in ");
+    s.command("lockall off");
+    s.expect(", including 1 allocations locked on their own");
+    s.command("status");
+    s.expect("fillmem holds 0.0 MB of availrmem (0.0 MB in locked allocations");
+    s.command("lock 2");
+    s.expect("locked allocation 2 (1 megabytes) in ");
+}
+
+#[test]
+fn fork() {
+    let mut s = Session::start();
+
+    s.command("fork");
+    s.expect("no children");
+    s.command("grow 2");
+    s.expect("grew by 2 megabytes");
+    s.command("pshm 1");
+    s.expect("(allocation 2)");
+    s.command("fork 2 write");
+    s.expect("forked 2 children, which copied 2 megabytes each");
+    s.command("fork 1");
+    s.expect("forked 1 children");
+    s.command("fork");
+    s.expect("2 children (written): pids ");
+    s.expect("1 children: pids ");
+    s.command("fork stop");
+    s.expect("released 3 children");
+}
+
+#[test]
+fn spawn() {
+    let mut s = Session::start();
+
+    s.command("spawn");
+    s.expect("no workers");
+    s.command("spawn 0 1");
+    s.expect("spawn at least one worker");
+    s.command("spawn 2 1");
+    s.expect("spawned 2 workers of 1 megabytes each");
+    s.command("spawn");
+    s.expect("worker pid ");
+    s.expect(": 1 megabytes");
+    s.command("spawn stop");
+    s.expect("stopped 2 workers");
+    s.command("spawn 2");
+    s.expect("usage: spawn [N SIZE | stop]");
+}
+
+#[test]
+fn reserve() {
+    let mut s = Session::start();
+
+    s.command("reserve 4");
+    s.expect("mapped 4 megabytes reserving swap (allocation 1)");
+    s.command("reserve 4 noreserve");
+    s.expect("mapped 4 megabytes without reserving swap (allocation 2)");
+    s.command("list");
+    s.expect("   2       4.0 MB ");
+    s.expect(" noreserve\r\n");
+    s.command("reserve 4 maybe");
+    s.expect("reserve option \"maybe\" unknown");
+}
+
+#[test]
+fn copy() {
+    let mut s = Session::start();
+
+    s.command("grow 2 pattern random");
+    s.expect("(allocation 1)");
+    s.command("copy 1");
+    s.expect("copied allocation 1 (2 megabytes) to allocation 2 in ");
+    s.expect(" GB/s");
+    s.command("verify 2");
+    s.expect("allocation 2: 2 megabytes ok");
+    s.command("copy 3");
+    s.expect("no allocation 3");
+    s.command("protect 1 none");
+    s.expect("protected allocation 1: none");
+    s.command("copy 1");
+    s.expect("allocation 1 is not readable");
+}
+
+#[test]
+fn protect() {
+    let mut s = Session::start();
+
+    s.command("grow 1");
+    s.expect("(allocation 1)");
+    s.command("grow 2");
+    s.expect("(allocation 2)");
+    s.command("protect 1 ro");
+    s.expect("protected allocation 1: ro");
+    s.command("protect 2 none");
+    s.expect("protected allocation 2: none");
+    s.command("list");
+    s.expect(" prot=ro");
+    s.expect(" prot=none");
+    s.command("touch");
+    s.expect("touched 0 megabytes");
+    s.command("touch 1");
+    s.expect("allocation 1 is not writable");
+    s.command("verify 2");
+    s.expect("allocation 2 is not readable");
+    s.command("churn 2");
+    s.expect("allocation 2 is not writable");
+    s.command("verify");
+    s.expect("allocation 1: 1 megabytes ok");
+    s.expect("verified in ");
+    s.command("protect 2 rw");
+    s.command("touch");
+    s.expect("touched 2 megabytes");
+    s.command("protect 1 exec");
+    s.expect("protection \"exec\" must be none, ro, or rw");
+    s.command("protect 1");
+    s.expect("usage: protect ID none|ro|rw");
+}
+
+#[test]
+fn verify() {
+    let mut s = Session::start();
+
+    s.command("grow 2 pattern random -j 2");
+    s.expect("(allocation 1)");
+    s.command("grow 1 pattern text");
+    s.expect("(allocation 2)");
+    s.command("reserve 1");
+    s.expect("(allocation 3)");
+    s.command("touch stride 1");
+    s.expect("touched 3 megabytes");
+    s.command("verify");
+    s.expect("allocation 1: 2 megabytes ok");
+    s.expect("allocation 2: 1 megabytes ok");
+    s.expect("allocation 3: no known pattern to verify");
+    s.expect("0 allocations with errors");
+    s.command("verify 4");
+    s.expect("no allocation 4");
+}
+
+#[test]
+fn lgrp() {
+    let mut s = Session::start();
+
+    s.command("lgrp");
+    s.expect("lgrp 1: 2048.0 MB installed, 512.0 MB free");
+    s.expect("lgrp 2: 2048.0 MB installed, 768.0 MB free");
+    s.command("grow 1 lgrp");
+    s.expect("grow option \"lgrp\" requires an lgroup ID");
+    s.command("grow 1 lgrp one");
+    s.expect("grow option \"lgrp\" expects an lgroup ID, not \"one\"");
+}
+
+#[test]
+fn bind() {
+    let mut s = Session::start();
+
+    s.command("grow 1 bind 0");
+    s.expect("grew by 1 megabytes (bound to CPU 0) in ");
+    s.command("touch bind 0");
+    s.expect("touched 1 megabytes in ");
+    s.expect(", CPU 0)");
+    s.command("touch bind");
+    s.expect("touch bound to which CPU?");
+    s.command("grow 1 bind");
+    s.expect("grow option \"bind\" requires a CPU ID");
+    s.command("grow 1 bind -");
+    s.expect("grow option \"bind\" expects a CPU ID, not \"-\"");
+    s.command("grow 1 -jmany");
+    s.expect("grow option \"-j\" expects a thread count, not \"many\"");
+    s.command("grow 1 seed x");
+    s.expect("grow option \"seed\" expects a number, not \"x\"");
+}
+
+#[test]
+fn timeout() {
+    let mut s = Session::start();
+
+    s.command("timeout 500ms grow 8 rate 4");
+    s.expect("interrupted! grew by ");
+    s.expect(" of 8 megabytes in ");
+    s.expect("(allocation 1)");
+    s.expect("timed out after 0.5s");
+    s.command("list");
+    s.expect("   1  ");
+    s.command("timeout 1");
+    s.expect("usage: timeout PERIOD COMMAND ...");
+    s.command("timeout 1 timeout 1 list");
+    s.expect("timeout may not be nested");
+}
+
+#[test]
+fn ramp() {
+    let mut s = Session::start();
+
+    s.command("ramp");
+    s.expect("not ramping");
+    s.command("ramp 1 every 100ms 3");
+    s.expect("started job 1: ramp by 1 megabytes every 0.1s");
+    s.expect("job 1: grew by 1 megabytes in ");
+    s.expect("(allocation 1)");
+    s.expect("job 1: grew by 1 megabytes in ");
+    s.expect("(allocation 3)");
+    s.command("ramp");
+    s.expect("(job 1): 3 steps of 3");
+    s.command("ramp stop");
+    s.expect("stopped ramp (job 1) after 3 steps");
+    s.command("status");
+    s.expect("3 allocations, 3 megabytes");
+    s.command("ramp 1");
+    s.expect("usage: ramp [SIZE every PERIOD [COUNT] | stop]");
+}
+
+#[test]
+fn oscillate() {
+    let mut s = Session::start();
+
+    s.command("oscillate");
+    s.expect("not oscillating");
+    s.command("oscillate 1 every 200ms &");
+    s.expect("started job 1: oscillate by 1 megabytes every 0.2s");
+    s.expect("job 1: cycle 1: grew by 1 megabytes in ");
+    s.expect("job 1: cycle 2: grew by 1 megabytes in ");
+    s.command("oscillate stop");
+    s.expect("stopped oscillation (job 1) after ");
+    s.command("status");
+    s.expect("0 allocations, 0 megabytes");
+
+    s.command("oscillate 1 every 200ms");
+    s.expect("cycle 1: grew by 1 megabytes in ");
+    s.send("\x03");
+    s.expect("interrupted! oscillated for ");
+    s.command("oscillate 1");
+    s.expect("usage: oscillate [SIZE every PERIOD [&] | stop]");
+}
+
+#[test]
+fn leak() {
+    let mut s = Session::start();
+
+    s.command("leak 3");
+    s.expect("leaked 3 megabytes in ");
+    s.expect(
+        " msec before reaching the limit of 3 megabytes (allocations 1-1)",
+    );
+    s.command("set vmem-cap 256m");
+    s.expect("vmem-cap now 256m");
+    s.command("leak");
+    s.expect(" msec before could not grow: ");
+    s.command("leak 1 2");
+    s.expect("usage: leak [MAX]");
+}
+
+#[test]
+fn fragment() {
+    let mut s = Session::start();
+
+    s.command("fragment 4 4k 64k");
+    s.expect("made ");
+    s.expect(" allocations of ");
+    s.expect(" and freed ");
+    s.expect(" msec, leaving ");
+    s.expect(" (allocations 1-");
+    s.command("verify");
+    s.expect("verified in ");
+    s.expect("; 0 allocations with errors");
+    s.command("fragment 4 64k 4k");
+    s.expect("fragment sizes must be non-zero, and MINSIZE at most MAXSIZE");
+    s.command("fragment 4");
+    s.expect("usage: fragment TOTAL MINSIZE MAXSIZE");
+}
+
+#[test]
+fn arcfill() {
+    let mut s = Session::start();
+
+    s.command(&format!("arcfill {} 1", env!("CARGO_MANIFEST_DIR")));
+    s.expect("read 1 megabytes from ");
+    s.expect(" MB/s");
+    s.expect("; ARC size ");
+    s.command("arcfill /nonexistent");
+    s.expect("arcfill: could not read \"/nonexistent\": ");
+    s.command("arcfill");
+    s.expect("usage: arcfill PATH [SIZE]");
+}
+
+#[test]
+fn dread() {
+    let mut s = Session::start();
+
+    s.command(&format!("dread {} 1", env!("CARGO_MANIFEST_DIR")));
+    s.expect("read 1 megabytes from ");
+    s.expect("freemem ");
+    s.command("dread /nonexistent 1");
+    s.expect("dread: could not read \"/nonexistent\": ");
+    s.command("dread /nonexistent");
+    s.expect("usage: dread PATH SIZE");
+}
+
+#[test]
+fn churn() {
+    let mut s = Session::start();
+
+    s.command("churn");
+    s.expect("nothing to churn");
+    s.command("grow 1");
+    s.expect("grew by 1 megabytes");
+    s.command("churn 1 rate 10");
+    s.expect("pass 1: touched 1 megabytes in ");
+    s.expect("pass 2: touched 1 megabytes in ");
+    s.send("\x03");
+    s.expect("interrupted! churned for ");
+
+    /*
+     * The rate may also be given positionally, after the ID.
+     */
+    s.command("churn 1 10");
+    s.expect("pass 1: touched 1 megabytes in ");
+    s.send("\x03");
+    s.expect("interrupted! churned for ");
+    s.command("churn 1 fast");
+    s.expect("invalid rate \"fast\": expected a number");
+    s.command("churn 1 10 20");
+    s.expect("churn option \"20\" unknown");
+}
+
+#[test]
+fn grow_rate() {
+    let mut s = Session::start();
+
+    /*
+     * Growing by 2 megabytes at 4 MB/s should take at least half a second.
+     */
+    let start = std::time::Instant::now();
+    s.command("grow 2 rate 4");
+    s.expect(" MB/s (target 4 MB/s) (allocation 1)");
+    assert!(start.elapsed().as_millis() >= 500);
+
+    s.command("grow 2 rate fast");
+    s.expect("invalid rate \"fast\": expected a number");
+    s.command("grow 2 rate 1e-300");
+    s.expect("invalid rate \"1e-300\": must be at least 0.01 MB/s");
+    s.command("churn rate 1e-300");
+    s.expect("invalid rate \"1e-300\": must be at least 0.01 MB/s");
+
+    /*
+     * Each of the 8 threads gets a share of the rate so small that it would
+     * wait more than a minute after its first chunk, but an interrupt must
+     * still take effect at once.
+     */
+    let start = std::time::Instant::now();
+    s.command("timeout 500ms grow 64 -j 8 rate 0.1");
+    s.expect("timed out after 0.5s");
+    assert!(start.elapsed().as_secs() < 5);
+}
+
+#[test]
+fn growuntil() {
+    let mut s = Session::start();
+
+    /*
+     * Simulated freemem is fixed at 1024 MB, so there is nothing to do.
+     */
+    s.command("growuntil free 2048");
+    s.expect(
+        "freemem 1024.0 MB is below 2048.0 MB after growing by 0 megabytes",
+    );
+    s.command("growuntil");
+    s.expect("usage: growuntil free SIZE");
+}
+
+#[test]
+fn grow_background() {
+    let mut s = Session::start();
+
+    s.command("grow 2 &");
+    s.expect("started job 1: grow by 2 megabytes");
+    s.expect("job 1: grew by 2 megabytes");
+    s.command("status");
+    s.expect("1 allocations, 2 megabytes");
+
+    s.command("grow 4 -j 2 &");
+    s.expect("started job 2: grow by 4 megabytes");
+    s.expect(" with 2 threads, ");
+    s.command("grow 3 -j3");
+    s.expect(" with 3 threads, ");
+    s.command("status");
+    s.expect("3 allocations, 9 megabytes");
+    s.command("grow 1 -j 0");
+    s.expect("grow needs at least one thread");
+
+    s.command("grow 1 sideways &");
+    s.expect("grow option \"sideways\" unknown");
+}
+
+#[test]
+fn pshm() {
+    let mut s = Session::start();
+
+    s.command("pshm 2");
+    s.expect(" of 2 megabytes in ");
+    s.expect("(allocation 1)");
+    s.command("pshm 1 /fillmem.test");
+    s.expect("created segment /fillmem.test of 1 megabytes in ");
+    s.command("pshm 1 /fillmem.test");
+    s.expect("could not create segment");
+    s.command("list");
+    s.expect("shm=/fillmem.test");
+    s.command("free");
+    s.expect("freed 3 megabytes");
+    s.command("pshm 1 /fillmem.test");
+    s.expect("(allocation 3)");
+    s.command("pshm");
+    s.expect("usage: pshm SIZE [NAME]");
+}
+
+#[test]
+fn flush() {
+    let mut s = Session::start();
+
+    s.command("growfile 2");
+    s.expect("(allocation 1)");
+    s.command("grow 1");
+    s.expect("(allocation 2)");
+    s.command("flush 1");
+    s.expect("flushed allocation 1 (2 megabytes) in ");
+    s.command("flush 2");
+    s.expect("allocation 2 is not backed by a file");
+    s.command("flush 3");
+    s.expect("no allocation 3");
+}
+
+#[test]
+fn growfile() {
+    let mut s = Session::start();
+
+    let path = session_file().with_extension("data");
+    s.command(&format!("growfile 2 {}", path.display()));
+    s.expect(&format!("grew file {} by 2 megabytes", path.display()));
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 2 * 1024 * 1024);
+
+    s.command("grow 1");
+    s.expect("grew by 1 megabytes");
+    s.command("status");
+    s.expect("2 allocations, 3 megabytes (2 megabytes file-backed)");
+    s.command("list");
+    s.expect(&format!(" file={}\r\n", path.display()));
+
+    /*
+     * Freeing the mapping should shorten, and finally remove, the file.
+     */
+    s.command("free 2");
+    s.expect("freed 2 megabytes");
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 1024 * 1024);
+    s.command("free");
+    s.expect("freed 1 megabytes");
+    assert!(!path.exists());
+}
+
+#[test]
+fn second_session_refused() {
+    let path = session_file();
+    let mut s0 = Session::start_with(&path);
+    s0.expect("fillmem> ");
+
+    let mut s1 = Session::start_with(&path);
+    s1.expect(&format!("fillmem is already running as pid {}", s0.child.id()));
+    assert!(!s1.wait().success());
+}
+
+#[test]
+fn stale_session_cleaned_up() {
+    /*
+     * Use the process ID of a child that has already exited to simulate a
+     * crashed session.
+     */
+    let mut dead = Command::new("true").spawn().unwrap();
+    dead.wait().unwrap();
+    let path = session_file();
+    std::fs::write(
+        &path,
+        format!("pid={}\nstart=then\ncontrol=-\n", dead.id()),
+    )
+    .unwrap();
+
+    let mut s = Session::start_with(&path);
+    s.expect(&format!("cleaned up after crashed session in pid {}", dead.id()));
+    s.expect("fillmem> ");
+    assert!(std::fs::read_to_string(&path)
+        .unwrap()
+        .contains(&format!("pid={}\n", s.child.id())));
+}
+
+#[test]
+fn empty_session_cleaned_up() {
+    /*
+     * A crash between creating the session file and writing to it leaves the
+     * file empty.
+     */
+    let path = session_file();
+    std::fs::write(&path, "").unwrap();
+
+    let mut s = Session::start_with(&path);
+    s.expect(&format!(
+        "cleaned up stale session: session file {path:?} does not contain a \
+        process ID"
+    ));
+    s.expect("fillmem> ");
+    assert!(std::fs::read_to_string(&path)
+        .unwrap()
+        .contains(&format!("pid={}\n", s.child.id())));
+}
+
+#[test]
+fn quiet_mode() {
+    let mut s = Session::start();
+
+    s.command("/etc/passwd");
+    s.expect("\"/etc/passwd\" not understood");
+    s.command("set quiet on");
+    s.expect("quiet now on");
+    s.command("/etc/passwd");
+    s.expect("\"<path>\" not understood");
+    s.command("set quiet off");
+    s.command("/etc/passwd");
+    s.expect("\"/etc/passwd\" not understood");
+}